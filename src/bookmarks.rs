@@ -0,0 +1,395 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::persist::{self, PersistError};
+use crate::time::{now, Timestamp};
+
+mod netscape;
+
+/// On-disk format version of the bookmarks file.
+pub const BOOKMARKS_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BookmarkId(pub u64);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: BookmarkId,
+    pub title: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub added: Timestamp,
+    pub modified: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: BookmarkId,
+    pub title: String,
+    pub children: Vec<Node>,
+    pub added: Timestamp,
+    pub modified: Timestamp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Node {
+    Bookmark(Bookmark),
+    Folder(Folder),
+}
+
+impl Node {
+    pub fn id(&self) -> BookmarkId {
+        match self {
+            Node::Bookmark(b) => b.id,
+            Node::Folder(f) => f.id,
+        }
+    }
+}
+
+impl Folder {
+    fn new(id: BookmarkId, title: String) -> Self {
+        let now = now();
+        Self {
+            id,
+            title,
+            children: Vec::new(),
+            added: now,
+            modified: now,
+        }
+    }
+
+    fn find(&self, id: BookmarkId) -> Option<&Node> {
+        self.children.iter().find_map(|child| match child {
+            child if child.id() == id => Some(child),
+            Node::Folder(folder) => folder.find(id),
+            Node::Bookmark(_) => None,
+        })
+    }
+
+    fn folder_mut(&mut self, id: BookmarkId) -> Option<&mut Folder> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter_mut().find_map(|child| match child {
+            Node::Folder(folder) => folder.folder_mut(id),
+            Node::Bookmark(_) => None,
+        })
+    }
+
+    fn remove(&mut self, id: BookmarkId) -> Option<Node> {
+        if let Some(pos) = self.children.iter().position(|child| child.id() == id) {
+            self.modified = now();
+            return Some(self.children.remove(pos));
+        }
+        self.children.iter_mut().find_map(|child| match child {
+            Node::Folder(folder) => folder.remove(id),
+            Node::Bookmark(_) => None,
+        })
+    }
+
+    fn visit<'a>(&'a self, out: &mut Vec<&'a Bookmark>) {
+        for child in &self.children {
+            match child {
+                Node::Bookmark(bookmark) => out.push(bookmark),
+                Node::Folder(folder) => folder.visit(out),
+            }
+        }
+    }
+}
+
+/// A tree of bookmark folders persisted in the profile directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmarks {
+    root: Folder,
+    next_id: u64,
+}
+
+impl Default for Bookmarks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bookmarks {
+    pub fn new() -> Self {
+        Self {
+            root: Folder::new(BookmarkId(0), "Bookmarks".into()),
+            next_id: 1,
+        }
+    }
+
+    pub fn root(&self) -> &Folder {
+        &self.root
+    }
+
+    pub fn root_id(&self) -> BookmarkId {
+        self.root.id
+    }
+
+    fn next_id(&mut self) -> BookmarkId {
+        let id = BookmarkId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    pub fn find(&self, id: BookmarkId) -> Option<&Node> {
+        self.root.find(id)
+    }
+
+    /// All bookmarks in tree order.
+    pub fn all(&self) -> Vec<&Bookmark> {
+        let mut out = Vec::new();
+        self.root.visit(&mut out);
+        out
+    }
+
+    pub fn find_by_url(&self, url: &str) -> Vec<&Bookmark> {
+        self.all().into_iter().filter(|b| b.url == url).collect()
+    }
+
+    pub fn is_bookmarked(&self, url: &str) -> bool {
+        !self.find_by_url(url).is_empty()
+    }
+
+    pub fn with_tag(&self, tag: &str) -> Vec<&Bookmark> {
+        self.all()
+            .into_iter()
+            .filter(|b| b.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .collect()
+    }
+
+    /// Adds a bookmark at the end of `parent`. Returns `None` when `parent`
+    /// is not a folder.
+    pub fn add_bookmark(
+        &mut self,
+        parent: BookmarkId,
+        title: impl Into<String>,
+        url: impl Into<String>,
+        tags: Vec<String>,
+    ) -> Option<BookmarkId> {
+        let id = self.next_id();
+        let now = now();
+        let folder = self.root.folder_mut(parent)?;
+        folder.children.push(Node::Bookmark(Bookmark {
+            id,
+            title: title.into(),
+            url: url.into(),
+            tags,
+            added: now,
+            modified: now,
+        }));
+        folder.modified = now;
+        Some(id)
+    }
+
+    pub fn add_folder(
+        &mut self,
+        parent: BookmarkId,
+        title: impl Into<String>,
+    ) -> Option<BookmarkId> {
+        let id = self.next_id();
+        let folder = self.root.folder_mut(parent)?;
+        folder
+            .children
+            .push(Node::Folder(Folder::new(id, title.into())));
+        folder.modified = now();
+        Some(id)
+    }
+
+    /// Removes a bookmark or a folder with everything in it. The root folder
+    /// cannot be removed.
+    pub fn remove(&mut self, id: BookmarkId) -> Option<Node> {
+        self.root.remove(id)
+    }
+
+    /// Removes every bookmark pointing at `url`, returning how many there were.
+    pub fn remove_url(&mut self, url: &str) -> usize {
+        let ids: Vec<BookmarkId> = self.find_by_url(url).iter().map(|b| b.id).collect();
+        ids.iter().filter(|id| self.remove(**id).is_some()).count()
+    }
+
+    /// Moves a node to the end of folder `parent`. A folder cannot be moved
+    /// into itself or one of its descendants.
+    pub fn move_node(&mut self, id: BookmarkId, parent: BookmarkId) -> bool {
+        let into_itself = match self.find(id) {
+            Some(Node::Folder(folder)) => folder.id == parent || folder.find(parent).is_some(),
+            Some(Node::Bookmark(_)) => false,
+            None => return false,
+        };
+        if into_itself || self.root.folder_mut(parent).is_none() {
+            return false;
+        }
+        let Some(node) = self.remove(id) else {
+            return false;
+        };
+        let folder = self.root.folder_mut(parent).expect("checked above");
+        folder.children.push(node);
+        folder.modified = now();
+        true
+    }
+
+    pub fn rename(&mut self, id: BookmarkId, title: impl Into<String>) -> bool {
+        let title = title.into();
+        if let Some(folder) = self.root.folder_mut(id) {
+            folder.title = title;
+            folder.modified = now();
+            return true;
+        }
+        let Some(Node::Bookmark(_)) = self.find(id) else {
+            return false;
+        };
+        self.update_bookmark(id, |bookmark| bookmark.title = title)
+    }
+
+    pub fn set_tags(&mut self, id: BookmarkId, tags: Vec<String>) -> bool {
+        self.update_bookmark(id, |bookmark| bookmark.tags = tags)
+    }
+
+    fn update_bookmark(&mut self, id: BookmarkId, update: impl FnOnce(&mut Bookmark)) -> bool {
+        fn walk(folder: &mut Folder, id: BookmarkId) -> Option<&mut Bookmark> {
+            folder.children.iter_mut().find_map(|child| match child {
+                Node::Bookmark(b) if b.id == id => Some(b),
+                Node::Folder(f) => walk(f, id),
+                Node::Bookmark(_) => None,
+            })
+        }
+        match walk(&mut self.root, id) {
+            Some(bookmark) => {
+                update(bookmark);
+                bookmark.modified = now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Imports a Netscape bookmark file (as exported by Firefox, Chrome and
+    /// others) into folder `parent`, returning how many bookmarks were added.
+    pub fn import_netscape(&mut self, html: &str, parent: BookmarkId) -> Option<usize> {
+        self.root.folder_mut(parent)?;
+        let mut imported = netscape::parse(html);
+        let count = imported.iter().map(count_bookmarks).sum();
+        for node in &mut imported {
+            self.assign_ids(node);
+        }
+        let folder = self.root.folder_mut(parent)?;
+        folder.children.extend(imported);
+        folder.modified = now();
+        Some(count)
+    }
+
+    /// Exports the whole tree as a Netscape bookmark file.
+    pub fn export_netscape(&self) -> String {
+        netscape::write(&self.root)
+    }
+
+    fn assign_ids(&mut self, node: &mut Node) {
+        match node {
+            Node::Bookmark(bookmark) => bookmark.id = self.next_id(),
+            Node::Folder(folder) => {
+                folder.id = self.next_id();
+                for child in &mut folder.children {
+                    self.assign_ids(child);
+                }
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PersistError> {
+        persist::save(path, BOOKMARKS_FORMAT_VERSION, self)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PersistError> {
+        persist::load(path, BOOKMARKS_FORMAT_VERSION)
+    }
+}
+
+fn count_bookmarks(node: &Node) -> usize {
+    match node {
+        Node::Bookmark(_) => 1,
+        Node::Folder(folder) => folder.children.iter().map(count_bookmarks).sum(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(folder: &Folder) -> Vec<&str> {
+        folder
+            .children
+            .iter()
+            .map(|node| match node {
+                Node::Bookmark(b) => b.title.as_str(),
+                Node::Folder(f) => f.title.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folders_and_bookmarks() {
+        let mut store = Bookmarks::new();
+        let root = store.root_id();
+        let rust = store.add_folder(root, "Rust").unwrap();
+        let book = store
+            .add_bookmark(
+                rust,
+                "The Book",
+                "https://doc.rust-lang.org/book/",
+                vec!["docs".into()],
+            )
+            .unwrap();
+        store.add_bookmark(root, "Example", "https://example.com/", vec![]);
+
+        assert_eq!(titles(store.root()), ["Rust", "Example"]);
+        assert!(store.is_bookmarked("https://doc.rust-lang.org/book/"));
+        assert_eq!(store.with_tag("DOCS").len(), 1);
+        assert_eq!(store.add_bookmark(book, "x", "y", vec![]), None);
+
+        assert!(store.rename(book, "Rust Book"));
+        assert!(store.set_tags(book, vec!["rust".into()]));
+        match store.find(book) {
+            Some(Node::Bookmark(b)) => {
+                assert_eq!(b.title, "Rust Book");
+                assert_eq!(b.tags, ["rust"]);
+            }
+            other => panic!("unexpected node {:?}", other),
+        }
+
+        assert!(store.move_node(book, root));
+        assert_eq!(titles(store.root()), ["Rust", "Example", "Rust Book"]);
+        assert!(!store.move_node(rust, rust));
+
+        assert_eq!(store.remove_url("https://example.com/"), 1);
+        assert!(store.remove(rust).is_some());
+        assert_eq!(titles(store.root()), ["Rust Book"]);
+        assert!(store.remove(root).is_none());
+    }
+
+    #[test]
+    fn folder_cannot_move_into_descendant() {
+        let mut store = Bookmarks::new();
+        let outer = store.add_folder(store.root_id(), "outer").unwrap();
+        let inner = store.add_folder(outer, "inner").unwrap();
+        assert!(!store.move_node(outer, inner));
+        assert!(store.move_node(inner, store.root_id()));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-bookmarks-{}", std::process::id()));
+        let path = dir.join("bookmarks.json");
+        let mut store = Bookmarks::new();
+        let folder = store.add_folder(store.root_id(), "f").unwrap();
+        store.add_bookmark(folder, "a", "https://a.example/", vec!["t".into()]);
+        store.save(&path).unwrap();
+
+        let mut loaded = Bookmarks::load(&path).unwrap();
+        assert_eq!(loaded, store);
+        let next = loaded.add_folder(loaded.root_id(), "g").unwrap();
+        assert!(next.0 > folder.0 + 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
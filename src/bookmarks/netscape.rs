@@ -0,0 +1,409 @@
+//! Reader and writer for the Netscape Bookmark File format, the HTML dialect
+//! every major browser uses for bookmark import and export.
+
+use super::{Bookmark, BookmarkId, Folder, Node};
+use crate::time::{now, Timestamp};
+
+struct Tag<'a> {
+    name: String,
+    closing: bool,
+    attrs: Vec<(String, String)>,
+    /// Text between this tag and the next one.
+    text: &'a str,
+}
+
+impl Tag<'_> {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn timestamp(&self, name: &str) -> Option<Timestamp> {
+        self.attr(name)?.trim().parse().ok()
+    }
+}
+
+fn tokenize(html: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let after = &rest[start + 1..];
+        if let Some(comment) = after.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = find_tag_end(after) else {
+            break;
+        };
+        let inner = &after[..end];
+        rest = &after[end + 1..];
+        let text = &rest[..rest.find('<').unwrap_or(rest.len())];
+
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(inner) => (true, inner),
+            None => (false, inner),
+        };
+        let name_end = inner
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(inner.len());
+        tags.push(Tag {
+            name: inner[..name_end].to_ascii_uppercase(),
+            closing,
+            attrs: parse_attrs(&inner[name_end..]),
+            text,
+        });
+    }
+    tags
+}
+
+/// Finds the `>` closing a tag, skipping any inside quoted attribute values.
+fn find_tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_attrs(mut s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    loop {
+        s = s.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if s.is_empty() {
+            break;
+        }
+        let name_end = s
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(s.len());
+        let name = s[..name_end].to_string();
+        s = s[name_end..].trim_start();
+        let value = if let Some(after_eq) = s.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            match after_eq.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let body = &after_eq[1..];
+                    let end = body.find(q).unwrap_or(body.len());
+                    s = body.get(end + 1..).unwrap_or("");
+                    &body[..end]
+                }
+                _ => {
+                    let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                    s = &after_eq[end..];
+                    &after_eq[..end]
+                }
+            }
+        } else {
+            ""
+        };
+        attrs.push((name, decode_entities(value)));
+    }
+    attrs
+}
+
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity.strip_prefix('#').and_then(|num| {
+                    let code = match num.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                        None => num.parse().ok(),
+                    };
+                    code.and_then(char::from_u32)
+                }),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Parses a bookmark file into top-level nodes. Ids are left as zero for the
+/// store to assign. Parsing is lenient: unknown tags are skipped and
+/// unbalanced lists are closed at the end of input.
+pub(super) fn parse(html: &str) -> Vec<Node> {
+    let now = now();
+    let mut stack = vec![Folder::new(BookmarkId(0), String::new())];
+    // A folder heading waiting for its <DL>; exporters omit the list for
+    // empty folders.
+    let mut pending: Option<Folder> = None;
+    let mut seen_list = false;
+
+    for tag in tokenize(html) {
+        match (tag.name.as_str(), tag.closing) {
+            ("H3", false) => {
+                if let Some(folder) = pending.take() {
+                    push_node(&mut stack, Node::Folder(folder));
+                }
+                let added = tag.timestamp("ADD_DATE").unwrap_or(now);
+                pending = Some(Folder {
+                    id: BookmarkId(0),
+                    title: decode_entities(tag.text.trim()),
+                    children: Vec::new(),
+                    added,
+                    modified: tag.timestamp("LAST_MODIFIED").unwrap_or(added),
+                });
+            }
+            ("DL", false) => match pending.take() {
+                Some(folder) => stack.push(folder),
+                // The outermost list holds the top-level entries.
+                None if !seen_list => {}
+                None => stack.push(Folder::new(BookmarkId(0), String::new())),
+            },
+            ("DL", true) => {
+                if let Some(folder) = pending.take() {
+                    push_node(&mut stack, Node::Folder(folder));
+                }
+                if stack.len() > 1 {
+                    let folder = stack.pop().expect("stack has a parent");
+                    push_node(&mut stack, Node::Folder(folder));
+                }
+            }
+            ("A", false) => {
+                if let Some(folder) = pending.take() {
+                    push_node(&mut stack, Node::Folder(folder));
+                }
+                let Some(href) = tag.attr("HREF") else {
+                    continue;
+                };
+                let added = tag.timestamp("ADD_DATE").unwrap_or(now);
+                let tags = tag
+                    .attr("TAGS")
+                    .map(|tags| {
+                        tags.split(',')
+                            .map(str::trim)
+                            .filter(|t| !t.is_empty())
+                            .map(String::from)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                push_node(
+                    &mut stack,
+                    Node::Bookmark(Bookmark {
+                        id: BookmarkId(0),
+                        title: decode_entities(tag.text.trim()),
+                        url: href.to_string(),
+                        tags,
+                        added,
+                        modified: tag.timestamp("LAST_MODIFIED").unwrap_or(added),
+                    }),
+                );
+            }
+            _ => {}
+        }
+        if tag.name == "DL" && !tag.closing {
+            seen_list = true;
+        }
+    }
+
+    if let Some(folder) = pending.take() {
+        push_node(&mut stack, Node::Folder(folder));
+    }
+    while stack.len() > 1 {
+        let folder = stack.pop().expect("stack has a parent");
+        push_node(&mut stack, Node::Folder(folder));
+    }
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+fn push_node(stack: &mut [Folder], node: Node) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(node);
+    }
+}
+
+/// Writes `root`'s contents as a bookmark file.
+pub(super) fn write(root: &Folder) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n",
+    );
+    write_list(&mut out, &root.children, 0);
+    out
+}
+
+fn write_list(out: &mut String, nodes: &[Node], depth: usize) {
+    let indent = "    ".repeat(depth);
+    out.push_str(&format!("{}<DL><p>\n", indent));
+    for node in nodes {
+        match node {
+            Node::Bookmark(b) => {
+                out.push_str(&format!(
+                    "{}    <DT><A HREF=\"{}\" ADD_DATE=\"{}\" LAST_MODIFIED=\"{}\"",
+                    indent,
+                    escape(&b.url),
+                    b.added,
+                    b.modified
+                ));
+                if !b.tags.is_empty() {
+                    out.push_str(&format!(" TAGS=\"{}\"", escape(&b.tags.join(","))));
+                }
+                out.push_str(&format!(">{}</A>\n", escape(&b.title)));
+            }
+            Node::Folder(f) => {
+                out.push_str(&format!(
+                    "{}    <DT><H3 ADD_DATE=\"{}\" LAST_MODIFIED=\"{}\">{}</H3>\n",
+                    indent,
+                    f.added,
+                    f.modified,
+                    escape(&f.title)
+                ));
+                write_list(out, &f.children, depth + 1);
+            }
+        }
+    }
+    out.push_str(&format!("{}</DL><p>\n", indent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Bookmarks;
+    use super::*;
+
+    const FIREFOX_EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<meta http-equiv="Content-Security-Policy" content="default-src 'self'; script-src 'none'; img-src data: *; object-src 'none'"></meta>
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+    <DT><A HREF="https://www.mozilla.org/en-US/firefox/" ADD_DATE="1700000000" LAST_MODIFIED="1700000100" ICON_URI="fake-favicon-uri:https://www.mozilla.org/">Get Help &amp; Support</A>
+    <DT><H3 ADD_DATE="1700000000" LAST_MODIFIED="1700000200" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks Toolbar</H3>
+    <DL><p>
+        <DT><A HREF="https://doc.rust-lang.org/std/" ADD_DATE="1700000300" TAGS="rust,docs">std &lt;docs&gt;</A>
+        <DT><H3 ADD_DATE="1700000400">Empty</H3>
+        <DL><p>
+        </DL><p>
+    </DL><p>
+    <DT><A HREF='https://example.com/?a=1&amp;b=2'>Single quoted</A>
+</DL>
+"#;
+
+    const CHROME_EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1690000000" LAST_MODIFIED="1690000001" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks bar</H3>
+    <DL><p>
+        <DT><A HREF="https://github.com/" ADD_DATE="1690000002" ICON="data:image/png;base64,AAA=">GitHub</A>
+        <DT><H3 ADD_DATE="1690000003">No list folder</H3>
+        <DT><A HREF="https://crates.io/">crates.io</A>
+    </DL><p>
+</DL><p>
+"#;
+
+    #[test]
+    fn imports_firefox_export() {
+        let nodes = parse(FIREFOX_EXPORT);
+        assert_eq!(nodes.len(), 3);
+        let Node::Bookmark(first) = &nodes[0] else {
+            panic!("expected bookmark");
+        };
+        assert_eq!(first.title, "Get Help & Support");
+        assert_eq!(first.added, 1700000000);
+        assert_eq!(first.modified, 1700000100);
+
+        let Node::Folder(toolbar) = &nodes[1] else {
+            panic!("expected folder");
+        };
+        assert_eq!(toolbar.title, "Bookmarks Toolbar");
+        assert_eq!(toolbar.children.len(), 2);
+        let Node::Bookmark(std_docs) = &toolbar.children[0] else {
+            panic!("expected bookmark");
+        };
+        assert_eq!(std_docs.title, "std <docs>");
+        assert_eq!(std_docs.tags, ["rust", "docs"]);
+        assert!(matches!(&toolbar.children[1], Node::Folder(f) if f.children.is_empty()));
+
+        let Node::Bookmark(last) = &nodes[2] else {
+            panic!("expected bookmark");
+        };
+        assert_eq!(last.url, "https://example.com/?a=1&b=2");
+    }
+
+    #[test]
+    fn imports_chrome_export_with_listless_folder() {
+        let nodes = parse(CHROME_EXPORT);
+        assert_eq!(nodes.len(), 1);
+        let Node::Folder(bar) = &nodes[0] else {
+            panic!("expected folder");
+        };
+        let kinds: Vec<&str> = bar
+            .children
+            .iter()
+            .map(|n| match n {
+                Node::Bookmark(b) => b.title.as_str(),
+                Node::Folder(f) => f.title.as_str(),
+            })
+            .collect();
+        assert_eq!(kinds, ["GitHub", "No list folder", "crates.io"]);
+    }
+
+    #[test]
+    fn export_round_trips_through_import() {
+        let mut store = Bookmarks::new();
+        let folder = store.add_folder(store.root_id(), "A & B").unwrap();
+        store.add_bookmark(
+            folder,
+            "Quote \"x\"",
+            "https://q.example/?a=1&b=2",
+            vec!["t1".into(), "t2".into()],
+        );
+        store.add_bookmark(store.root_id(), "<Top>", "https://top.example/", vec![]);
+
+        let html = store.export_netscape();
+        let mut imported = Bookmarks::new();
+        assert_eq!(imported.import_netscape(&html, imported.root_id()), Some(2));
+        assert_eq!(imported.export_netscape(), html);
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &#60;&#x3E; &bogus; &"),
+            "a & b <> &bogus; &"
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::APP_NAME;
+
+pub const USAGE: &str = "\
+Usage: wrybrowser [OPTIONS] [URL]
+
+Opens URL, or restores the last session's page when no URL is given.
+
+Options:
+  --profile <DIR>         Use DIR as the profile directory
+  --private               Do not read or write any profile data
+  --window-size <WxH>     Initial window size in logical pixels, e.g. 1280x800
+  --user-agent <STRING>   User agent sent by every tab
+  --kiosk                 Fullscreen without a toolbar or window decorations
+  --new-tab <URL>         Open URL in an additional tab (may be repeated)
+  --config <FILE>         Read settings from FILE instead of the profile's config
+  --restore-session       Reopen the tabs and windows of the last session
+  --headless              Run without a window and print the final state as JSON
+  --script <FILE>         Run the navigation commands in FILE headless ('-' for stdin)
+  -V, --version           Print the version and exit
+  -h, --help              Print this help and exit";
+
+/// Everything the command line can ask of a browser launch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LaunchOptions {
+    /// URL for the first tab, navigated to on top of the restored history.
+    pub url: Option<String>,
+    /// URLs opened in tabs of their own, in order.
+    pub new_tabs: Vec<String>,
+    pub profile: Option<PathBuf>,
+    /// Run without a profile: nothing is restored or saved.
+    pub private: bool,
+    /// Initial inner size of the window, in logical pixels.
+    pub window_size: Option<(u32, u32)>,
+    pub user_agent: Option<String>,
+    pub kiosk: bool,
+    pub config: Option<PathBuf>,
+    /// Reopen the profile's last session instead of offering to.
+    pub restore_session: bool,
+    /// Run without a window, even in GUI builds.
+    pub headless: bool,
+    /// Navigation script for headless runs; `-` reads standard input.
+    pub script: Option<PathBuf>,
+}
+
+/// What the command line asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Launch(LaunchOptions),
+    Help,
+    Version,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliError {
+    UnknownOption(String),
+    MissingValue(&'static str),
+    /// A value was given to a flag that takes none, as in `--kiosk=yes`.
+    UnexpectedValue(&'static str),
+    InvalidValue {
+        option: &'static str,
+        value: String,
+        reason: &'static str,
+    },
+    /// More than one positional URL.
+    UnexpectedArgument(String),
+    Conflict(&'static str, &'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            CliError::MissingValue(option) => write!(f, "option '{}' needs a value", option),
+            CliError::UnexpectedValue(option) => {
+                write!(f, "option '{}' does not take a value", option)
+            }
+            CliError::InvalidValue {
+                option,
+                value,
+                reason,
+            } => write!(f, "invalid value '{}' for '{}': {}", value, option, reason),
+            CliError::UnexpectedArgument(arg) => write!(
+                f,
+                "unexpected argument '{}' (use --new-tab to open more URLs)",
+                arg
+            ),
+            CliError::Conflict(a, b) => write!(f, "'{}' cannot be used with '{}'", a, b),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Parses the arguments that follow the program name. Options take their
+/// value either as the next argument or after `=`; `--` ends option parsing.
+pub fn parse<I, S>(args: I) -> Result<Command, CliError>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut options = LaunchOptions::default();
+    let mut args = args.into_iter().map(Into::into);
+    let mut only_positional = false;
+    while let Some(arg) = args.next() {
+        if only_positional || !arg.starts_with('-') || arg == "-" {
+            if options.url.is_some() {
+                return Err(CliError::UnexpectedArgument(arg));
+            }
+            options.url = Some(arg);
+            continue;
+        }
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |option: &'static str| match inline.clone().or_else(|| args.next()) {
+            Some(value) if !value.is_empty() => Ok(value),
+            _ => Err(CliError::MissingValue(option)),
+        };
+        let flag = |option: &'static str| match inline {
+            Some(_) => Err(CliError::UnexpectedValue(option)),
+            None => Ok(()),
+        };
+        match name {
+            "--" => only_positional = true,
+            "-h" | "--help" => return flag("--help").map(|_| Command::Help),
+            "-V" | "--version" => return flag("--version").map(|_| Command::Version),
+            "--profile" => options.profile = Some(value("--profile")?.into()),
+            "--private" => {
+                flag("--private")?;
+                options.private = true;
+            }
+            "--window-size" => {
+                let size = value("--window-size")?;
+                options.window_size = Some(parse_size(&size).ok_or(CliError::InvalidValue {
+                    option: "--window-size",
+                    value: size,
+                    reason: "expected WIDTHxHEIGHT with positive integers, e.g. 1280x800",
+                })?);
+            }
+            "--user-agent" => options.user_agent = Some(value("--user-agent")?),
+            "--kiosk" => {
+                flag("--kiosk")?;
+                options.kiosk = true;
+            }
+            "--new-tab" => options.new_tabs.push(value("--new-tab")?),
+            "--config" => options.config = Some(value("--config")?.into()),
+            "--restore-session" => {
+                flag("--restore-session")?;
+                options.restore_session = true;
+            }
+            "--headless" => {
+                flag("--headless")?;
+                options.headless = true;
+            }
+            "--script" => {
+                options.script = Some(value("--script")?.into());
+                options.headless = true;
+            }
+            _ => return Err(CliError::UnknownOption(name.to_string())),
+        }
+    }
+    if options.private && options.profile.is_some() {
+        return Err(CliError::Conflict("--private", "--profile"));
+    }
+    if options.private && options.restore_session {
+        return Err(CliError::Conflict("--private", "--restore-session"));
+    }
+    Ok(Command::Launch(options))
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once(['x', 'X'])?;
+    let width: u32 = width.trim().parse().ok()?;
+    let height: u32 = height.trim().parse().ok()?;
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// `wrybrowser 0.1.0`
+pub fn version() -> String {
+    format!("{} {}", APP_NAME, env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn launch(args: &[&str]) -> LaunchOptions {
+        match parse(args.iter().copied()) {
+            Ok(Command::Launch(options)) => options,
+            other => panic!("{:?} parsed as {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let options = launch(&[
+            "--profile",
+            "/tmp/p",
+            "--window-size=1280x800",
+            "--user-agent",
+            "Test/1.0",
+            "--kiosk",
+            "--new-tab",
+            "a.com",
+            "--new-tab=b.com",
+            "--config",
+            "c.toml",
+            "--restore-session",
+            "--script",
+            "-",
+            "example.com",
+        ]);
+        assert_eq!(
+            options,
+            LaunchOptions {
+                url: Some("example.com".into()),
+                new_tabs: vec!["a.com".into(), "b.com".into()],
+                profile: Some("/tmp/p".into()),
+                private: false,
+                window_size: Some((1280, 800)),
+                user_agent: Some("Test/1.0".into()),
+                kiosk: true,
+                config: Some("c.toml".into()),
+                restore_session: true,
+                headless: true,
+                script: Some("-".into()),
+            }
+        );
+        assert_eq!(launch(&[]), LaunchOptions::default());
+        assert!(launch(&["--private"]).private);
+        assert!(launch(&["--headless"]).headless);
+        assert_eq!(launch(&["--", "--kiosk"]).url.as_deref(), Some("--kiosk"));
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(parse(["--kiosk", "-h"]), Ok(Command::Help));
+        assert_eq!(parse(["--version"]), Ok(Command::Version));
+        assert_eq!(parse(["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            parse(["--frobnicate"]),
+            Err(CliError::UnknownOption("--frobnicate".into()))
+        );
+        assert_eq!(
+            parse(["--profile"]),
+            Err(CliError::MissingValue("--profile"))
+        );
+        assert_eq!(
+            parse(["--new-tab="]),
+            Err(CliError::MissingValue("--new-tab"))
+        );
+        assert_eq!(
+            parse(["--kiosk=yes"]),
+            Err(CliError::UnexpectedValue("--kiosk"))
+        );
+        for size in ["1280", "0x800", "wide x tall", "1280x-1"] {
+            assert!(
+                matches!(
+                    parse(["--window-size", size]),
+                    Err(CliError::InvalidValue {
+                        option: "--window-size",
+                        ..
+                    })
+                ),
+                "{}",
+                size
+            );
+        }
+        assert_eq!(
+            parse(["a.com", "b.com"]),
+            Err(CliError::UnexpectedArgument("b.com".into()))
+        );
+        assert_eq!(
+            parse(["--private", "--profile", "p"]),
+            Err(CliError::Conflict("--private", "--profile"))
+        );
+        assert_eq!(
+            parse(["--restore-session", "--private"]),
+            Err(CliError::Conflict("--private", "--restore-session"))
+        );
+        assert_eq!(
+            CliError::MissingValue("--config").to_string(),
+            "option '--config' needs a value"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::persist::PersistError;
+use crate::popups::NewWindowPolicy;
+use crate::url_fixup::DEFAULT_SEARCH_TEMPLATE;
+use crate::visits::Retention;
+use crate::{DEFAULT_HISTORY_CAPACITY, DEFAULT_HOMEPAGE};
+
+/// Top-level keys of the config file; anything else is reported as unknown.
+const KNOWN_KEYS: &[&str] = &[
+    "homepage",
+    "search_engine",
+    "toolbar_height",
+    "default_zoom",
+    "user_agent",
+    "download_dir",
+    "history_retention_days",
+    "history_max_visits",
+    "tab_history_capacity",
+    "new_window_policy",
+    "block_popups",
+    "keys",
+];
+
+/// User settings read from `config.toml`. Every key is optional:
+///
+/// ```toml
+/// homepage = "https://example.org"
+/// search_engine = "https://www.google.com/search?q={searchTerms}"
+/// toolbar_height = 36
+/// default_zoom = 1.25
+/// user_agent = "Mozilla/5.0 ..."
+/// download_dir = "/home/me/Downloads"
+/// history_retention_days = 30
+/// history_max_visits = 5000
+/// tab_history_capacity = 100
+/// new_window_policy = "new-window"
+/// block_popups = false
+///
+/// [keys]
+/// "Ctrl+Shift+T" = "new-tab"
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Page for new tabs and first launches.
+    pub homepage: String,
+    /// Search URL template containing `{searchTerms}`.
+    pub search_engine: String,
+    /// Toolbar height in logical pixels.
+    pub toolbar_height: f64,
+    /// Zoom factor applied to every page, 1.0 being 100%.
+    pub default_zoom: f64,
+    pub user_agent: Option<String>,
+    /// Where downloads are saved; the engine's choice when unset.
+    pub download_dir: Option<PathBuf>,
+    /// Days the global history keeps visits for; 0 keeps them forever.
+    pub history_retention_days: u32,
+    /// Most visits the global history keeps; 0 for no limit.
+    pub history_max_visits: usize,
+    /// Most back/forward entries each tab keeps; a tab may have its own.
+    pub tab_history_capacity: usize,
+    /// Where links and scripts asking for a new window open.
+    pub new_window_policy: NewWindowPolicy,
+    /// Block new windows that pages open without a user gesture.
+    pub block_popups: bool,
+    /// Key bindings, from key chord to action name.
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            homepage: DEFAULT_HOMEPAGE.into(),
+            search_engine: DEFAULT_SEARCH_TEMPLATE.into(),
+            toolbar_height: 40.0,
+            default_zoom: 1.0,
+            user_agent: None,
+            download_dir: None,
+            history_retention_days: Retention::default().max_age_days,
+            history_max_visits: Retention::default().max_visits,
+            tab_history_capacity: DEFAULT_HISTORY_CAPACITY,
+            new_window_policy: NewWindowPolicy::default(),
+            block_popups: true,
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Parses a config file. Returns the config along with warnings about
+    /// unknown keys and out-of-range values, which fall back to defaults.
+    pub fn parse(text: &str) -> Result<(Self, Vec<String>), toml::de::Error> {
+        let table: toml::Table = toml::from_str(text)?;
+        let mut warnings: Vec<String> = table
+            .keys()
+            .filter(|key| !KNOWN_KEYS.contains(&key.as_str()))
+            .map(|key| format!("unknown key '{}'", key))
+            .collect();
+        let mut config: Config = toml::Value::Table(table).try_into()?;
+        let defaults = Config::default();
+        if !(config.toolbar_height.is_finite() && config.toolbar_height >= 0.0) {
+            warnings.push(format!(
+                "toolbar_height must not be negative, using {}",
+                defaults.toolbar_height
+            ));
+            config.toolbar_height = defaults.toolbar_height;
+        }
+        if !(config.default_zoom.is_finite() && config.default_zoom > 0.0) {
+            warnings.push(format!(
+                "default_zoom must be positive, using {}",
+                defaults.default_zoom
+            ));
+            config.default_zoom = defaults.default_zoom;
+        }
+        if config.tab_history_capacity == 0 {
+            warnings.push(format!(
+                "tab_history_capacity must be positive, using {}",
+                defaults.tab_history_capacity
+            ));
+            config.tab_history_capacity = defaults.tab_history_capacity;
+        }
+        Ok((config, warnings))
+    }
+
+    /// How long the global history keeps visits.
+    pub fn retention(&self) -> Retention {
+        Retention {
+            max_age_days: self.history_retention_days,
+            max_visits: self.history_max_visits,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<(Self, Vec<String>), PersistError> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|err| PersistError::Corrupt {
+            path: path.to_path_buf(),
+            reason: err.message().to_string(),
+        })
+    }
+
+    /// Loads `path`, printing any warnings. A missing file yields the
+    /// defaults unless `required` is set.
+    pub fn load_or_default(path: &Path, required: bool) -> Result<Self, PersistError> {
+        match Self::load(path) {
+            Ok((config, warnings)) => {
+                for warning in warnings {
+                    eprintln!("{}: {}", path.display(), warning);
+                }
+                Ok(config)
+            }
+            Err(err) if err.is_not_found() && !required => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Notices when the config file is created, modified or removed, by
+/// comparing its modification time between polls.
+#[derive(Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file changed since the previous call (or since `new`).
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_every_setting() {
+        let (config, warnings) = Config::parse(
+            r#"
+            homepage = "https://example.org"
+            search_engine = "https://search.example/?q={searchTerms}"
+            toolbar_height = 36
+            default_zoom = 1.25
+            user_agent = "Test/1.0"
+            download_dir = "/tmp/downloads"
+            history_retention_days = 30
+            history_max_visits = 0
+            tab_history_capacity = 100
+            new_window_policy = "block"
+            block_popups = false
+
+            [keys]
+            "Ctrl+Shift+T" = "new-tab"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(warnings, Vec::<String>::new());
+        assert_eq!(config.homepage, "https://example.org");
+        assert_eq!(
+            config.search_engine,
+            "https://search.example/?q={searchTerms}"
+        );
+        assert_eq!(config.toolbar_height, 36.0);
+        assert_eq!(config.default_zoom, 1.25);
+        assert_eq!(config.user_agent.as_deref(), Some("Test/1.0"));
+        assert_eq!(config.download_dir, Some(PathBuf::from("/tmp/downloads")));
+        assert_eq!(
+            config.retention(),
+            Retention {
+                max_age_days: 30,
+                max_visits: 0
+            }
+        );
+        assert_eq!(config.tab_history_capacity, 100);
+        assert_eq!(config.new_window_policy, NewWindowPolicy::Block);
+        assert!(!config.block_popups);
+        assert_eq!(config.keys["Ctrl+Shift+T"], "new-tab");
+
+        assert_eq!(Config::parse("").unwrap().0, Config::default());
+    }
+
+    #[test]
+    fn warns_about_unknown_keys_and_bad_values() {
+        let (config, warnings) = Config::parse(
+            "homepage = \"https://example.org\"\nhome_page = \"x\"\ndefault_zoom = 0\ntoolbar_height = -5\ntab_history_capacity = 0\n",
+        )
+        .unwrap();
+        assert_eq!(config.homepage, "https://example.org");
+        assert_eq!(config.default_zoom, 1.0);
+        assert_eq!(config.toolbar_height, 40.0);
+        assert_eq!(config.tab_history_capacity, DEFAULT_HISTORY_CAPACITY);
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(warnings[0], "unknown key 'home_page'");
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(Config::parse("homepage = ").is_err());
+        assert!(Config::parse("toolbar_height = \"tall\"").is_err());
+        assert!(Config::parse("new_window_policy = \"popup\"").is_err());
+
+        let missing = Path::new("/nonexistent/wrybrowser/config.toml");
+        assert_eq!(
+            Config::load_or_default(missing, false).unwrap(),
+            Config::default()
+        );
+        assert!(Config::load_or_default(missing, true)
+            .unwrap_err()
+            .is_not_found());
+    }
+
+    #[test]
+    fn watcher_sees_changes() {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::remove_file(&path).ok();
+
+        let mut watcher = ConfigWatcher::new(&path);
+        assert!(!watcher.changed());
+
+        fs::write(&path, "homepage = \"a\"").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(watcher.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,973 @@
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+use winit::{
+    application::ApplicationHandler,
+    dpi::{LogicalPosition, LogicalSize},
+    event::{ElementState, WindowEvent},
+    event_loop::{ActiveEventLoop, EventLoopProxy},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::{Fullscreen, Window, WindowId},
+};
+use wry::http::{header::CONTENT_TYPE, Response};
+use wry::{PageLoadEvent, WebView, WebViewBuilder};
+
+use crate::keymap::{Chord, Modifiers};
+use crate::visits;
+use crate::{
+    Browser, Config, ConfigWatcher, Effect, Layout, PopupRequest, SameDocumentNavigation,
+    ScrollPosition, TabId, WindowGeometry, APP_NAME,
+};
+
+const TOOLBAR_HTML: &str = r#"<style>
+body{margin:0;display:flex;align-items:center;gap:4px;font:13px sans-serif}
+#tabs{display:flex;gap:2px;overflow:hidden;max-width:40%}
+.tab{padding:2px 4px;border:1px solid #ccc;max-width:12em;overflow:hidden;white-space:nowrap;cursor:default}
+.tab.active{background:#ddd}
+.tab button{border:none;background:none;padding:0 2px}
+#spinner{width:10px;height:10px;border:2px solid #ccc;border-top-color:#36c;border-radius:50%;visibility:hidden}
+body.loading #spinner{visibility:visible;animation:spin 1s linear infinite}
+@keyframes spin{to{transform:rotate(360deg)}}
+#star{font-size:15px;border:none;background:none}
+#notice{color:#555;max-width:20em;overflow:hidden;white-space:nowrap}
+#jump{max-width:16em}
+</style>
+<div id='tabs'></div>
+<button id='newtab'>+</button>
+<button id='back' disabled>Back</button>
+<button id='forward' disabled>Forward</button>
+<select id='jump' hidden></select>
+<button id='reload' title='Reload (shift-click to bypass the cache)'>Reload</button>
+<span id='spinner'></span>
+<input id='addr' style='flex:1'>
+<button id='star' title='Bookmark this page (Ctrl+D)'>☆</button>
+<button id='bookmarks' title='Show bookmarks (Ctrl+B)'>Bookmarks</button>
+<button id='history' title='Show history (Ctrl+H)'>History</button>
+<span id='session' hidden><span id='session-text'></span> <button id='restore'>Restore</button><button id='dismiss' title='Dismiss'>×</button></span>
+<span id='notice'></span>
+<script>
+let seq=0;
+const send=(type,fields)=>window.ipc.postMessage(JSON.stringify(Object.assign({v:1,id:++seq,type},fields)));
+const $=id=>document.getElementById(id);
+$('newtab').addEventListener('click',()=>send('new-tab'));
+for(const [id,forward] of [['back',false],['forward',true]]){
+  const button=$(id);
+  let timer,held=false;
+  const menu=()=>{held=true;send('history-menu',{forward})};
+  button.addEventListener('click',()=>{if(!held)send(id)});
+  button.addEventListener('pointerdown',e=>{held=false;if(e.button===0)timer=setTimeout(menu,500)});
+  button.addEventListener('pointerup',()=>clearTimeout(timer));
+  button.addEventListener('pointerleave',()=>clearTimeout(timer));
+  button.addEventListener('contextmenu',e=>{e.preventDefault();menu()});
+}
+$('jump').addEventListener('change',e=>{
+  const delta=+e.target.value;
+  e.target.hidden=true;
+  if(delta)send('go-by',{delta});
+});
+$('jump').addEventListener('blur',e=>{e.target.hidden=true});
+$('reload').addEventListener('click',e=>{
+  if(document.body.classList.contains('loading'))send('stop');
+  else send(e.shiftKey?'hard-reload':'reload');
+});
+$('star').addEventListener('click',()=>send('toggle-bookmark'));
+$('bookmarks').addEventListener('click',()=>send('toggle-sidebar'));
+$('history').addEventListener('click',()=>send('go',{url:'wry://history'}));
+$('restore').addEventListener('click',()=>{$('session').hidden=true;send('restore-session')});
+$('dismiss').addEventListener('click',()=>{$('session').hidden=true;send('dismiss-session')});
+$('addr').addEventListener('keydown',e=>{
+  if(e.key==='Enter'){send('go',{url:e.target.value});e.target.blur()}
+  else if(e.key==='Escape'){e.target.value=e.target.dataset.url||'';e.target.blur()}
+});
+const renderTabs=(tabs,active)=>{
+  const strip=$('tabs');
+  strip.textContent='';
+  tabs.forEach((label,i)=>{
+    const tab=document.createElement('span');
+    tab.className=i===active?'tab active':'tab';
+    tab.textContent=label;
+    tab.title=label;
+    tab.draggable=true;
+    tab.addEventListener('click',()=>send('select-tab',{index:i}));
+    tab.addEventListener('auxclick',e=>{if(e.button===1)send('close-tab',{index:i})});
+    tab.addEventListener('dragstart',e=>e.dataTransfer.setData('text/plain',i));
+    tab.addEventListener('dragover',e=>e.preventDefault());
+    tab.addEventListener('drop',e=>{e.preventDefault();send('move-tab',{from:+e.dataTransfer.getData('text/plain'),to:i})});
+    tab.addEventListener('dragend',e=>{if(e.dataTransfer.dropEffect==='none'&&(e.clientY<0||e.clientY>innerHeight))send('move-tab-to-new-window',{index:i})});
+    const close=document.createElement('button');
+    close.textContent='×';
+    close.addEventListener('click',e=>{e.stopPropagation();send('close-tab',{index:i})});
+    tab.appendChild(close);
+    strip.appendChild(tab);
+  });
+};
+window.onCoreEvent=msg=>{
+  if(msg.v!==1)return;
+  switch(msg.type){
+    case 'navigation-state':{
+      const addr=$('addr');
+      addr.dataset.url=msg.url;
+      if(document.activeElement!==addr)addr.value=msg.url;
+      $('back').disabled=!msg.can_go_back;
+      $('forward').disabled=!msg.can_go_forward;
+      document.body.classList.toggle('loading',msg.loading);
+      $('reload').textContent=msg.loading?'Stop':'Reload';
+      $('star').textContent=msg.bookmarked?'★':'☆';
+      break;
+    }
+    case 'tabs':renderTabs(msg.tabs,msg.active);break;
+    case 'history-menu':{
+      const jump=$('jump');
+      jump.textContent='';
+      jump.add(new Option(msg.forward?'Forward to…':'Back to…',''));
+      for(const entry of msg.entries){
+        const option=new Option(entry.title,entry.delta);
+        option.title=entry.url;
+        jump.add(option);
+      }
+      jump.hidden=false;
+      jump.focus();
+      try{jump.showPicker()}catch(e){}
+      break;
+    }
+    case 'confirm-close':{
+      let question='Close '+(msg.tabs>1?msg.tabs+' tabs':'the window');
+      if(msg.downloads)question+=' and cancel '+msg.downloads+(msg.downloads===1?' download':' downloads');
+      if(confirm(question+'?'))send('close-window');
+      break;
+    }
+    case 'session-available':
+      $('session-text').textContent=(msg.crashed?'The browser did not shut down properly. ':'')+'Restore '+msg.tabs+(msg.tabs===1?' tab':' tabs')+' from the last session?';
+      $('session').hidden=false;
+      break;
+    case 'notice':{
+      const notice=$('notice');
+      notice.textContent=msg.message;
+      notice.title=msg.message;
+      clearTimeout(notice.timer);
+      notice.timer=setTimeout(()=>{notice.textContent=''},5000);
+      break;
+    }
+    case 'popup-blocked':{
+      const notice=$('notice'),open=document.createElement('button');
+      notice.textContent='Popup blocked ';
+      notice.title=msg.url;
+      open.textContent='Open';
+      open.addEventListener('click',()=>{notice.textContent='';send('open-popup',{url:msg.url})});
+      notice.appendChild(open);
+      clearTimeout(notice.timer);
+      notice.timer=setTimeout(()=>{notice.textContent=''},10000);
+      break;
+    }
+    case 'error':console.warn('toolbar request',msg.id,'failed:',msg.message);break;
+  }
+};
+</script>"#;
+
+const SIDEBAR_HTML: &str = r#"<style>
+body{margin:0;padding:4px;font:13px sans-serif;border-right:1px solid #ccc;box-sizing:border-box;min-height:100vh}
+ul{list-style:none;margin:0;padding-left:12px}
+li{white-space:nowrap;overflow:hidden;text-overflow:ellipsis}
+a{color:inherit;text-decoration:none;cursor:pointer}
+a:hover{text-decoration:underline}
+li button{border:none;background:none;padding:0 2px;visibility:hidden}
+li:hover>button{visibility:visible}
+#actions{display:flex;gap:4px;margin-bottom:4px}
+</style>
+<div id='actions'>
+<label><button id='import'>Import…</button><input id='file' type='file' accept='.html,.htm' hidden></label>
+<button id='export'>Export</button>
+</div>
+<ul id='tree'></ul>
+<script>
+let seq=0;
+const send=(type,fields)=>window.ipc.postMessage(JSON.stringify(Object.assign({v:1,id:++seq,type},fields)));
+const $=id=>document.getElementById(id);
+$('import').addEventListener('click',()=>$('file').click());
+$('file').addEventListener('change',e=>{
+  const file=e.target.files[0];
+  if(!file)return;
+  file.text().then(html=>send('import-bookmarks',{html}));
+  e.target.value='';
+});
+$('export').addEventListener('click',()=>send('export-bookmarks'));
+const removeButton=id=>{
+  const remove=document.createElement('button');
+  remove.textContent='×';
+  remove.title='Remove';
+  remove.addEventListener('click',e=>{e.preventDefault();e.stopPropagation();send('remove-bookmark',{bookmark:id})});
+  return remove;
+};
+const render=(children,list)=>{
+  children.forEach(node=>{
+    const item=document.createElement('li');
+    if(node.kind==='folder'){
+      const details=document.createElement('details');
+      const summary=document.createElement('summary');
+      summary.textContent=node.title||'(untitled folder)';
+      summary.appendChild(removeButton(node.id));
+      const sub=document.createElement('ul');
+      render(node.children,sub);
+      details.append(summary,sub);
+      item.appendChild(details);
+    }else{
+      const link=document.createElement('a');
+      link.textContent=node.title||node.url;
+      link.title=node.url+(node.tags.length?' ['+node.tags.join(', ')+']':'');
+      link.addEventListener('click',()=>send('open-url',{url:node.url}));
+      item.append(link,removeButton(node.id));
+    }
+    list.appendChild(item);
+  });
+};
+window.onCoreEvent=msg=>{
+  if(msg.v!==1||msg.type!=='bookmarks')return;
+  const tree=$('tree');
+  tree.textContent='';
+  render(msg.root.children,tree);
+};
+</script>"#;
+
+/// The `wry://history` page. It talks to the core like the toolbar does,
+/// but the core only accepts history commands from it.
+const HISTORY_HTML: &str = r#"<!doctype html><title>History</title>
+<style>
+body{font:14px sans-serif;margin:16px auto;max-width:60em}
+form{display:flex;gap:6px;flex-wrap:wrap;margin-bottom:8px}
+#text{flex:1}
+ul{list-style:none;padding:0}
+li{display:flex;gap:8px;align-items:center;padding:2px 0}
+li a{flex:1;overflow:hidden;text-overflow:ellipsis;white-space:nowrap}
+time{color:#666;font-size:12px;min-width:11em}
+li button{border:none;background:none;cursor:pointer}
+#notice{color:#555}
+</style>
+<h1>History</h1>
+<form id='search'>
+<input id='text' type='search' placeholder='Search URLs and titles'>
+<input id='domain' placeholder='Domain'>
+<label>From <input id='since' type='date'></label>
+<label>To <input id='until' type='date'></label>
+<button>Search</button>
+</form>
+<div>Clear: <button data-range='last-hour'>Last hour</button> <button data-range='last-day'>Last day</button> <button data-range='all'>Everything</button> <span id='notice'></span></div>
+<ul id='visits'></ul>
+<script>
+let seq=0,lastSearch=0;
+const send=(type,fields)=>{window.ipc.postMessage(JSON.stringify(Object.assign({v:1,id:++seq,type},fields)));return seq};
+const $=id=>document.getElementById(id);
+const seconds=(input,days)=>input.value?Date.parse(input.value)/1000+days*86400:null;
+const search=()=>{
+  lastSearch=send('search-history',{query:{
+    text:$('text').value||null,
+    domain:$('domain').value||null,
+    since:seconds($('since'),0),
+    until:seconds($('until'),1),
+    limit:500
+  }});
+};
+$('search').addEventListener('submit',e=>{e.preventDefault();search()});
+document.querySelectorAll('[data-range]').forEach(button=>button.addEventListener('click',()=>{
+  if(button.dataset.range!=='all'||confirm('Clear all history?'))send('clear-history',{range:button.dataset.range});
+}));
+const render=visits=>{
+  const list=$('visits');
+  list.textContent='';
+  visits.forEach(visit=>{
+    const item=document.createElement('li');
+    const time=document.createElement('time');
+    time.textContent=new Date(visit.time*1000).toLocaleString();
+    const link=document.createElement('a');
+    link.href=visit.url;
+    link.textContent=visit.title||visit.url;
+    link.title=visit.url;
+    const remove=document.createElement('button');
+    remove.textContent='×';
+    remove.title='Remove from history';
+    remove.addEventListener('click',()=>send('remove-visit',{visit:visit.id}));
+    item.append(time,link,remove);
+    list.appendChild(item);
+  });
+  if(!visits.length)list.textContent='No visits.';
+};
+window.onCoreEvent=msg=>{
+  if(msg.v!==1)return;
+  switch(msg.type){
+    case 'visits':render(msg.visits);break;
+    case 'notice':$('notice').textContent=msg.message;break;
+    case 'ack':if(msg.id!==lastSearch)search();break;
+    case 'error':console.warn('history request',msg.id,'failed:',msg.message);break;
+  }
+};
+search();
+</script>"#;
+
+/// Injected into every page, with `TOKEN` replaced: reports where the page
+/// is scrolled to, a moment after scrolling stops, so going back can
+/// restore it.
+///
+/// This and the other injected scripts run before the page's own. They
+/// keep what they need from the page's globals at that point, and spell
+/// out their messages rather than stringifying objects, so no `toJSON` or
+/// `JSON.stringify` the page defines later ever sees the token.
+const SCROLL_REPORT_SCRIPT: &str = r#"((token)=>{let timer;
+const post=window.ipc.postMessage.bind(window.ipc),stringify=JSON.stringify,tail=',"token":'+stringify(token)+'}';
+addEventListener('scroll',()=>{
+  clearTimeout(timer);
+  timer=setTimeout(()=>post('{"url":'+stringify(''+location.href)+',"x":'+(+scrollX)+',"y":'+(+scrollY)+tail),200);
+},{passive:true});})(TOKEN);"#;
+
+/// Injected into every page, with `TOKEN` replaced: takes over
+/// `window.open` and links to new windows, and reports them with whether
+/// the user clicked or typed within the last second. Opening a page in the
+/// window itself or in one of its frames is left to `window.open`. The
+/// token, which page scripts cannot read, keeps them from posting reports
+/// of their own.
+const POPUP_SCRIPT: &str = r#"((token)=>{let last=0;
+const post=window.ipc.postMessage.bind(window.ipc),stringify=JSON.stringify,tail=',"token":'+stringify(token)+'}';
+const now=Date.now,Url=URL;
+for(const type of ['pointerdown','keydown','click'])addEventListener(type,e=>{if(e.isTrusted)last=now()},true);
+const report=url=>post('{"popup":'+stringify(''+new Url(url,location.href).href)+',"gesture":'+(now()-last<1000)+tail);
+const open=window.open;
+window.open=function(url,target){
+  const name=target==null?'':String(target);
+  if(['_self','_parent','_top'].includes(name.toLowerCase())||(name&&window.frames[name]))return open.apply(this,arguments);
+  if(url)report(String(url));
+  return null;
+};
+addEventListener('click',e=>{
+  const link=e.target.closest&&e.target.closest('a[target]');
+  if(!link||!link.href||e.defaultPrevented)return;
+  const target=link.target.toLowerCase();
+  if(['','_self','_parent','_top'].includes(target)||window.frames[link.target])return;
+  e.preventDefault();
+  report(link.href);
+});})(TOKEN);"#;
+
+/// Injected into every page, with `TOKEN` replaced: reports the page
+/// moving to another URL without loading, through the History API, an
+/// anchor, or going back or forward within itself.
+const SAME_DOCUMENT_SCRIPT: &str = r#"((token)=>{
+const post=window.ipc.postMessage.bind(window.ipc),stringify=JSON.stringify,tail=',"token":'+stringify(token)+'}';
+const report=kind=>post('{"same_document":"'+kind+'","url":'+stringify(''+location.href)+tail);
+for(const kind of ['push','replace']){
+  const method=history[kind+'State'];
+  history[kind+'State']=function(...args){const result=method.apply(this,args);report(kind);return result};
+}
+addEventListener('popstate',()=>report('traverse'));
+addEventListener('hashchange',()=>report('traverse'));
+})(TOKEN);"#;
+
+/// What [`SAME_DOCUMENT_SCRIPT`] posts.
+#[derive(Deserialize)]
+struct SameDocumentReport {
+    same_document: SameDocumentNavigation,
+    url: String,
+    token: String,
+}
+
+/// What [`POPUP_SCRIPT`] posts.
+#[derive(Deserialize)]
+struct PopupReport {
+    popup: String,
+    gesture: bool,
+    token: String,
+}
+
+/// A secret for one view's [`SCROLL_REPORT_SCRIPT`], [`POPUP_SCRIPT`] and
+/// [`SAME_DOCUMENT_SCRIPT`].
+fn script_token() -> String {
+    format!("{:016x}", RandomState::new().build_hasher().finish())
+}
+
+/// What [`SCROLL_REPORT_SCRIPT`] posts.
+#[derive(Deserialize)]
+struct ScrollReport {
+    url: String,
+    #[serde(flatten)]
+    scroll: ScrollPosition,
+    token: String,
+}
+
+/// Width of the bookmarks sidebar, in logical pixels.
+const SIDEBAR_WIDTH: f64 = 250.0;
+
+/// Events delivered to the event loop from WebView callbacks.
+pub enum UserEvent {
+    /// Raw JSON message posted by the toolbar or sidebar of `window`.
+    Toolbar {
+        window: WindowId,
+        body: String,
+    },
+    PageLoad {
+        tab: TabId,
+        event: PageLoadEvent,
+        url: String,
+    },
+    TitleChanged {
+        tab: TabId,
+        title: String,
+    },
+    Scrolled {
+        tab: TabId,
+        url: String,
+        scroll: ScrollPosition,
+    },
+    /// A page moved to `url` without loading a new document.
+    SameDocument {
+        tab: TabId,
+        kind: SameDocumentNavigation,
+        url: String,
+    },
+    /// A page asked for a new window.
+    NewWindowRequested {
+        tab: TabId,
+        request: PopupRequest,
+    },
+    /// Any other message posted by a page, for [`Browser::handle_page_message`].
+    PageMessage {
+        tab: TabId,
+        /// The URL of the page that posted it, as the engine reports it.
+        origin: String,
+        body: String,
+    },
+    /// The config file was created, modified or removed.
+    ConfigChanged,
+    /// Time for a periodic session snapshot.
+    SaveSession,
+    DownloadStarted,
+    /// A download finished, failed or was cancelled.
+    DownloadFinished,
+}
+
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls the config file on a background thread, sending
+/// [`UserEvent::ConfigChanged`] whenever it changes, until the event loop
+/// goes away.
+pub fn watch_config(mut watcher: ConfigWatcher, proxy: EventLoopProxy<UserEvent>) {
+    thread::spawn(move || loop {
+        thread::sleep(CONFIG_POLL_INTERVAL);
+        if watcher.changed() && proxy.send_event(UserEvent::ConfigChanged).is_err() {
+            break;
+        }
+    });
+}
+
+/// How often the session is snapshotted, so a crash loses little.
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Sends [`UserEvent::SaveSession`] every [`SESSION_SAVE_INTERVAL`] until
+/// the event loop goes away.
+pub fn save_session_periodically(proxy: EventLoopProxy<UserEvent>) {
+    thread::spawn(move || loop {
+        thread::sleep(SESSION_SAVE_INTERVAL);
+        if proxy.send_event(UserEvent::SaveSession).is_err() {
+            break;
+        }
+    });
+}
+
+/// Builds one of the browser's own HTML panels (toolbar, sidebar). Messages
+/// they post are forwarded to the event loop as [`UserEvent::Toolbar`].
+fn build_panel(
+    window: &Window,
+    html: &str,
+    bounds: wry::Rect,
+    proxy: Option<EventLoopProxy<UserEvent>>,
+) -> WebView {
+    let window_id = window.id();
+    WebViewBuilder::new()
+        .with_html(html)
+        .with_bounds(bounds)
+        .with_ipc_handler(move |req| {
+            if let Some(proxy) = &proxy {
+                proxy
+                    .send_event(UserEvent::Toolbar {
+                        window: window_id,
+                        body: req.body().clone(),
+                    })
+                    .ok();
+            }
+        })
+        .build_as_child(window)
+        .unwrap()
+}
+
+fn build_content_view(
+    window: &Window,
+    bounds: wry::Rect,
+    tab: TabId,
+    url: &str,
+    config: &Config,
+    user_agent: Option<&str>,
+    proxy: Option<EventLoopProxy<UserEvent>>,
+) -> WebView {
+    let token = script_token();
+    let token_literal = serde_json::to_string(&token).unwrap();
+    let mut builder = WebViewBuilder::new();
+    if let Some(user_agent) = user_agent {
+        builder = builder.with_user_agent(user_agent);
+    }
+    let download_dir = config.download_dir.clone();
+    let view = builder
+        .with_download_started_handler({
+            let proxy = proxy.clone();
+            move |url, dest| {
+                if let Some(dir) = &download_dir {
+                    let name = dest
+                        .file_name()
+                        .map(|name| name.to_os_string())
+                        .or_else(|| {
+                            let path = url.split(['?', '#']).next().unwrap_or_default();
+                            path.rsplit('/')
+                                .next()
+                                .filter(|name| !name.is_empty())
+                                .map(Into::into)
+                        })
+                        .unwrap_or_else(|| "download".into());
+                    *dest = dir.join(name);
+                }
+                if let Some(proxy) = &proxy {
+                    proxy.send_event(UserEvent::DownloadStarted).ok();
+                }
+                true
+            }
+        })
+        .with_download_completed_handler({
+            let proxy = proxy.clone();
+            move |_, _, _| {
+                if let Some(proxy) = &proxy {
+                    proxy.send_event(UserEvent::DownloadFinished).ok();
+                }
+            }
+        })
+        .with_url(url)
+        .with_bounds(bounds)
+        .with_visible(false)
+        .with_custom_protocol("wry".into(), |_, request| {
+            let (status, body) = if visits::is_history_page(&request.uri().to_string()) {
+                (200, HISTORY_HTML)
+            } else {
+                (404, "Not found")
+            };
+            Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(Cow::Borrowed(body.as_bytes()))
+                .unwrap()
+        })
+        .with_initialization_script(&SCROLL_REPORT_SCRIPT.replace("TOKEN", &token_literal))
+        .with_initialization_script(&POPUP_SCRIPT.replace("TOKEN", &token_literal))
+        .with_initialization_script(&SAME_DOCUMENT_SCRIPT.replace("TOKEN", &token_literal))
+        .with_ipc_handler({
+            let proxy = proxy.clone();
+            move |req| {
+                let Some(proxy) = &proxy else {
+                    return;
+                };
+                let body = req.body();
+                let event = if let Some(report) = serde_json::from_str::<ScrollReport>(body)
+                    .ok()
+                    .filter(|report| report.token == token)
+                {
+                    UserEvent::Scrolled {
+                        tab,
+                        url: report.url,
+                        scroll: report.scroll,
+                    }
+                } else if let Some(report) = serde_json::from_str::<PopupReport>(body)
+                    .ok()
+                    .filter(|report| report.token == token)
+                {
+                    UserEvent::NewWindowRequested {
+                        tab,
+                        request: PopupRequest {
+                            url: report.popup,
+                            user_gesture: report.gesture,
+                        },
+                    }
+                } else if let Some(report) = serde_json::from_str::<SameDocumentReport>(body)
+                    .ok()
+                    .filter(|report| report.token == token)
+                {
+                    UserEvent::SameDocument {
+                        tab,
+                        kind: report.same_document,
+                        url: report.url,
+                    }
+                } else {
+                    UserEvent::PageMessage {
+                        tab,
+                        origin: req.uri().to_string(),
+                        body: body.clone(),
+                    }
+                };
+                proxy.send_event(event).ok();
+            }
+        })
+        // Whatever the script above did not catch, such as forms posting
+        // to a new window, is reported without a gesture.
+        .with_new_window_req_handler({
+            let proxy = proxy.clone();
+            move |url| {
+                if let Some(proxy) = &proxy {
+                    let request = PopupRequest {
+                        url,
+                        user_gesture: false,
+                    };
+                    proxy
+                        .send_event(UserEvent::NewWindowRequested { tab, request })
+                        .ok();
+                }
+                false
+            }
+        })
+        .with_document_title_changed_handler({
+            let proxy = proxy.clone();
+            move |title| {
+                if let Some(proxy) = &proxy {
+                    proxy
+                        .send_event(UserEvent::TitleChanged { tab, title })
+                        .ok();
+                }
+            }
+        })
+        .with_on_page_load_handler(move |event, url| {
+            if let Some(proxy) = &proxy {
+                proxy
+                    .send_event(UserEvent::PageLoad { tab, event, url })
+                    .ok();
+            }
+        })
+        .build_as_child(window)
+        .unwrap();
+    view.zoom(config.default_zoom).ok();
+    view
+}
+
+/// The keymap chord for a key press, or `None` for a bare modifier key.
+fn chord(key: &Key, mods: ModifiersState) -> Option<Chord> {
+    let modifiers = Modifiers {
+        ctrl: mods.control_key(),
+        alt: mods.alt_key(),
+        shift: mods.shift_key(),
+        meta: mods.super_key(),
+    };
+    match key {
+        Key::Character(c) => Some(Chord::character(modifiers, c)),
+        Key::Named(
+            NamedKey::Control
+            | NamedKey::Shift
+            | NamedKey::Alt
+            | NamedKey::Super
+            | NamedKey::Meta
+            | NamedKey::Hyper
+            | NamedKey::AltGraph,
+        ) => None,
+        Key::Named(named) => Some(Chord::named(modifiers, &format!("{:?}", named))),
+        _ => None,
+    }
+}
+
+impl Browser {
+    fn current_layout(&self) -> Option<Layout> {
+        let window = self.window.as_ref()?;
+        let size = window.inner_size();
+        Some(Layout::compute(
+            size.width,
+            size.height,
+            window.scale_factor(),
+            &self.layout,
+        ))
+    }
+
+    /// Recomputes the layout for the current window size and moves every
+    /// view into place.
+    fn apply_layout(&self) {
+        let Some(layout) = self.current_layout() else {
+            return;
+        };
+        if let Some(toolbar) = &self.toolbar {
+            toolbar.set_bounds(layout.toolbar);
+        }
+        if let (Some(sidebar), Some(bounds)) = (&self.sidebar, layout.sidebar) {
+            sidebar.set_bounds(bounds);
+        }
+        for tab in self.tabs.iter() {
+            if let Some(view) = &tab.view {
+                view.set_bounds(layout.content);
+            }
+        }
+    }
+
+    /// Creates content views for tabs that lack one, shows only the active
+    /// tab and refreshes the toolbar.
+    fn attach_views(&mut self) {
+        let (Some(window), Some(layout)) = (&self.window, self.current_layout()) else {
+            return;
+        };
+        let user_agent = self.effective_user_agent().map(str::to_owned);
+        let (config, proxy) = (&self.config, &self.proxy);
+        self.tabs.fill_views(|tab| {
+            let url = tab.history.current_url();
+            Box::new(build_content_view(
+                window,
+                layout.content.into(),
+                tab.id(),
+                &url,
+                config,
+                user_agent.as_deref(),
+                proxy.clone(),
+            ))
+        });
+        self.sync_tabs();
+    }
+
+    /// Shows or hides the bookmarks sidebar, building it on first use.
+    fn toggle_sidebar(&mut self) {
+        let open = self.layout.sidebar_width.is_none();
+        self.layout.sidebar_width = open.then_some(SIDEBAR_WIDTH);
+        if open && self.sidebar.is_none() {
+            let (Some(window), Some(layout)) = (&self.window, self.current_layout()) else {
+                return;
+            };
+            if let Some(bounds) = layout.sidebar {
+                self.sidebar = Some(Box::new(build_panel(
+                    window,
+                    SIDEBAR_HTML,
+                    bounds.into(),
+                    self.proxy.clone(),
+                )));
+            }
+        }
+        if let Some(sidebar) = &self.sidebar {
+            sidebar.set_visible(open);
+        }
+        self.apply_layout();
+        self.sync_sidebar();
+    }
+
+    /// Carries out what the browser asked of the window, then retitles the
+    /// window after the active tab.
+    fn apply(&mut self, event_loop: &ActiveEventLoop, effect: Effect) {
+        match effect {
+            Effect::None => {}
+            Effect::TabsChanged => self.attach_views(),
+            Effect::ToggleSidebar => self.toggle_sidebar(),
+            Effect::WindowsChanged => {
+                self.attach_views();
+                self.create_windows(event_loop);
+            }
+            Effect::CloseWindow => drop(self.close_window()),
+            Effect::Close => self.close(event_loop),
+        }
+        if let Some(window) = &self.window {
+            window.set_title(&self.window_title());
+        }
+    }
+
+    /// Records where the window is and how large, for session snapshots.
+    /// A fullscreen kiosk window has nothing worth restoring.
+    fn update_geometry(&mut self) {
+        let Some(window) = self.window.as_ref().filter(|_| !self.kiosk) else {
+            return;
+        };
+        let scale = window.scale_factor();
+        let size: LogicalSize<u32> = window.inner_size().to_logical(scale);
+        let position = window.outer_position().ok().map(|position| {
+            let position: LogicalPosition<i32> = position.to_logical(scale);
+            (position.x, position.y)
+        });
+        self.geometry = Some(WindowGeometry {
+            position,
+            size: (size.width, size.height),
+        });
+    }
+
+    /// Shuts the browser down, then drops the windows (after the views
+    /// inside them) and leaves the event loop.
+    fn close(&mut self, event_loop: &ActiveEventLoop) {
+        self.shutdown();
+        self.window = None;
+        self.other_windows.clear();
+        event_loop.exit();
+    }
+
+    /// Makes the browser window behind `id` the current one. Returns false
+    /// for a window that is already gone.
+    fn focus(&mut self, id: WindowId) -> bool {
+        if self.window.as_ref().map(Window::id) == Some(id) {
+            return true;
+        }
+        let index = self
+            .other_windows
+            .iter()
+            .position(|window| window.window.as_ref().map(Window::id) == Some(id));
+        index.is_some_and(|index| self.focus_window(index))
+    }
+
+    /// Runs `f` for every window in turn, each as the current one, and ends
+    /// in the window it started in.
+    fn for_each_window(&mut self, mut f: impl FnMut(&mut Self)) {
+        for index in 0..self.other_windows.len() {
+            self.focus_window(index);
+            f(self);
+            self.focus_window(index);
+        }
+        f(self);
+    }
+
+    /// Gives every browser window that does not have a window yet one, with
+    /// a toolbar and views for its tabs.
+    fn create_windows(&mut self, event_loop: &ActiveEventLoop) {
+        self.for_each_window(|browser| {
+            if browser.window.is_none() {
+                browser.create_window(event_loop);
+            }
+        });
+    }
+
+    /// Creates the current browser window's window and toolbar, at its
+    /// restored geometry if it has one.
+    fn create_window(&mut self, event_loop: &ActiveEventLoop) {
+        let mut attributes = Window::default_attributes().with_title(APP_NAME);
+        let restored = self.geometry.filter(|_| !self.kiosk);
+        if let Some((width, height)) = self.window_size.or(restored.map(|g| g.size)) {
+            attributes = attributes.with_inner_size(LogicalSize::new(width, height));
+        }
+        if let Some((x, y)) = restored.and_then(|g| g.position) {
+            attributes = attributes.with_position(LogicalPosition::new(x, y));
+        }
+        if self.kiosk {
+            attributes = attributes
+                .with_decorations(false)
+                .with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        let window = event_loop.create_window(attributes).unwrap();
+
+        let size = window.inner_size();
+        let layout = Layout::compute(size.width, size.height, window.scale_factor(), &self.layout);
+
+        let toolbar = build_panel(
+            &window,
+            TOOLBAR_HTML,
+            layout.toolbar.into(),
+            self.proxy.clone(),
+        );
+        if self.kiosk {
+            toolbar.set_visible(false).ok();
+        }
+
+        self.window = Some(window);
+        self.toolbar = Some(Box::new(toolbar));
+        self.update_geometry();
+        self.apply(event_loop, Effect::TabsChanged);
+    }
+}
+
+impl ApplicationHandler<UserEvent> for Browser {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        self.create_windows(event_loop);
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        // Handle the event in the window it came from.
+        let focused = match &event {
+            UserEvent::Toolbar { window, .. } => self.focus(*window),
+            UserEvent::PageLoad { tab, .. }
+            | UserEvent::TitleChanged { tab, .. }
+            | UserEvent::Scrolled { tab, .. }
+            | UserEvent::SameDocument { tab, .. }
+            | UserEvent::NewWindowRequested { tab, .. }
+            | UserEvent::PageMessage { tab, .. } => self.focus_tab(*tab),
+            _ => true,
+        };
+        if !focused {
+            return;
+        }
+        let effect = match event {
+            UserEvent::Toolbar { body, .. } => self.handle_toolbar_message(&body),
+            UserEvent::PageLoad { tab, event, url } => {
+                match event {
+                    PageLoadEvent::Started => self.page_load_started(tab, url),
+                    PageLoadEvent::Finished => self.page_load_finished(tab, url),
+                }
+                Effect::None
+            }
+            UserEvent::TitleChanged { tab, title } => {
+                self.title_changed(tab, title);
+                Effect::None
+            }
+            UserEvent::Scrolled { tab, url, scroll } => {
+                self.scrolled(tab, &url, scroll);
+                Effect::None
+            }
+            UserEvent::SameDocument { tab, kind, url } => {
+                self.same_document_navigation(tab, kind, url);
+                Effect::None
+            }
+            UserEvent::NewWindowRequested { tab, request } => {
+                self.new_window_requested(tab, request)
+            }
+            UserEvent::PageMessage { tab, origin, body } => {
+                self.handle_page_message(tab, &origin, &body);
+                Effect::None
+            }
+            UserEvent::SaveSession => {
+                // Once the window is gone, the clean snapshot taken on
+                // shutdown must stay.
+                if self.window.is_some() {
+                    self.snapshot_session();
+                }
+                Effect::None
+            }
+            UserEvent::DownloadStarted => {
+                self.active_downloads += 1;
+                Effect::None
+            }
+            UserEvent::DownloadFinished => {
+                self.active_downloads = self.active_downloads.saturating_sub(1);
+                Effect::None
+            }
+            UserEvent::ConfigChanged => {
+                self.reload_config();
+                self.for_each_window(|browser| browser.apply_layout());
+                Effect::None
+            }
+        };
+        self.apply(event_loop, effect);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
+        if !self.focus(id) {
+            return;
+        }
+        match event {
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                if let Some(chord) = chord(&event.logical_key, self.modifiers) {
+                    let effect = self.handle_key(chord);
+                    self.apply(event_loop, effect);
+                }
+            }
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                self.update_geometry();
+                self.apply_layout();
+            }
+            WindowEvent::Moved(_) => self.update_geometry(),
+            WindowEvent::ModifiersChanged(mods) => {
+                self.modifiers = mods.state();
+            }
+            WindowEvent::CloseRequested => {
+                let effect = self.request_close();
+                self.apply(event_loop, effect);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,687 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::rc::Rc;
+
+use serde::Serialize;
+use url::Url;
+
+use crate::layout::Rect;
+use crate::{Browser, Chord, Effect, PageView, TabCommand, TabId};
+
+/// One step of a headless navigation script.
+///
+/// Scripts have one command per line; blank lines and lines starting with
+/// `#` are skipped, and a line holding only a URL navigates to it, so a plain
+/// list of URLs is a script too.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    /// `go <url or search>`
+    Go(String),
+    Back,
+    Forward,
+    Reload,
+    HardReload,
+    Stop,
+    /// `new-tab [url]`, opening the homepage without a URL.
+    NewTab(Option<String>),
+    /// `close-tab [index]`, closing the active tab without an index.
+    CloseTab(Option<usize>),
+    /// `select-tab <index>`, counting from 0.
+    SelectTab(usize),
+    /// `bookmark`: toggles a bookmark on the active page.
+    ToggleBookmark,
+    /// `key <chord>`: a key press, as bound in the keymap.
+    Key(Chord),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Parses a navigation script.
+pub fn parse_script(text: &str) -> Result<Vec<ScriptCommand>, ScriptError> {
+    let mut commands = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| ScriptError {
+            line: i + 1,
+            message,
+        };
+        let (word, arg) = match line.split_once(char::is_whitespace) {
+            Some((word, arg)) => (word, Some(arg.trim())),
+            None => (line, None),
+        };
+        let index = |arg: Option<&str>| -> Result<usize, ScriptError> {
+            let arg = arg.ok_or_else(|| error(format!("'{}' needs a tab index", word)))?;
+            arg.parse()
+                .map_err(|_| error(format!("'{}' is not a tab index", arg)))
+        };
+        let no_arg = |command: ScriptCommand| match arg {
+            Some(arg) => Err(error(format!("unexpected argument '{}'", arg))),
+            None => Ok(command),
+        };
+        let command = match word {
+            "go" => ScriptCommand::Go(
+                arg.ok_or_else(|| error("'go' needs a URL".into()))?
+                    .to_string(),
+            ),
+            "back" => no_arg(ScriptCommand::Back)?,
+            "forward" => no_arg(ScriptCommand::Forward)?,
+            "reload" => no_arg(ScriptCommand::Reload)?,
+            "hard-reload" => no_arg(ScriptCommand::HardReload)?,
+            "stop" => no_arg(ScriptCommand::Stop)?,
+            "bookmark" => no_arg(ScriptCommand::ToggleBookmark)?,
+            "new-tab" => ScriptCommand::NewTab(arg.map(str::to_string)),
+            "close-tab" => ScriptCommand::CloseTab(arg.map(|a| index(Some(a))).transpose()?),
+            "select-tab" => ScriptCommand::SelectTab(index(arg)?),
+            "key" => ScriptCommand::Key(
+                arg.ok_or_else(|| error("'key' needs a key chord".into()))?
+                    .parse()
+                    .map_err(error)?,
+            ),
+            _ if arg.is_none() => ScriptCommand::Go(line.to_string()),
+            _ => return Err(error(format!("unknown command '{}'", word))),
+        };
+        commands.push(command);
+    }
+    Ok(commands)
+}
+
+/// What a page load reported back, in the order the GUI's WebView
+/// callbacks would.
+#[derive(Debug, Clone, PartialEq)]
+enum PageEvent {
+    Started(TabId, String),
+    Finished(TabId, String),
+    Title(TabId, String),
+    Failed(String),
+}
+
+type EventQueue = Rc<RefCell<VecDeque<PageEvent>>>;
+
+/// Loads a page without a network: `file://` URLs are read from disk,
+/// `about:` and `data:` pages are blank, and anything else stands in as an
+/// empty page. Returns the page title.
+pub fn load_offline(url: &str) -> Result<Option<String>, String> {
+    let parsed = Url::parse(url).map_err(|err| format!("{}: {}", url, err))?;
+    if parsed.scheme() != "file" {
+        return Ok(None);
+    }
+    let path = parsed
+        .to_file_path()
+        .map_err(|_| format!("{}: not a local path", url))?;
+    let html = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(page_title(&html))
+}
+
+/// Contents of the first `<title>` element, with whitespace collapsed.
+fn page_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = html[start..end]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+/// A content view for headless runs. Loads complete through
+/// [`load_offline`] and report back through the driver's event queue, the
+/// way WebView callbacks go through the event loop.
+struct HeadlessView {
+    tab: TabId,
+    current: RefCell<String>,
+    events: EventQueue,
+}
+
+impl HeadlessView {
+    fn new(tab: TabId, url: &str, events: EventQueue) -> Self {
+        let view = Self {
+            tab,
+            current: RefCell::new(String::new()),
+            events,
+        };
+        view.load_url(url);
+        view
+    }
+}
+
+impl PageView for HeadlessView {
+    fn load_url(&self, url: &str) {
+        *self.current.borrow_mut() = url.to_string();
+        let mut events = self.events.borrow_mut();
+        events.push_back(PageEvent::Started(self.tab, url.to_string()));
+        match load_offline(url) {
+            Ok(title) => {
+                events.push_back(PageEvent::Finished(self.tab, url.to_string()));
+                if let Some(title) = title {
+                    events.push_back(PageEvent::Title(self.tab, title));
+                }
+            }
+            Err(err) => {
+                events.push_back(PageEvent::Finished(self.tab, url.to_string()));
+                events.push_back(PageEvent::Failed(err));
+            }
+        }
+    }
+
+    fn evaluate_script(&self, _script: &str) {}
+
+    fn set_bounds(&self, _bounds: Rect) {}
+
+    fn set_visible(&self, _visible: bool) {}
+
+    fn reload(&self, _bypass_cache: bool) {
+        let url = self.current.borrow().clone();
+        self.load_url(&url);
+    }
+
+    fn zoom(&self, _factor: f64) {}
+}
+
+/// State of one tab at the end of a headless run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TabReport {
+    pub url: String,
+    pub title: Option<String>,
+    pub loading: bool,
+    pub can_go_back: bool,
+    pub can_go_forward: bool,
+    pub history: Vec<String>,
+    pub history_index: usize,
+}
+
+/// What a headless run prints as JSON.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    /// Tabs of the window the script ended in.
+    pub tabs: Vec<TabReport>,
+    pub active: usize,
+    pub windows: usize,
+    pub window_title: String,
+    /// Whether the script closed the last tab.
+    pub closed: bool,
+    pub bookmarks: Vec<String>,
+    /// Pages that failed to load.
+    pub errors: Vec<String>,
+}
+
+/// Runs a [`Browser`] without a window, applying script commands the way
+/// the GUI applies toolbar messages and key presses.
+pub struct Driver {
+    pub browser: Browser,
+    events: EventQueue,
+    closed: bool,
+    errors: Vec<String>,
+}
+
+impl Driver {
+    pub fn new(browser: Browser) -> Self {
+        let mut driver = Self {
+            browser,
+            events: EventQueue::default(),
+            closed: false,
+            errors: Vec::new(),
+        };
+        driver.apply(Effect::TabsChanged);
+        driver
+    }
+
+    /// Runs every command in order, stopping early once the last tab is
+    /// closed.
+    pub fn run_script(&mut self, commands: &[ScriptCommand]) {
+        for command in commands {
+            if self.closed {
+                break;
+            }
+            self.run(command);
+        }
+    }
+
+    pub fn run(&mut self, command: &ScriptCommand) {
+        let browser = &mut self.browser;
+        let effect = match command {
+            ScriptCommand::Go(url) => {
+                browser.navigate(url);
+                Effect::None
+            }
+            ScriptCommand::Back => {
+                browser.go_back();
+                Effect::None
+            }
+            ScriptCommand::Forward => {
+                browser.go_forward();
+                Effect::None
+            }
+            ScriptCommand::Reload => {
+                browser.reload(false);
+                Effect::None
+            }
+            ScriptCommand::HardReload => {
+                browser.reload(true);
+                Effect::None
+            }
+            ScriptCommand::Stop => {
+                browser.stop();
+                Effect::None
+            }
+            ScriptCommand::NewTab(url) => {
+                let url = url
+                    .as_deref()
+                    .and_then(|url| browser.url_fixup.fixup(url))
+                    .unwrap_or_else(|| browser.homepage());
+                browser.tab_command(TabCommand::Open(url))
+            }
+            ScriptCommand::CloseTab(Some(index)) => browser.tab_command(TabCommand::Close(*index)),
+            ScriptCommand::CloseTab(None) => browser.tab_command(TabCommand::CloseActive),
+            ScriptCommand::SelectTab(index) => browser.tab_command(TabCommand::Select(*index)),
+            ScriptCommand::ToggleBookmark => {
+                browser.toggle_bookmark();
+                Effect::None
+            }
+            ScriptCommand::Key(chord) => browser.handle_key(chord.clone()),
+        };
+        self.apply(effect);
+    }
+
+    fn apply(&mut self, effect: Effect) {
+        match effect {
+            Effect::TabsChanged => self.attach_views(),
+            Effect::WindowsChanged => {
+                for index in 0..self.browser.other_windows.len() {
+                    self.browser.focus_window(index);
+                    self.attach_views();
+                    self.browser.focus_window(index);
+                }
+                self.attach_views();
+            }
+            Effect::CloseWindow => {
+                self.browser.close_window();
+            }
+            Effect::Close => {
+                self.browser.shutdown();
+                self.closed = true;
+            }
+            Effect::None | Effect::ToggleSidebar => {}
+        }
+        self.drain_events();
+    }
+
+    /// Gives the current window's tabs that have no view a headless one.
+    fn attach_views(&mut self) {
+        let events = &self.events;
+        self.browser.tabs.fill_views(|tab| {
+            let url = tab.history.current_url();
+            Box::new(HeadlessView::new(tab.id(), &url, events.clone()))
+        });
+        self.browser.sync_tabs();
+    }
+
+    /// Shuts the browser down at the end of a run, as closing the window
+    /// would, unless the script already closed the last tab.
+    pub fn shutdown(&mut self) {
+        if !self.closed {
+            self.browser.shutdown();
+        }
+    }
+
+    /// Handles queued page events, each in the window of its tab, then
+    /// returns to the window the script is working in.
+    fn drain_events(&mut self) {
+        let current = self.browser.tabs.active().id();
+        loop {
+            let Some(event) = self.events.borrow_mut().pop_front() else {
+                break;
+            };
+            match event {
+                PageEvent::Started(tab, url) if self.browser.focus_tab(tab) => {
+                    self.browser.page_load_started(tab, url)
+                }
+                PageEvent::Finished(tab, url) if self.browser.focus_tab(tab) => {
+                    self.browser.page_load_finished(tab, url)
+                }
+                PageEvent::Title(tab, title) if self.browser.focus_tab(tab) => {
+                    self.browser.title_changed(tab, title)
+                }
+                PageEvent::Failed(err) => self.errors.push(err),
+                _ => {}
+            }
+        }
+        self.browser.focus_tab(current);
+    }
+
+    pub fn report(&self) -> Report {
+        let browser = &self.browser;
+        Report {
+            tabs: browser
+                .tabs
+                .iter()
+                .map(|tab| TabReport {
+                    url: tab.history.current_url(),
+                    title: tab.title.clone(),
+                    loading: tab.load_state.is_loading(),
+                    can_go_back: tab.history.can_go_back(),
+                    can_go_forward: tab.history.can_go_forward(),
+                    history: tab
+                        .history
+                        .entries()
+                        .into_iter()
+                        .map(|entry| entry.url)
+                        .collect(),
+                    history_index: tab.history.index(),
+                })
+                .collect(),
+            active: browser.tabs.active_index(),
+            windows: browser.window_count(),
+            window_title: browser.window_title(),
+            closed: self.closed,
+            bookmarks: browser
+                .bookmarks
+                .all()
+                .iter()
+                .map(|b| b.url.clone())
+                .collect(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{History, Profile, Session, TabCommand, Transition, VisitQuery};
+
+    #[test]
+    fn parses_scripts() {
+        let script = "\
+# visit a few pages
+example.com
+go rust borrow checker
+back
+
+new-tab file:///tmp/x.html
+new-tab
+close-tab 1
+close-tab
+select-tab 0
+key Ctrl+T
+bookmark
+";
+        assert_eq!(
+            parse_script(script).unwrap(),
+            [
+                ScriptCommand::Go("example.com".into()),
+                ScriptCommand::Go("rust borrow checker".into()),
+                ScriptCommand::Back,
+                ScriptCommand::NewTab(Some("file:///tmp/x.html".into())),
+                ScriptCommand::NewTab(None),
+                ScriptCommand::CloseTab(Some(1)),
+                ScriptCommand::CloseTab(None),
+                ScriptCommand::SelectTab(0),
+                ScriptCommand::Key("Ctrl+T".parse().unwrap()),
+                ScriptCommand::ToggleBookmark,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_script_errors_with_line_numbers() {
+        let err = parse_script("back\nselect-tab two").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.to_string(), "line 2: 'two' is not a tab index");
+        assert!(parse_script("back now").is_err());
+        assert!(parse_script("teleport somewhere").is_err());
+        assert!(parse_script("key Hyper+X").is_err());
+    }
+
+    #[test]
+    fn extracts_titles() {
+        assert_eq!(
+            page_title("<html><TITLE lang=en>\n  Hello\n  world </TITLE>").as_deref(),
+            Some("Hello world")
+        );
+        assert_eq!(page_title("<title></title>"), None);
+        assert_eq!(page_title("<p>no title</p>"), None);
+    }
+
+    #[test]
+    fn drives_navigation_offline() {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-headless-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let page = dir.join("page.html");
+        fs::write(&page, "<title>Local page</title>").unwrap();
+        let page_url = Url::from_file_path(&page).unwrap().to_string();
+
+        let browser = Browser::new(History::new("https://a.example/".into()), None);
+        let mut driver = Driver::new(browser);
+        let script = format!(
+            "b.example\n{}\nback\nnew-tab file:///nonexistent/wrybrowser.html\nselect-tab 0\n",
+            page.display()
+        );
+        driver.run_script(&parse_script(&script).unwrap());
+        let report = driver.report();
+
+        assert_eq!(report.active, 0);
+        assert_eq!(report.tabs.len(), 2);
+        let first = &report.tabs[0];
+        assert_eq!(
+            first.history,
+            [
+                "https://a.example/",
+                "https://b.example/",
+                page_url.as_str()
+            ]
+        );
+        assert_eq!(first.history_index, 1);
+        assert_eq!(first.url, "https://b.example/");
+        assert!(first.can_go_forward);
+        assert!(!first.loading);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].contains("nonexistent"));
+
+        driver.run_script(&parse_script("forward\nclose-tab 1\nclose-tab\nback").unwrap());
+        let report = driver.report();
+        assert_eq!(report.tabs[0].title.as_deref(), Some("Local page"));
+        assert_eq!(report.window_title, "Local page - wrybrowser");
+        assert!(report.closed);
+        assert_eq!(report.tabs[0].history_index, 2);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Hands the driver `events` in place of whatever its views reported.
+    fn replay(driver: &mut Driver, events: &[PageEvent]) {
+        let mut queue = driver.events.borrow_mut();
+        queue.clear();
+        queue.extend(events.iter().cloned());
+        drop(queue);
+        driver.drain_events();
+    }
+
+    fn history(driver: &Driver) -> (Vec<String>, usize) {
+        let tab = &driver.report().tabs[driver.browser.tabs.active_index()];
+        (tab.history.clone(), tab.history_index)
+    }
+
+    #[test]
+    fn commits_each_navigation_once() {
+        use PageEvent::{Finished, Started};
+        let s = String::from;
+
+        let browser = Browser::new(History::new("https://a.example/".into()), None);
+        let mut driver = Driver::new(browser);
+        let id = driver.browser.tabs.active().id();
+
+        // A typed URL redirected twice leaves one entry, for where it landed.
+        driver.browser.navigate("http://b.example/");
+        replay(
+            &mut driver,
+            &[
+                Started(id, s("http://b.example/")),
+                Started(id, s("https://b.example/")),
+                Finished(id, s("https://www.b.example/")),
+                // A late duplicate commits nothing.
+                Finished(id, s("https://www.b.example/")),
+            ],
+        );
+        let a = s("https://a.example/");
+        let b = s("https://www.b.example/");
+        assert_eq!(history(&driver), (vec![a.clone(), b.clone()], 1));
+        let visits = driver.browser.visits.search(&VisitQuery::default());
+        let hops: Vec<(&str, Transition)> = visits
+            .iter()
+            .take(3)
+            .map(|visit| (visit.url.as_str(), visit.transition))
+            .collect();
+        assert_eq!(
+            hops,
+            [
+                ("https://www.b.example/", Transition::Typed),
+                ("https://b.example/", Transition::Redirect),
+                ("http://b.example/", Transition::Redirect),
+            ]
+        );
+
+        // The page navigating by itself follows a link.
+        replay(
+            &mut driver,
+            &[
+                Started(id, s("https://c.example/")),
+                Finished(id, s("https://c.example/")),
+            ],
+        );
+        let c = s("https://c.example/");
+        assert_eq!(history(&driver), (vec![a.clone(), b.clone(), c.clone()], 2));
+        let entry = driver.browser.history().current();
+        assert_eq!(entry.transition, Transition::Link);
+
+        // Going back reloads an entry without pushing it again, even when
+        // the page redirects elsewhere this time.
+        driver.browser.go_back();
+        replay(
+            &mut driver,
+            &[
+                Started(id, b.clone()),
+                Finished(id, s("https://www.b.example/home")),
+            ],
+        );
+        let home = s("https://www.b.example/home");
+        assert_eq!(history(&driver), (vec![a.clone(), home.clone(), c], 1));
+        assert!(driver.browser.history().can_go_forward());
+
+        driver.browser.reload(false);
+        replay(
+            &mut driver,
+            &[Started(id, home.clone()), Finished(id, home.clone())],
+        );
+        assert_eq!(history(&driver).1, 1);
+        assert_eq!(driver.browser.history().entries().len(), 3);
+
+        // A stopped load never commits.
+        driver.browser.navigate("d.example");
+        replay(&mut driver, &[Started(id, s("https://d.example/"))]);
+        driver.browser.stop();
+        replay(&mut driver, &[Finished(id, s("https://d.example/"))]);
+        assert_eq!(history(&driver).0[1], home);
+
+        // A new tab whose first page redirects keeps a single entry.
+        driver
+            .browser
+            .tab_command(TabCommand::Open(s("https://e.example/")));
+        driver.attach_views();
+        let tab = driver.browser.tabs.active().id();
+        replay(
+            &mut driver,
+            &[
+                Started(tab, s("https://e.example/")),
+                Finished(tab, s("https://e.example/welcome")),
+            ],
+        );
+        assert_eq!(history(&driver), (vec![s("https://e.example/welcome")], 0));
+    }
+
+    #[test]
+    fn shuts_down_in_order() {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-shutdown-{}", std::process::id()));
+        let profile = Profile::open(&dir).unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let mut browser = Browser::new(
+            History::new("https://a.example/".into()),
+            Some(profile.clone()),
+        );
+        for name in ["first", "second"] {
+            let log = log.clone();
+            browser.on_shutdown(move |browser| {
+                let views = browser.tabs.iter().filter(|tab| tab.view.is_some()).count();
+                log.borrow_mut()
+                    .push(format!("{} hook, {} views", name, views));
+            });
+        }
+        let mut driver = Driver::new(browser);
+        driver.run_script(
+            &parse_script(
+                "b.example
+new-tab c.example
+close-tab
+close-tab
+back",
+            )
+            .unwrap(),
+        );
+
+        assert!(driver.report().closed);
+        assert_eq!(
+            *log.borrow(),
+            ["first hook, 1 views", "second hook, 1 views"]
+        );
+        assert!(driver.browser.tabs.iter().all(|tab| tab.view.is_none()));
+        let session = Session::load(&profile.session_path()).unwrap();
+        assert!(session.clean_exit);
+        assert_eq!(
+            session.windows[0].tabs[0].history.current_url(),
+            "https://b.example/"
+        );
+        let history = History::load(&profile.history_path()).unwrap();
+        assert_eq!(history.current_url(), "https://b.example/");
+
+        // Hooks run once, and a run that never closed the window shuts down
+        // at the end.
+        driver.shutdown();
+        assert_eq!(log.borrow().len(), 2);
+        let mut browser = Browser::new(
+            History::new("https://d.example/".into()),
+            Some(profile.clone()),
+        );
+        let hook_log = log.clone();
+        browser.on_shutdown(move |_| hook_log.borrow_mut().push("third hook".into()));
+        let mut driver = Driver::new(browser);
+        driver.shutdown();
+        assert_eq!(log.borrow().last().map(String::as_str), Some("third hook"));
+        assert!(!driver.report().closed);
+        let session = Session::load(&profile.session_path()).unwrap();
+        assert_eq!(
+            session.windows[0].tabs[0].history.current_url(),
+            "https://d.example/"
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,1281 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::persist::{self, PersistError};
+use crate::time::{now, Timestamp};
+
+/// On-disk format version of the history file.
+pub const HISTORY_FORMAT_VERSION: u32 = 3;
+
+/// Entries a tab's history keeps unless told otherwise, as in most
+/// browsers.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 50;
+
+fn default_capacity() -> usize {
+    DEFAULT_HISTORY_CAPACITY
+}
+
+/// Identifies one entry of a [`History`]. Only meaningful for the history
+/// that handed it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// How the browser last arrived at a history entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transition {
+    /// Entered in the address bar or picked from a bookmark.
+    Typed,
+    /// Followed from the page itself.
+    Link,
+    BackForward,
+    Reload,
+    /// The server or the page sent the load elsewhere.
+    Redirect,
+}
+
+/// Scroll offset of a page, in CSS pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ScrollPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// One page in a tab's history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: Option<String>,
+    /// Zero for entries carried over from formats without timestamps.
+    pub first_visit: Timestamp,
+    pub last_visit: Timestamp,
+    pub visit_count: u32,
+    pub transition: Transition,
+    /// Where the page was scrolled to when last seen, restored on going
+    /// back or forward to it.
+    pub scroll: ScrollPosition,
+}
+
+impl HistoryEntry {
+    pub fn new(url: String, transition: Transition) -> Self {
+        let now = now();
+        Self {
+            url,
+            title: None,
+            first_visit: now,
+            last_visit: now,
+            visit_count: 1,
+            transition,
+            scroll: ScrollPosition::default(),
+        }
+    }
+
+    fn visit(&mut self, transition: Transition) {
+        self.last_visit = now();
+        self.visit_count += 1;
+        self.transition = transition;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Node {
+    entry: HistoryEntry,
+    parent: Option<usize>,
+    /// The child `forward()` goes to: the most recently visited branch.
+    active_child: Option<usize>,
+    /// The page load this entry's document came from, shared by entries
+    /// visited within that document. Only meaningful while the view that
+    /// loaded it lives, so not saved.
+    #[serde(skip)]
+    document: Option<u64>,
+}
+
+/// An id no other page load has had.
+fn next_document() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The history tree. Nodes are stored in creation order, so a parent always
+/// comes before its children. In linear mode the tree is a single chain and
+/// navigating away from the middle drops everything after it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Tree {
+    branching: bool,
+    /// Most entries the current branch holds; older ones are evicted.
+    #[serde(default = "default_capacity")]
+    capacity: usize,
+    current: usize,
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new(entry: HistoryEntry, branching: bool) -> Self {
+        Self {
+            branching,
+            capacity: DEFAULT_HISTORY_CAPACITY,
+            current: 0,
+            nodes: vec![Node {
+                entry,
+                parent: None,
+                active_child: None,
+                document: None,
+            }],
+        }
+    }
+
+    /// Adds an entry for `url` after the current one, returning false if
+    /// `url` is the current one already.
+    fn push(&mut self, url: String, transition: Transition) -> bool {
+        if self.nodes[self.current].entry.url == url {
+            return false;
+        }
+        self.append(url, transition);
+        true
+    }
+
+    /// Adds an entry for `url` after the current one, even if `url` is the
+    /// current one already.
+    fn append(&mut self, url: String, transition: Transition) {
+        if !self.branching {
+            self.nodes.truncate(self.current + 1);
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            entry: HistoryEntry::new(url, transition),
+            parent: Some(self.current),
+            active_child: None,
+            document: None,
+        });
+        self.nodes[self.current].active_child = Some(id);
+        self.current = id;
+        self.evict();
+    }
+
+    /// Shrinks the current branch to the capacity, dropping its oldest
+    /// entries, then if need be those furthest forward, but never the
+    /// current one. Branches off dropped entries go with them. If the
+    /// other branches still take the tree over the capacity, drops their
+    /// oldest tips until it fits. Renumbers the remaining nodes, keeping
+    /// their order.
+    fn evict(&mut self) {
+        if self.nodes.len() <= self.capacity {
+            return;
+        }
+        let path = self.path();
+        let mut keep = vec![true; self.nodes.len()];
+        if path.len() > self.capacity {
+            let start = (path.len() - self.capacity).min(self.depth());
+            let root = path[start];
+            let cut = path.get(start + self.capacity).copied();
+            keep[..root].fill(false);
+            for id in root + 1..self.nodes.len() {
+                keep[id] = Some(id) != cut && self.nodes[id].parent.is_some_and(|p| keep[p]);
+            }
+        }
+        let mut on_path = vec![false; self.nodes.len()];
+        for &id in &path {
+            on_path[id] = true;
+        }
+        let mut children = vec![0usize; self.nodes.len()];
+        for (id, node) in self.nodes.iter().enumerate() {
+            if let Some(parent) = node.parent.filter(|_| keep[id]) {
+                children[parent] += 1;
+            }
+        }
+        let mut excess = keep
+            .iter()
+            .filter(|&&kept| kept)
+            .count()
+            .saturating_sub(self.capacity);
+        while excess > 0 {
+            // The current branch fits, so some other branch has a tip left.
+            let tip = (0..self.nodes.len())
+                .find(|&id| keep[id] && !on_path[id] && children[id] == 0)
+                .expect("a branch off the current one");
+            keep[tip] = false;
+            if let Some(parent) = self.nodes[tip].parent {
+                children[parent] -= 1;
+            }
+            excess -= 1;
+        }
+        let mut new_ids = vec![None; self.nodes.len()];
+        let kept = (0..self.nodes.len()).filter(|&id| keep[id]);
+        for (new_id, id) in kept.enumerate() {
+            new_ids[id] = Some(new_id);
+        }
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .enumerate()
+            .filter(|&(id, _)| keep[id])
+            .map(|(_, mut node)| {
+                node.parent = node.parent.and_then(|id| new_ids[id]);
+                node.active_child = node.active_child.and_then(|id| new_ids[id]);
+                node
+            })
+            .collect();
+        self.current = new_ids[self.current].unwrap_or(0);
+    }
+
+    fn current_node(&mut self) -> &mut Node {
+        &mut self.nodes[self.current]
+    }
+
+    fn push_same_document(&mut self, url: String) {
+        let document = self.nodes[self.current].document;
+        self.append(url, Transition::Link);
+        self.current_node().document = document;
+    }
+
+    fn same_document(&self, delta: isize) -> bool {
+        let document = self.nodes[self.current].document;
+        self.offset(delta)
+            .is_some_and(|id| document.is_some() && self.nodes[id].document == document)
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    fn current_entry(&mut self) -> &mut HistoryEntry {
+        &mut self.nodes[self.current].entry
+    }
+
+    fn entry(&self) -> &HistoryEntry {
+        &self.nodes[self.current].entry
+    }
+
+    /// Moves to `id`, counting it as a back/forward visit.
+    fn go_to(&mut self, id: usize) -> HistoryEntry {
+        self.current = id;
+        let entry = self.current_entry();
+        entry.visit(Transition::BackForward);
+        entry.clone()
+    }
+
+    fn back(&mut self) -> Option<HistoryEntry> {
+        let parent = self.nodes[self.current].parent?;
+        Some(self.go_to(parent))
+    }
+
+    fn forward(&mut self) -> Option<HistoryEntry> {
+        let child = self.nodes[self.current].active_child?;
+        Some(self.go_to(child))
+    }
+
+    /// Moves `delta` entries back (negative) or forward along the current
+    /// branch, counting it as a back/forward visit.
+    fn go(&mut self, delta: isize) -> Option<HistoryEntry> {
+        let target = self.offset(delta)?;
+        Some(self.go_to(target))
+    }
+
+    /// The entry `delta` steps from the current one along the current
+    /// branch, if it is another one.
+    fn offset(&self, delta: isize) -> Option<usize> {
+        let path = self.path();
+        let target = self
+            .depth()
+            .checked_add_signed(delta)
+            .filter(|&target| delta != 0 && target < path.len())?;
+        Some(path[target])
+    }
+
+    fn children(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .skip(id + 1)
+            .filter(move |(_, node)| node.parent == Some(id))
+            .map(|(i, _)| i)
+    }
+
+    /// The root-to-tip path through the current entry, following the
+    /// active branch past it.
+    fn path(&self) -> Vec<usize> {
+        let mut path = vec![self.current];
+        while let Some(parent) = self.nodes[path[path.len() - 1]].parent {
+            path.push(parent);
+        }
+        path.reverse();
+        let mut tip = self.current;
+        while let Some(child) = self.nodes[tip].active_child {
+            path.push(child);
+            tip = child;
+        }
+        path
+    }
+
+    fn entries(&self) -> Vec<HistoryEntry> {
+        self.path()
+            .into_iter()
+            .map(|id| self.nodes[id].entry.clone())
+            .collect()
+    }
+
+    fn can_go_back(&self) -> bool {
+        self.nodes[self.current].parent.is_some()
+    }
+
+    fn can_go_forward(&self) -> bool {
+        self.nodes[self.current].active_child.is_some()
+    }
+
+    fn branches(&self) -> Vec<(NodeId, HistoryEntry)> {
+        self.children(self.current)
+            .map(|id| (NodeId(id), self.nodes[id].entry.clone()))
+            .collect()
+    }
+
+    fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut id = self.current;
+        while let Some(parent) = self.nodes[id].parent {
+            depth += 1;
+            id = parent;
+        }
+        depth
+    }
+
+    /// Makes `id` the current entry and the branches leading to it active.
+    fn jump_to(&mut self, id: usize) -> Option<HistoryEntry> {
+        self.nodes.get(id)?;
+        let mut child = id;
+        while let Some(parent) = self.nodes[child].parent {
+            self.nodes[parent].active_child = Some(child);
+            child = parent;
+        }
+        let entry = self.go_to(id);
+        // The branch jumped to may be longer than the one left.
+        self.evict();
+        Some(entry)
+    }
+
+    /// Checks the invariants `load` relies on, returning what is wrong.
+    fn validate(&self) -> Result<(), String> {
+        if self.capacity == 0 {
+            return Err("capacity is zero".into());
+        }
+        if self.current >= self.nodes.len() {
+            return Err(format!(
+                "index {} out of range for {} entries",
+                self.current,
+                self.nodes.len()
+            ));
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            match node.parent {
+                None if i == 0 => {}
+                Some(parent) if parent < i => {}
+                _ => return Err(format!("entry {} has an invalid parent", i)),
+            }
+            if let Some(child) = node.active_child {
+                if self.nodes.get(child).and_then(|c| c.parent) != Some(i) {
+                    return Err(format!("entry {} has an invalid active branch", i));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a [`History`] guards its state: [`RefCellLock`] for a history used
+/// on one thread, [`MutexLock`] for one shared between threads.
+pub trait HistoryLock {
+    type Cell<T>;
+
+    fn new<T>(value: T) -> Self::Cell<T>;
+    fn read<T, R>(cell: &Self::Cell<T>, f: impl FnOnce(&T) -> R) -> R;
+    fn write<T, R>(cell: &Self::Cell<T>, f: impl FnOnce(&mut T) -> R) -> R;
+    fn into_inner<T>(cell: Self::Cell<T>) -> T;
+}
+
+/// Keeps a history in a [`RefCell`]: cheap, but for one thread only.
+#[derive(Debug)]
+pub struct RefCellLock;
+
+impl HistoryLock for RefCellLock {
+    type Cell<T> = RefCell<T>;
+
+    fn new<T>(value: T) -> RefCell<T> {
+        RefCell::new(value)
+    }
+
+    fn read<T, R>(cell: &RefCell<T>, f: impl FnOnce(&T) -> R) -> R {
+        f(&cell.borrow())
+    }
+
+    fn write<T, R>(cell: &RefCell<T>, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut cell.borrow_mut())
+    }
+
+    fn into_inner<T>(cell: RefCell<T>) -> T {
+        cell.into_inner()
+    }
+}
+
+/// Keeps a history in a [`Mutex`], so it is `Send + Sync`.
+#[derive(Debug)]
+pub struct MutexLock;
+
+impl HistoryLock for MutexLock {
+    type Cell<T> = Mutex<T>;
+
+    fn new<T>(value: T) -> Mutex<T> {
+        Mutex::new(value)
+    }
+
+    // No operation can panic halfway through changing the tree, so a
+    // poisoned lock still guards a consistent one.
+    fn read<T, R>(cell: &Mutex<T>, f: impl FnOnce(&T) -> R) -> R {
+        f(&cell.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn write<T, R>(cell: &Mutex<T>, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut cell.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn into_inner<T>(cell: Mutex<T>) -> T {
+        cell.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Back/forward history of one tab.
+///
+/// By default the history is linear like in every browser: navigating from
+/// the middle discards the entries after it. A history made with
+/// [`History::branching`] keeps them as a sibling branch instead, which
+/// [`History::branches`] and [`History::jump_to`] can get back to; `back()`
+/// and `forward()` then follow the most recently visited branch.
+///
+/// Serializes as the whole tree, so it can be embedded in other files such
+/// as session snapshots.
+pub struct History<L: HistoryLock = RefCellLock> {
+    tree: L::Cell<Tree>,
+}
+
+/// A [`History`] that can be shared between threads, such as a WebView
+/// handler that wry runs off the main thread, or a thread saving it in the
+/// background. Each operation holds a lock on the history while it runs.
+/// Made from a [`History`] with `into()`.
+pub type SharedHistory = History<MutexLock>;
+
+impl<L: HistoryLock> fmt::Debug for History<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read(|tree| f.debug_struct("History").field("tree", tree).finish())
+    }
+}
+
+impl<L: HistoryLock> Clone for History<L> {
+    fn clone(&self) -> Self {
+        Self::from_tree(self.read(Tree::clone))
+    }
+}
+
+impl<L: HistoryLock> PartialEq for History<L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.read(|tree| other.read(|other| tree == other))
+    }
+}
+
+impl<L: HistoryLock> Serialize for History<L> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.read(|tree| tree.serialize(serializer))
+    }
+}
+
+impl<'de, L: HistoryLock> Deserialize<'de> for History<L> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tree = Tree::deserialize(deserializer)?;
+        tree.validate().map_err(de::Error::custom)?;
+        Ok(Self::from_tree(tree))
+    }
+}
+
+impl From<History> for SharedHistory {
+    fn from(history: History) -> Self {
+        Self::from_tree(RefCellLock::into_inner(history.tree))
+    }
+}
+
+/// Format version 1: a linear list of URLs.
+#[derive(Deserialize)]
+struct HistoryFileV1 {
+    index: usize,
+    entries: Vec<String>,
+}
+
+/// Format version 2: the tree, with bare URLs for entries.
+#[derive(Deserialize)]
+struct TreeV2 {
+    branching: bool,
+    current: usize,
+    nodes: Vec<NodeV2>,
+}
+
+#[derive(Deserialize)]
+struct NodeV2 {
+    url: String,
+    parent: Option<usize>,
+    active_child: Option<usize>,
+}
+
+impl From<HistoryFileV1> for TreeV2 {
+    fn from(file: HistoryFileV1) -> Self {
+        let count = file.entries.len();
+        let nodes = file
+            .entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, url)| NodeV2 {
+                url,
+                parent: i.checked_sub(1),
+                active_child: (i + 1 < count).then_some(i + 1),
+            })
+            .collect();
+        Self {
+            branching: false,
+            current: file.index,
+            nodes,
+        }
+    }
+}
+
+impl From<TreeV2> for Tree {
+    fn from(tree: TreeV2) -> Self {
+        let nodes = tree
+            .nodes
+            .into_iter()
+            .map(|node| Node {
+                entry: HistoryEntry {
+                    first_visit: 0,
+                    last_visit: 0,
+                    ..HistoryEntry::new(node.url, Transition::Link)
+                },
+                parent: node.parent,
+                active_child: node.active_child,
+                document: None,
+            })
+            .collect();
+        Self {
+            branching: tree.branching,
+            capacity: DEFAULT_HISTORY_CAPACITY,
+            current: tree.current,
+            nodes,
+        }
+    }
+}
+
+impl History {
+    pub fn new(initial: String) -> Self {
+        Self::with_root(initial, false)
+    }
+
+    /// A history that keeps forward branches instead of discarding them.
+    pub fn branching(initial: String) -> Self {
+        Self::with_root(initial, true)
+    }
+
+    fn with_root(initial: String, branching: bool) -> Self {
+        let entry = HistoryEntry::new(initial, Transition::Typed);
+        Self::from_tree(Tree::new(entry, branching))
+    }
+
+    /// Reads a history previously written by [`History::save`], including
+    /// the formats of earlier versions.
+    pub fn load(path: &Path) -> Result<Self, PersistError> {
+        let tree = match persist::load::<Tree>(path, HISTORY_FORMAT_VERSION) {
+            Err(PersistError::UnsupportedVersion { found: 2, .. }) => {
+                persist::load::<TreeV2>(path, 2)?.into()
+            }
+            Err(PersistError::UnsupportedVersion { found: 1, .. }) => {
+                TreeV2::from(persist::load::<HistoryFileV1>(path, 1)?).into()
+            }
+            result => result?,
+        };
+        tree.validate().map_err(|reason| PersistError::Corrupt {
+            path: PathBuf::from(path),
+            reason,
+        })?;
+        Ok(Self::from_tree(tree))
+    }
+}
+
+impl<L: HistoryLock> History<L> {
+    fn from_tree(tree: Tree) -> Self {
+        Self { tree: L::new(tree) }
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&Tree) -> R) -> R {
+        L::read(&self.tree, f)
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut Tree) -> R) -> R {
+        L::write(&self.tree, f)
+    }
+
+    pub fn is_branching(&self) -> bool {
+        self.read(|tree| tree.branching)
+    }
+
+    /// Records a link followed to `url`.
+    pub fn push(&self, url: String) {
+        self.visit(url, Transition::Link);
+    }
+
+    /// Records a navigation to `url`. Navigating to the current URL again
+    /// changes nothing.
+    pub fn visit(&self, url: String, transition: Transition) {
+        self.write(|tree| tree.push(url, transition));
+    }
+
+    /// Records a navigation within the current page to `url`, as
+    /// `history.pushState` or following a link to an anchor make. The new
+    /// entry shares the page's document. Unlike [`visit`], this adds an
+    /// entry even for the current URL, as `history.pushState` does.
+    ///
+    /// [`visit`]: History::visit
+    pub fn visit_same_document(&self, url: String) {
+        self.write(|tree| tree.push_same_document(url));
+    }
+
+    /// The current entry's page was loaded anew, as a document of its own.
+    pub fn loaded_document(&self) {
+        self.write(|tree| tree.current_node().document = Some(next_document()));
+    }
+
+    /// Whether the entry `delta` steps away shows the same document as the
+    /// current one, so the page itself can go there without loading.
+    pub fn same_document(&self, delta: isize) -> bool {
+        self.read(|tree| tree.same_document(delta))
+    }
+
+    /// Points the current entry at `url`, e.g. because the page it shows
+    /// was redirected there. Keeps the entry's title, visits and scroll.
+    pub fn replace_current(&self, url: String) {
+        self.write(|tree| tree.current_entry().url = url);
+    }
+
+    /// Counts a reload of the current entry.
+    pub fn reloaded(&self) {
+        self.write(|tree| tree.current_entry().visit(Transition::Reload));
+    }
+
+    pub fn set_title(&self, title: String) {
+        self.write(|tree| tree.current_entry().title = Some(title));
+    }
+
+    pub fn set_scroll(&self, scroll: ScrollPosition) {
+        self.write(|tree| tree.current_entry().scroll = scroll);
+    }
+
+    pub fn current(&self) -> HistoryEntry {
+        self.read(|tree| tree.entry().clone())
+    }
+
+    pub fn current_url(&self) -> String {
+        self.read(|tree| tree.entry().url.clone())
+    }
+
+    pub fn back(&self) -> Option<HistoryEntry> {
+        self.write(Tree::back)
+    }
+
+    pub fn forward(&self) -> Option<HistoryEntry> {
+        self.write(Tree::forward)
+    }
+
+    /// Goes `delta` entries back (negative) or forward at once. Returns the
+    /// entry gone to, or `None`, moving nowhere, if there is no entry that
+    /// far or `delta` is zero.
+    pub fn go(&self, delta: isize) -> Option<HistoryEntry> {
+        self.write(|tree| tree.go(delta))
+    }
+
+    /// Number of entries on the current branch, as in [`History::entries`].
+    pub fn len(&self) -> usize {
+        self.read(|tree| tree.path().len())
+    }
+
+    /// Always false: a history has at least its first entry.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Most entries [`History::entries`] holds; see
+    /// [`History::set_capacity`].
+    pub fn capacity(&self) -> usize {
+        self.read(|tree| tree.capacity)
+    }
+
+    /// Keeps at most `capacity` entries (at least one), evicting the oldest
+    /// when a new one would not fit. Shrinking evicts the oldest entries
+    /// before the current one, then those furthest forward. Ids from
+    /// [`History::current_id`] and [`History::branches`] taken before an
+    /// eviction no longer apply.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.write(|tree| tree.set_capacity(capacity));
+    }
+
+    /// Every entry on the current branch, oldest first.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.read(Tree::entries)
+    }
+
+    /// Position of the current entry in [`History::entries`].
+    pub fn index(&self) -> usize {
+        self.read(Tree::depth)
+    }
+
+    pub fn current_id(&self) -> NodeId {
+        self.read(|tree| NodeId(tree.current))
+    }
+
+    /// The entries reachable by going forward from the current one, oldest
+    /// branch first. A linear history has at most one.
+    pub fn branches(&self) -> Vec<(NodeId, HistoryEntry)> {
+        self.read(Tree::branches)
+    }
+
+    /// Makes `id` the current entry, on whatever branch it is. Returns the
+    /// entry, or `None` if this history has no such entry.
+    pub fn jump_to(&self, id: NodeId) -> Option<HistoryEntry> {
+        self.write(|tree| tree.jump_to(id.0))
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.read(Tree::can_go_back)
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.read(Tree::can_go_forward)
+    }
+
+    /// A copy of the history as it is now, e.g. to save or to show.
+    pub fn snapshot(&self) -> History {
+        History::from_tree(self.read(Tree::clone))
+    }
+
+    /// Writes the history atomically to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), PersistError> {
+        persist::save(path, HISTORY_FORMAT_VERSION, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn urls(entries: Vec<HistoryEntry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.url).collect()
+    }
+
+    fn url(entry: Option<HistoryEntry>) -> Option<String> {
+        entry.map(|entry| entry.url)
+    }
+
+    #[test]
+    fn history_navigation() {
+        let history = History::new("a".into());
+        history.push("b".into());
+        history.push("c".into());
+
+        assert_eq!(history.current_url(), "c");
+        assert!(history.can_go_back());
+        assert!(!history.can_go_forward());
+
+        assert_eq!(url(history.back()), Some("b".into()));
+        assert_eq!(history.current_url(), "b");
+        assert_eq!(url(history.back()), Some("a".into()));
+        assert_eq!(history.back(), None);
+        assert_eq!(history.current_url(), "a");
+        assert!(!history.can_go_back());
+        assert!(history.can_go_forward());
+
+        assert_eq!(url(history.forward()), Some("b".into()));
+        assert_eq!(url(history.forward()), Some("c".into()));
+        assert_eq!(history.forward(), None);
+        assert_eq!(history.current_url(), "c");
+    }
+
+    #[test]
+    fn goes_several_steps_at_once() {
+        let history = History::new("a".into());
+        for page in ["b", "c", "d"] {
+            history.push(page.into());
+        }
+        assert_eq!((history.len(), history.index()), (4, 3));
+
+        let b = history.go(-2).unwrap();
+        assert_eq!(
+            (b.url.as_str(), b.transition),
+            ("b", Transition::BackForward)
+        );
+        assert_eq!(history.index(), 1);
+        assert_eq!(history.len(), 4);
+        for delta in [0, -2, 3, isize::MIN, isize::MAX] {
+            assert_eq!(history.go(delta), None, "{}", delta);
+        }
+        assert_eq!(history.index(), 1);
+        assert_eq!(url(history.go(2)), Some("d".into()));
+        assert_eq!(url(history.go(-3)), Some("a".into()));
+
+        // A branching history goes along the branch `forward` follows.
+        let history = History::branching("a".into());
+        history.push("b".into());
+        history.push("c".into());
+        history.go(-2);
+        history.push("x".into());
+        history.push("y".into());
+        history.go(-2);
+        assert_eq!(url(history.go(2)), Some("y".into()));
+        assert_eq!(urls(history.entries()), ["a", "x", "y"]);
+        assert_eq!(history.len(), 3);
+    }
+
+    #[test]
+    fn tracks_entries_sharing_a_document() {
+        let history = History::new("a".into());
+        history.loaded_document();
+        history.visit_same_document("a#1".into());
+        history.visit_same_document("a/route".into());
+        assert!(history.same_document(-2));
+        assert!(!history.same_document(0));
+        assert!(!history.same_document(-3));
+
+        // Loading a page starts a new document, even for a former entry.
+        history.push("b".into());
+        assert!(!history.same_document(-1));
+        history.loaded_document();
+        history.visit_same_document("b#top".into());
+        assert!(history.same_document(-1));
+        history.go(-2);
+        history.loaded_document();
+        assert!(!history.same_document(-1));
+        assert!(!history.same_document(1));
+
+        // A restored history has no live documents.
+        let restored: History =
+            serde_json::from_str(&serde_json::to_string(&history).unwrap()).unwrap();
+        assert_eq!(urls(restored.entries()), urls(history.entries()));
+        restored.go(-1);
+        assert!(!restored.same_document(-1));
+    }
+
+    #[test]
+    fn entries_record_visits() {
+        let history = History::new("a".into());
+        history.visit("b".into(), Transition::Typed);
+        history.set_title("Page B".into());
+        history.set_scroll(ScrollPosition { x: 0.0, y: 300.0 });
+        history.push("c".into());
+
+        let b = history.back().unwrap();
+        assert_eq!(b.title.as_deref(), Some("Page B"));
+        assert_eq!(b.scroll, ScrollPosition { x: 0.0, y: 300.0 });
+        assert_eq!(b.transition, Transition::BackForward);
+        assert_eq!(b.visit_count, 2);
+        assert!(b.last_visit >= b.first_visit);
+
+        history.reloaded();
+        let b = history.current();
+        assert_eq!(b.transition, Transition::Reload);
+        assert_eq!(b.visit_count, 3);
+
+        // Visiting the current URL again is not a new entry.
+        history.visit("b".into(), Transition::Typed);
+        assert_eq!(history.entries().len(), 3);
+        assert_eq!(history.current().visit_count, 3);
+
+        let c = history.forward().unwrap();
+        assert_eq!((c.title, c.scroll), (None, ScrollPosition::default()));
+
+        history.replace_current("c2".into());
+        assert_eq!(urls(history.entries()), ["a", "b", "c2"]);
+        assert_eq!(history.current().visit_count, 2);
+    }
+
+    #[test]
+    fn linear_history_drops_the_forward_branch() {
+        let history = History::new("a".into());
+        history.push("b".into());
+        history.push("c".into());
+        history.back();
+        history.push("d".into());
+
+        assert_eq!(urls(history.entries()), ["a", "b", "d"]);
+        assert_eq!(history.forward(), None);
+        history.back();
+        assert_eq!(history.branches().len(), 1);
+        assert_eq!(url(history.forward()), Some("d".into()));
+    }
+
+    #[test]
+    fn branching_history_keeps_forward_branches() {
+        let history = History::branching("a".into());
+        history.push("b".into());
+        history.push("c".into());
+        history.back();
+        let b = history.current_id();
+        history.push("d".into());
+
+        // back/forward follow the newest branch, like a linear history.
+        assert_eq!(urls(history.entries()), ["a", "b", "d"]);
+        assert_eq!(history.index(), 2);
+        assert_eq!(url(history.back()), Some("b".into()));
+        assert_eq!(url(history.forward()), Some("d".into()));
+        assert_eq!(history.forward(), None);
+
+        history.jump_to(b);
+        let branches = history.branches();
+        let branch_urls: Vec<&str> = branches
+            .iter()
+            .map(|(_, entry)| entry.url.as_str())
+            .collect();
+        assert_eq!(branch_urls, ["c", "d"]);
+
+        // Jumping to the old branch makes it the one forward() follows.
+        assert_eq!(url(history.jump_to(branches[0].0)), Some("c".into()));
+        assert_eq!(urls(history.entries()), ["a", "b", "c"]);
+        assert_eq!(url(history.back()), Some("b".into()));
+        assert_eq!(url(history.forward()), Some("c".into()));
+
+        assert_eq!(history.jump_to(NodeId(99)), None);
+        assert_eq!(history.current_url(), "c");
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-history-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn history_save_and_load() {
+        let path = temp_path("history.json");
+        let history = History::new("a".into());
+        history.push("b".into());
+        history.set_title("B".into());
+        history.push("c".into());
+        history.back();
+        history.save(&path).unwrap();
+
+        let loaded = History::load(&path).unwrap();
+        assert_eq!(loaded.current(), history.current());
+        assert_eq!(url(loaded.forward()), Some("c".into()));
+        assert_eq!(url(loaded.back()), Some("b".into()));
+        assert_eq!(url(loaded.back()), Some("a".into()));
+    }
+
+    #[test]
+    fn branches_survive_save_and_load() {
+        let path = temp_path("branching.json");
+        let history = History::branching("a".into());
+        history.push("b".into());
+        history.back();
+        history.push("c".into());
+        history.save(&path).unwrap();
+
+        let loaded = History::load(&path).unwrap();
+        assert!(loaded.is_branching());
+        assert_eq!(urls(loaded.entries()), ["a", "c"]);
+        loaded.back();
+        assert_eq!(loaded.branches().len(), 2);
+    }
+
+    #[test]
+    fn loads_older_formats() {
+        let path = temp_path("v1.json");
+        fs::write(
+            &path,
+            r#"{"version":1,"data":{"index":1,"entries":["a","b","c"]}}"#,
+        )
+        .unwrap();
+        let loaded = History::load(&path).unwrap();
+        assert!(!loaded.is_branching());
+        assert_eq!(urls(loaded.entries()), ["a", "b", "c"]);
+        let current = loaded.current();
+        assert_eq!(current.url, "b");
+        assert_eq!((current.visit_count, current.first_visit), (1, 0));
+
+        let path = temp_path("v2.json");
+        fs::write(
+            &path,
+            r#"{"version":2,"data":{"branching":true,"current":1,"nodes":[
+                {"url":"a","parent":null,"active_child":1},
+                {"url":"b","parent":0,"active_child":null}]}}"#,
+        )
+        .unwrap();
+        let loaded = History::load(&path).unwrap();
+        assert!(loaded.is_branching());
+        assert_eq!(urls(loaded.entries()), ["a", "b"]);
+        assert_eq!(loaded.index(), 1);
+    }
+
+    #[test]
+    fn history_load_rejects_bad_files() {
+        let path = temp_path("bad-index.json");
+        fs::write(&path, r#"{"version":1,"data":{"index":5,"entries":["a"]}}"#).unwrap();
+        assert!(matches!(
+            History::load(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+
+        let path = temp_path("empty.json");
+        fs::write(&path, r#"{"version":1,"data":{"index":0,"entries":[]}}"#).unwrap();
+        assert!(matches!(
+            History::load(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+
+        let path = temp_path("bad-parent.json");
+        fs::write(
+            &path,
+            r#"{"version":2,"data":{"branching":true,"current":0,"nodes":[
+                {"url":"a","parent":null,"active_child":null},
+                {"url":"b","parent":1,"active_child":null}]}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            History::load(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+
+        let path = temp_path("old-version.json");
+        fs::write(&path, r#"{"version":0,"data":["a"]}"#).unwrap();
+        assert!(matches!(
+            History::load(&path),
+            Err(PersistError::UnsupportedVersion { found: 0, .. })
+        ));
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shared_history_matches_history() {
+        assert_send_sync::<SharedHistory>();
+        let shared = SharedHistory::from(History::new("a".into()));
+        shared.visit("b".into(), Transition::Typed);
+        shared.set_title("B".into());
+        shared.push("c".into());
+        assert_eq!(url(shared.back()), Some("b".into()));
+        assert!(shared.can_go_back() && shared.can_go_forward());
+        assert_eq!(shared.index(), 1);
+        shared.replace_current("b2".into());
+
+        let history = shared.snapshot();
+        assert_eq!(urls(history.entries()), ["a", "b2", "c"]);
+        assert_eq!(history.current(), shared.current());
+        let path = temp_path("shared.json");
+        shared.save(&path).unwrap();
+        assert_eq!(History::load(&path).unwrap(), history);
+        let again = SharedHistory::from(history);
+        assert_eq!(url(again.forward()), Some("c".into()));
+    }
+
+    #[test]
+    fn shared_history_survives_concurrent_use() {
+        use std::sync::Arc;
+        use std::thread;
+
+        const THREADS: usize = 8;
+        const STEPS: usize = 500;
+        // A bounded linear history, evicting as it goes, and an unbounded
+        // branching one, which loses nothing.
+        for (branching, capacity) in [(false, DEFAULT_HISTORY_CAPACITY), (true, usize::MAX)] {
+            let shared = Arc::new(if branching {
+                SharedHistory::from(History::branching("root".into()))
+            } else {
+                SharedHistory::from(History::new("root".into()))
+            });
+            shared.set_capacity(capacity);
+            let writers: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let shared = Arc::clone(&shared);
+                    thread::spawn(move || {
+                        for i in 0..STEPS {
+                            match i % 5 {
+                                0 | 1 => shared.push(format!("{}-{}", t, i)),
+                                2 => drop(shared.back()),
+                                3 => drop(shared.forward()),
+                                _ => shared.set_title(format!("title {}", i)),
+                            }
+                        }
+                    })
+                })
+                .collect();
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let shared = Arc::clone(&shared);
+                    thread::spawn(move || {
+                        for _ in 0..STEPS {
+                            // Each read sees the history between two
+                            // operations, never halfway through one.
+                            let history = shared.snapshot();
+                            history.tree.borrow().validate().unwrap();
+                            let entries = history.entries();
+                            assert!(history.index() < entries.len());
+                            assert!(entries.len() <= capacity);
+                            if branching {
+                                assert_eq!(entries[0].url, "root");
+                            }
+                            assert_eq!(history.current_url(), entries[history.index()].url);
+                        }
+                    })
+                })
+                .collect();
+            for thread in writers.into_iter().chain(readers) {
+                thread.join().unwrap();
+            }
+
+            let history = shared.snapshot();
+            history.tree.borrow().validate().unwrap();
+            let nodes = history.tree.borrow().nodes.len();
+            // Every push added an entry to the branching history.
+            if branching {
+                assert_eq!(nodes, 1 + THREADS * STEPS * 2 / 5);
+            } else {
+                assert!(nodes <= capacity);
+            }
+            assert_eq!(history.entries().len(), shared.entries().len());
+        }
+    }
+
+    /// A small xorshift generator, so the property tests below replay the
+    /// same operations on every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_entries_up_to_its_capacity() {
+        let history = History::new("0".into());
+        assert_eq!(history.capacity(), DEFAULT_HISTORY_CAPACITY);
+        history.set_capacity(3);
+        for i in 1..=4 {
+            history.push(i.to_string());
+        }
+        assert_eq!(urls(history.entries()), ["2", "3", "4"]);
+        assert_eq!(history.index(), 2);
+        assert_eq!(url(history.go(-2)), Some("2".into()));
+        assert!(!history.can_go_back());
+
+        // Shrinking keeps the current entry, dropping older ones first.
+        history.forward();
+        history.set_capacity(1);
+        assert_eq!(urls(history.entries()), ["3"]);
+        history.set_capacity(0);
+        assert_eq!(history.capacity(), 1);
+    }
+
+    /// Pushes, goes back and forward and changes the capacity at random,
+    /// checking a linear history against a plain list of URLs.
+    #[test]
+    fn eviction_matches_a_bounded_list() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..200 {
+            let capacity = 1 + rng.below(8);
+            let history = History::new("u0".into());
+            history.set_capacity(capacity);
+            let (mut model, mut index, mut capacity) = (vec![String::from("u0")], 0, capacity);
+            for step in 1..300 {
+                match rng.below(10) {
+                    0..=3 => {
+                        let url = format!("u{}", step);
+                        history.push(url.clone());
+                        model.truncate(index + 1);
+                        model.push(url);
+                        if model.len() > capacity {
+                            model.remove(0);
+                        }
+                        index = model.len() - 1;
+                    }
+                    4 | 5 => {
+                        let entry = history.back();
+                        assert_eq!(entry.is_some(), index > 0);
+                        index = index.saturating_sub(1);
+                    }
+                    6 | 7 => {
+                        let entry = history.forward();
+                        assert_eq!(entry.is_some(), index + 1 < model.len());
+                        index = (index + 1).min(model.len() - 1);
+                    }
+                    8 => {
+                        let delta = rng.below(7) as isize - 3;
+                        let target = index as isize + delta;
+                        let entry = history.go(delta);
+                        if delta != 0 && (0..model.len() as isize).contains(&target) {
+                            assert_eq!(url(entry), Some(model[target as usize].clone()));
+                            index = target as usize;
+                        } else {
+                            assert_eq!(entry, None);
+                        }
+                    }
+                    _ => {
+                        capacity = 1 + rng.below(8);
+                        history.set_capacity(capacity);
+                        if model.len() > capacity {
+                            let start = (model.len() - capacity).min(index);
+                            model.drain(..start);
+                            model.truncate(capacity);
+                            index -= start;
+                        }
+                    }
+                }
+                assert_eq!(urls(history.entries()), model);
+                assert_eq!(history.index(), index);
+                assert_eq!(history.len(), model.len());
+                assert!(history.len() <= history.capacity());
+                history.tree.borrow().validate().unwrap();
+            }
+        }
+    }
+
+    /// The same with branches: the whole tree stays within the capacity,
+    /// and well-formed.
+    #[test]
+    fn eviction_keeps_branching_histories_well_formed() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..200 {
+            let history = History::branching("u0".into());
+            history.set_capacity(1 + rng.below(8));
+            for step in 1..300 {
+                match rng.below(10) {
+                    0..=3 => history.push(format!("u{}", step)),
+                    4 | 5 => drop(history.back()),
+                    6 => drop(history.forward()),
+                    7 => {
+                        let branches = history.branches();
+                        if !branches.is_empty() {
+                            let (id, entry) = branches[rng.below(branches.len())].clone();
+                            assert_eq!(url(history.jump_to(id)), Some(entry.url));
+                        }
+                    }
+                    8 => drop(history.go(rng.below(7) as isize - 3)),
+                    _ => history.set_capacity(1 + rng.below(8)),
+                }
+                let tree = history.tree.borrow();
+                tree.validate().unwrap();
+                assert!(tree.nodes.len() <= tree.capacity);
+                drop(tree);
+                let entries = history.entries();
+                assert_eq!(history.current_url(), entries[history.index()].url);
+            }
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::bookmarks::Folder;
+use crate::visits::{ClearRange, Visit, VisitQuery};
+
+/// Version of the toolbar <-> core message protocol. Both directions carry
+/// it in a `v` field; messages with any other version are rejected.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Commands sent by the toolbar page to the Rust core.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ToolbarCommand {
+    Back,
+    Forward,
+    /// Go `delta` entries back (negative) or forward in the active tab.
+    GoBy {
+        delta: isize,
+    },
+    /// Ask for the entries behind the Back button, or the Forward one,
+    /// answered with [`CoreEvent::HistoryMenu`].
+    HistoryMenu {
+        forward: bool,
+    },
+    Go {
+        url: String,
+    },
+    Reload,
+    /// Reload bypassing the cache.
+    HardReload,
+    Stop,
+    NewTab,
+    CloseTab {
+        index: usize,
+    },
+    SelectTab {
+        index: usize,
+    },
+    MoveTab {
+        from: usize,
+        to: usize,
+    },
+    /// Bookmark the active page, or remove its bookmarks if it has any.
+    ToggleBookmark,
+    ToggleSidebar,
+    /// Load `url` in the active tab, e.g. a bookmark clicked in the sidebar.
+    OpenUrl {
+        url: String,
+    },
+    RemoveBookmark {
+        bookmark: u64,
+    },
+    /// Import the contents of a Netscape bookmark file.
+    ImportBookmarks {
+        html: String,
+    },
+    ExportBookmarks,
+    /// Ask for the visits matching `query`, answered with
+    /// [`CoreEvent::Visits`]. Also accepted from the history page.
+    SearchHistory {
+        query: VisitQuery,
+    },
+    RemoveVisit {
+        visit: u64,
+    },
+    ClearHistory {
+        range: ClearRange,
+    },
+    /// Reopen the session offered by [`CoreEvent::SessionAvailable`].
+    RestoreSession,
+    /// Forget the offered session.
+    DismissSession,
+    /// Close the window without asking again, after the user confirmed
+    /// [`CoreEvent::ConfirmClose`].
+    CloseWindow,
+    NewWindow,
+    /// Move the tab at `index` into a window of its own, e.g. after it was
+    /// dragged out of the tab strip.
+    MoveTabToNewWindow {
+        index: usize,
+    },
+    /// Open a popup reported by [`CoreEvent::PopupBlocked`] after all.
+    OpenPopup {
+        url: String,
+    },
+}
+
+impl ToolbarCommand {
+    /// Whether `kind` is the `type` tag of a command. Asks serde about a
+    /// message with nothing else in it, so any error other than an unknown
+    /// variant is about the missing fields of a command it does know.
+    fn is_kind(kind: &str) -> bool {
+        let probe = serde_json::json!({ "type": kind });
+        match serde_json::from_value::<Self>(probe) {
+            Ok(_) => true,
+            Err(err) => !err.to_string().starts_with("unknown variant"),
+        }
+    }
+}
+
+/// A decoded toolbar message. `id` is chosen by the toolbar and echoed in the
+/// matching [`CoreEvent::Ack`] or [`CoreEvent::Error`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub id: Option<u64>,
+    pub command: ToolbarCommand,
+}
+
+/// State and replies pushed from the core to the toolbar.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum CoreEvent {
+    NavigationState {
+        url: String,
+        can_go_back: bool,
+        can_go_forward: bool,
+        loading: bool,
+        bookmarked: bool,
+    },
+    Tabs {
+        tabs: Vec<String>,
+        active: usize,
+    },
+    Bookmarks {
+        root: Folder,
+    },
+    /// Result of a [`ToolbarCommand::HistoryMenu`]: the entries behind the
+    /// Back button, or the Forward one, nearest first.
+    HistoryMenu {
+        forward: bool,
+        entries: Vec<HistoryMenuEntry>,
+    },
+    /// Result of a [`ToolbarCommand::SearchHistory`], newest first.
+    Visits {
+        visits: Vec<Visit>,
+    },
+    /// The previous session can be restored. `crashed` when it did not end
+    /// with a clean shutdown.
+    SessionAvailable {
+        tabs: usize,
+        crashed: bool,
+    },
+    /// Closing the window would close `tabs` tabs and cancel `downloads`
+    /// downloads; the toolbar asks before sending
+    /// [`ToolbarCommand::CloseWindow`].
+    ConfirmClose {
+        tabs: usize,
+        downloads: usize,
+    },
+    /// A page tried to open `url` in a new window and was stopped.
+    PopupBlocked {
+        url: String,
+    },
+    /// A short message for the user, shown in the toolbar.
+    Notice {
+        message: String,
+    },
+    Ack {
+        id: u64,
+    },
+    Error {
+        id: Option<u64>,
+        message: String,
+    },
+}
+
+/// One entry of a [`CoreEvent::HistoryMenu`]. Picking it sends
+/// [`ToolbarCommand::GoBy`] with its `delta`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryMenuEntry {
+    pub delta: isize,
+    /// The page title, or the URL for pages without one.
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IpcError {
+    /// Not JSON, or missing or mistyped fields.
+    Malformed { reason: String, id: Option<u64> },
+    /// A message for another version of the protocol.
+    UnsupportedVersion { version: u64, id: Option<u64> },
+    /// Well-formed message with a `type` the core does not know.
+    UnknownMessage { kind: String, id: Option<u64> },
+}
+
+impl IpcError {
+    /// The request id of the offending message, when it could be read.
+    pub fn request_id(&self) -> Option<u64> {
+        match self {
+            IpcError::Malformed { id, .. }
+            | IpcError::UnsupportedVersion { id, .. }
+            | IpcError::UnknownMessage { id, .. } => *id,
+        }
+    }
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcError::Malformed { reason, .. } => write!(f, "malformed message: {}", reason),
+            IpcError::UnsupportedVersion { version, .. } => write!(
+                f,
+                "unsupported protocol version {}, expected {}",
+                version, PROTOCOL_VERSION
+            ),
+            IpcError::UnknownMessage { kind, .. } => write!(f, "unknown message type {:?}", kind),
+        }
+    }
+}
+
+impl std::error::Error for IpcError {}
+
+/// Decodes one message posted by the toolbar.
+pub fn parse_request(raw: &str) -> Result<Request, IpcError> {
+    let value: Value = serde_json::from_str(raw).map_err(|err| IpcError::Malformed {
+        reason: err.to_string(),
+        id: None,
+    })?;
+    let id = value.get("id").and_then(Value::as_u64);
+    let malformed = |reason: String| IpcError::Malformed { reason, id };
+    let version = value
+        .get("v")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| malformed("missing protocol version".into()))?;
+    if version != PROTOCOL_VERSION as u64 {
+        return Err(IpcError::UnsupportedVersion { version, id });
+    }
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| malformed("missing message type".into()))?;
+    let kind = kind.to_string();
+    let command = serde_json::from_value(value).map_err(|err| {
+        if ToolbarCommand::is_kind(&kind) {
+            malformed(err.to_string())
+        } else {
+            IpcError::UnknownMessage { kind, id }
+        }
+    })?;
+    Ok(Request { id, command })
+}
+
+/// Encodes a request the way the toolbar does; used by tests and tooling.
+pub fn encode_request(request: &Request) -> String {
+    let mut value = serde_json::to_value(&request.command).unwrap_or(Value::Null);
+    if let Value::Object(map) = &mut value {
+        map.insert("v".into(), PROTOCOL_VERSION.into());
+        if let Some(id) = request.id {
+            map.insert("id".into(), id.into());
+        }
+    }
+    value.to_string()
+}
+
+/// Encodes an event as a versioned JSON object.
+pub fn encode_event(event: &CoreEvent) -> String {
+    let mut value = serde_json::to_value(event).unwrap_or(Value::Null);
+    if let Value::Object(map) = &mut value {
+        map.insert("v".into(), PROTOCOL_VERSION.into());
+    }
+    value.to_string()
+}
+
+/// JavaScript that delivers `event` to the toolbar's `onCoreEvent` handler.
+pub fn event_script(event: &CoreEvent) -> String {
+    format!("window.onCoreEvent({})", encode_event(event))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        let cases = [
+            (r#"{"v":1,"type":"back"}"#, None, ToolbarCommand::Back),
+            (
+                r#"{"v":1,"id":7,"type":"forward"}"#,
+                Some(7),
+                ToolbarCommand::Forward,
+            ),
+            (
+                r#"{"v":1,"id":8,"type":"go","url":"example.com"}"#,
+                Some(8),
+                ToolbarCommand::Go {
+                    url: "example.com".into(),
+                },
+            ),
+            (
+                r#"{"v":1,"type":"move-tab","from":2,"to":0}"#,
+                None,
+                ToolbarCommand::MoveTab { from: 2, to: 0 },
+            ),
+        ];
+        for (raw, id, command) in cases {
+            assert_eq!(parse_request(raw), Ok(Request { id, command }), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn every_command_round_trips() {
+        let commands = [
+            ToolbarCommand::Back,
+            ToolbarCommand::Forward,
+            ToolbarCommand::GoBy { delta: -3 },
+            ToolbarCommand::HistoryMenu { forward: true },
+            ToolbarCommand::Go { url: "a".into() },
+            ToolbarCommand::Reload,
+            ToolbarCommand::HardReload,
+            ToolbarCommand::Stop,
+            ToolbarCommand::NewTab,
+            ToolbarCommand::CloseTab { index: 1 },
+            ToolbarCommand::SelectTab { index: 2 },
+            ToolbarCommand::MoveTab { from: 0, to: 1 },
+            ToolbarCommand::ToggleBookmark,
+            ToolbarCommand::ToggleSidebar,
+            ToolbarCommand::OpenUrl { url: "b".into() },
+            ToolbarCommand::RemoveBookmark { bookmark: 3 },
+            ToolbarCommand::ImportBookmarks {
+                html: "<DL></DL>".into(),
+            },
+            ToolbarCommand::ExportBookmarks,
+            ToolbarCommand::SearchHistory {
+                query: VisitQuery {
+                    text: Some("rust".into()),
+                    ..VisitQuery::default()
+                },
+            },
+            ToolbarCommand::RemoveVisit { visit: 4 },
+            ToolbarCommand::ClearHistory {
+                range: ClearRange::LastHour,
+            },
+            ToolbarCommand::RestoreSession,
+            ToolbarCommand::DismissSession,
+            ToolbarCommand::CloseWindow,
+            ToolbarCommand::NewWindow,
+            ToolbarCommand::MoveTabToNewWindow { index: 2 },
+            ToolbarCommand::OpenPopup { url: "c".into() },
+        ];
+        for (i, command) in commands.into_iter().enumerate() {
+            let request = Request {
+                id: Some(i as u64),
+                command,
+            };
+            assert_eq!(parse_request(&encode_request(&request)), Ok(request));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_messages() {
+        assert!(matches!(
+            parse_request("back"),
+            Err(IpcError::Malformed { id: None, .. })
+        ));
+        assert!(matches!(
+            parse_request(r#"{"type":"back"}"#),
+            Err(IpcError::Malformed { .. })
+        ));
+        assert_eq!(
+            parse_request(r#"{"v":2,"type":"back"}"#),
+            Err(IpcError::UnsupportedVersion {
+                version: 2,
+                id: None
+            })
+        );
+        let err = parse_request(r#"{"v":2,"id":6,"type":"back"}"#).unwrap_err();
+        assert_eq!(err.request_id(), Some(6));
+        let err = parse_request(r#"{"v":1,"id":3,"type":"teleport"}"#).unwrap_err();
+        assert_eq!(
+            err,
+            IpcError::UnknownMessage {
+                kind: "teleport".into(),
+                id: Some(3)
+            }
+        );
+        assert_eq!(err.request_id(), Some(3));
+        assert!(matches!(
+            parse_request(r#"{"v":1,"type":"go"}"#),
+            Err(IpcError::Malformed { id: None, .. })
+        ));
+        let err = parse_request(r#"{"v":1,"id":5,"type":"go","url":7}"#).unwrap_err();
+        assert!(matches!(err, IpcError::Malformed { id: Some(5), .. }));
+        assert_eq!(err.request_id(), Some(5));
+        // An unknown value inside a known command is a bad payload.
+        assert!(matches!(
+            parse_request(r#"{"v":1,"type":"clear-history","range":"forever"}"#),
+            Err(IpcError::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn encodes_events() {
+        let event = CoreEvent::NavigationState {
+            url: "https://example.com/".into(),
+            can_go_back: true,
+            can_go_forward: false,
+            loading: false,
+            bookmarked: false,
+        };
+        let value: Value = serde_json::from_str(&encode_event(&event)).unwrap();
+        assert_eq!(value["v"], 1);
+        assert_eq!(value["type"], "navigation-state");
+        assert_eq!(value["can_go_back"], true);
+
+        assert_eq!(
+            event_script(&CoreEvent::Ack { id: 4 }),
+            r#"window.onCoreEvent({"id":4,"type":"ack","v":1})"#
+        );
+    }
+}
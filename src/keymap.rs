@@ -0,0 +1,671 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Named keys a binding can use, spelled as in the W3C `KeyboardEvent.key`
+/// names that winit's `NamedKey` follows.
+const NAMED_KEYS: &[&str] = &[
+    "Tab",
+    "Enter",
+    "Escape",
+    "Space",
+    "Backspace",
+    "Delete",
+    "Insert",
+    "Home",
+    "End",
+    "PageUp",
+    "PageDown",
+    "ArrowLeft",
+    "ArrowRight",
+    "ArrowUp",
+    "ArrowDown",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "F11",
+    "F12",
+    "BrowserBack",
+    "BrowserForward",
+    "BrowserRefresh",
+    "BrowserStop",
+    "BrowserHome",
+    "BrowserSearch",
+    "BrowserFavorites",
+];
+
+/// Shorter spellings accepted in config files.
+const KEY_ALIASES: &[(&str, &str)] = &[
+    ("esc", "Escape"),
+    ("return", "Enter"),
+    ("del", "Delete"),
+    ("pgup", "PageUp"),
+    ("pgdn", "PageDown"),
+    ("left", "ArrowLeft"),
+    ("right", "ArrowRight"),
+    ("up", "ArrowUp"),
+    ("down", "ArrowDown"),
+    ("plus", "+"),
+];
+
+/// Binding value that removes a default binding.
+pub const UNBIND: &str = "none";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// The Windows / Command key.
+    pub meta: bool,
+}
+
+/// One key press with the modifiers held, e.g. `Ctrl+Shift+T`.
+///
+/// Character keys are stored lower-cased so `Ctrl+Shift+T` matches whatever
+/// case the platform reports; named keys use their canonical spelling.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    pub key: String,
+}
+
+impl Chord {
+    /// A chord for a character key (`"t"`, `"1"`, `"["`).
+    pub fn character(modifiers: Modifiers, key: &str) -> Self {
+        Self {
+            modifiers,
+            key: key.to_lowercase(),
+        }
+    }
+
+    /// A chord for a named key, which must be spelled canonically
+    /// (`"PageUp"`, `"F5"`).
+    pub fn named(modifiers: Modifiers, key: &str) -> Self {
+        Self {
+            modifiers,
+            key: key.to_string(),
+        }
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (mods, key) = match s.strip_suffix("++") {
+            Some(mods) => (mods, "+"),
+            None if s == "+" => ("", "+"),
+            None => match s.rsplit_once('+') {
+                Some((mods, key)) => (mods, key),
+                None => ("", s),
+            },
+        };
+        let mut modifiers = Modifiers::default();
+        for name in mods.split('+').filter(|m| !m.is_empty()) {
+            let flag = match name.trim().to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "alt" | "option" => &mut modifiers.alt,
+                "shift" => &mut modifiers.shift,
+                "meta" | "super" | "cmd" | "win" => &mut modifiers.meta,
+                _ => return Err(format!("unknown modifier '{}'", name)),
+            };
+            if *flag {
+                return Err(format!("modifier '{}' given twice", name));
+            }
+            *flag = true;
+        }
+        let key = key.trim();
+        if key.is_empty() {
+            return Err("missing key".into());
+        }
+        if key.chars().count() == 1 {
+            return Ok(Chord::character(modifiers, key));
+        }
+        let lower = key.to_ascii_lowercase();
+        let canonical = NAMED_KEYS
+            .iter()
+            .copied()
+            .find(|named| named.eq_ignore_ascii_case(key))
+            .or_else(|| {
+                KEY_ALIASES
+                    .iter()
+                    .find(|(alias, _)| *alias == lower)
+                    .map(|(_, named)| *named)
+            })
+            .ok_or_else(|| format!("unknown key '{}'", key))?;
+        Ok(Chord::named(modifiers, canonical))
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.modifiers;
+        for (held, name) in [
+            (m.ctrl, "Ctrl+"),
+            (m.alt, "Alt+"),
+            (m.shift, "Shift+"),
+            (m.meta, "Meta+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        if self.key.chars().count() == 1 {
+            f.write_str(&self.key.to_uppercase())
+        } else {
+            f.write_str(&self.key)
+        }
+    }
+}
+
+/// A key sequence such as `Ctrl+K Ctrl+B`: chords separated by spaces.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sequence(pub Vec<Chord>);
+
+impl FromStr for Sequence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chords = s
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<Vec<Chord>, _>>()?;
+        if chords.is_empty() {
+            return Err("empty key sequence".into());
+        }
+        Ok(Sequence(chords))
+    }
+}
+
+impl fmt::Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chord) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", chord)?;
+        }
+        Ok(())
+    }
+}
+
+/// Something a key binding can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    NewTab,
+    CloseTab,
+    NextTab,
+    PreviousTab,
+    /// Select the tab at this zero-based position.
+    SelectTab(usize),
+    SelectLastTab,
+    MoveTabLeft,
+    MoveTabRight,
+    Back,
+    Forward,
+    Reload,
+    HardReload,
+    Stop,
+    ToggleBookmark,
+    ToggleSidebar,
+    /// Open the history page in a new tab.
+    ShowHistory,
+    NewWindow,
+    /// Move the active tab into a window of its own.
+    MoveTabToNewWindow,
+}
+
+impl Action {
+    const NAMED: &'static [(&'static str, Action)] = &[
+        ("new-tab", Action::NewTab),
+        ("close-tab", Action::CloseTab),
+        ("next-tab", Action::NextTab),
+        ("previous-tab", Action::PreviousTab),
+        ("select-last-tab", Action::SelectLastTab),
+        ("move-tab-left", Action::MoveTabLeft),
+        ("move-tab-right", Action::MoveTabRight),
+        ("back", Action::Back),
+        ("forward", Action::Forward),
+        ("reload", Action::Reload),
+        ("hard-reload", Action::HardReload),
+        ("stop", Action::Stop),
+        ("toggle-bookmark", Action::ToggleBookmark),
+        ("toggle-sidebar", Action::ToggleSidebar),
+        ("show-history", Action::ShowHistory),
+        ("new-window", Action::NewWindow),
+        ("move-tab-to-new-window", Action::MoveTabToNewWindow),
+    ];
+
+    /// The name used for this action in config files.
+    pub fn name(self) -> String {
+        match self {
+            Action::SelectTab(index) => format!("select-tab-{}", index + 1),
+            action => Self::NAMED
+                .iter()
+                .find(|(_, a)| *a == action)
+                .map(|(name, _)| name.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    /// Parses an action name: one of the kebab-case names above, or
+    /// `select-tab-N` with N from 1 to 8.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, action)) = Self::NAMED.iter().find(|(name, _)| *name == s) {
+            return Ok(*action);
+        }
+        match s.strip_prefix("select-tab-").map(str::parse::<usize>) {
+            Some(Ok(n @ 1..=8)) => Ok(Action::SelectTab(n - 1)),
+            _ => Err(format!("unknown action '{}'", s)),
+        }
+    }
+}
+
+/// A binding from the config file that could not be used as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingProblem {
+    InvalidKeys {
+        keys: String,
+        reason: String,
+    },
+    UnknownAction {
+        keys: String,
+        reason: String,
+    },
+    /// Two sequences collide: they are the same after normalization, or one
+    /// is a prefix of the other so the longer one can never fire.
+    Conflict {
+        first: Sequence,
+        second: Sequence,
+    },
+}
+
+impl fmt::Display for BindingProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingProblem::InvalidKeys { keys, reason } => {
+                write!(f, "cannot bind '{}': {}", keys, reason)
+            }
+            BindingProblem::UnknownAction { keys, reason } => {
+                write!(f, "cannot bind '{}': {}", keys, reason)
+            }
+            BindingProblem::Conflict { first, second } => {
+                write!(f, "'{}' conflicts with '{}'", first, second)
+            }
+        }
+    }
+}
+
+/// Key sequences and the actions they trigger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Sequence, Action>,
+}
+
+impl Default for Keymap {
+    /// The built-in bindings.
+    fn default() -> Self {
+        let mut bindings = vec![
+            ("Ctrl+T", Action::NewTab),
+            ("Ctrl+W", Action::CloseTab),
+            ("Ctrl+Tab", Action::NextTab),
+            ("Ctrl+PageDown", Action::NextTab),
+            ("Ctrl+Shift+Tab", Action::PreviousTab),
+            ("Ctrl+PageUp", Action::PreviousTab),
+            ("Ctrl+9", Action::SelectLastTab),
+            ("Ctrl+Shift+PageUp", Action::MoveTabLeft),
+            ("Ctrl+Shift+PageDown", Action::MoveTabRight),
+            ("Alt+ArrowLeft", Action::Back),
+            ("BrowserBack", Action::Back),
+            ("Alt+ArrowRight", Action::Forward),
+            ("BrowserForward", Action::Forward),
+            ("F5", Action::Reload),
+            ("BrowserRefresh", Action::Reload),
+            ("Ctrl+R", Action::Reload),
+            ("Ctrl+F5", Action::HardReload),
+            ("Ctrl+BrowserRefresh", Action::HardReload),
+            ("Ctrl+Shift+R", Action::HardReload),
+            ("Escape", Action::Stop),
+            ("BrowserStop", Action::Stop),
+            ("Ctrl+D", Action::ToggleBookmark),
+            ("Ctrl+B", Action::ToggleSidebar),
+            ("Ctrl+H", Action::ShowHistory),
+            ("Ctrl+N", Action::NewWindow),
+        ];
+        let select: Vec<String> = (1..=8).map(|n| format!("Ctrl+{}", n)).collect();
+        bindings.extend(
+            select
+                .iter()
+                .enumerate()
+                .map(|(i, keys)| (keys.as_str(), Action::SelectTab(i))),
+        );
+        Self {
+            bindings: bindings
+                .into_iter()
+                .map(|(keys, action)| (keys.parse().expect("built-in binding"), action))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default keymap with the `[keys]` table of the config file applied
+    /// on top. A user binding replaces a default one for the same keys, and
+    /// [`UNBIND`] removes it. Returns the keymap along with every binding
+    /// that was skipped or collides with another.
+    pub fn with_overrides(overrides: &BTreeMap<String, String>) -> (Self, Vec<BindingProblem>) {
+        let mut keymap = Self::default();
+        let mut problems = Vec::new();
+        let mut user: Vec<(Sequence, Option<Action>)> = Vec::new();
+        for (keys, action) in overrides {
+            let sequence = match keys.parse::<Sequence>() {
+                Ok(sequence) => sequence,
+                Err(reason) => {
+                    problems.push(BindingProblem::InvalidKeys {
+                        keys: keys.clone(),
+                        reason,
+                    });
+                    continue;
+                }
+            };
+            let action = match action.as_str() {
+                UNBIND => None,
+                name => match name.parse::<Action>() {
+                    Ok(action) => Some(action),
+                    Err(reason) => {
+                        problems.push(BindingProblem::UnknownAction {
+                            keys: keys.clone(),
+                            reason,
+                        });
+                        continue;
+                    }
+                },
+            };
+            if let Some((first, _)) = user.iter().find(|(seq, _)| *seq == sequence) {
+                problems.push(BindingProblem::Conflict {
+                    first: first.clone(),
+                    second: sequence,
+                });
+                continue;
+            }
+            user.push((sequence, action));
+        }
+        for (sequence, action) in user {
+            match action {
+                Some(action) => keymap.bindings.insert(sequence, action),
+                None => keymap.bindings.remove(&sequence),
+            };
+        }
+        problems.extend(keymap.conflicts());
+        (keymap, problems)
+    }
+
+    pub fn bind(&mut self, sequence: Sequence, action: Action) {
+        self.bindings.insert(sequence, action);
+    }
+
+    pub fn get(&self, sequence: &[Chord]) -> Option<Action> {
+        self.bindings.get(&Sequence(sequence.to_vec())).copied()
+    }
+
+    /// Whether some binding starts with `prefix` and is longer than it.
+    pub fn is_prefix(&self, prefix: &[Chord]) -> bool {
+        self.bindings
+            .keys()
+            .any(|seq| seq.0.len() > prefix.len() && seq.0.starts_with(prefix))
+    }
+
+    /// Pairs of bindings where the first is a prefix of the second, which
+    /// makes the second unreachable. Sorted for stable reporting.
+    pub fn conflicts(&self) -> Vec<BindingProblem> {
+        let mut conflicts: Vec<BindingProblem> = self
+            .bindings
+            .keys()
+            .flat_map(|short| {
+                self.bindings
+                    .keys()
+                    .filter(move |long| {
+                        long.0.len() > short.0.len() && long.0.starts_with(&short.0)
+                    })
+                    .map(move |long| BindingProblem::Conflict {
+                        first: short.clone(),
+                        second: long.clone(),
+                    })
+            })
+            .collect();
+        conflicts.sort_by_key(|c| c.to_string());
+        conflicts
+    }
+
+    /// Every binding, sorted by key sequence.
+    pub fn bindings(&self) -> Vec<(String, Action)> {
+        let mut bindings: Vec<(String, Action)> = self
+            .bindings
+            .iter()
+            .map(|(seq, action)| (seq.to_string(), *action))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+}
+
+/// What a key press amounted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+    Action(Action),
+    /// The press started or continued a multi-key sequence.
+    Pending,
+    /// Nothing is bound; the key belongs to the page.
+    Unbound,
+}
+
+/// Turns key presses into actions, remembering the chords typed so far of
+/// a multi-key sequence.
+#[derive(Debug, Clone, Default)]
+pub struct Dispatcher {
+    keymap: Keymap,
+    pending: Vec<Chord>,
+}
+
+impl Dispatcher {
+    pub fn new(keymap: Keymap) -> Self {
+        Self {
+            keymap,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Replaces the keymap, abandoning any half-typed sequence.
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.pending.clear();
+    }
+
+    /// Chords typed so far of an unfinished sequence.
+    pub fn pending(&self) -> &[Chord] {
+        &self.pending
+    }
+
+    pub fn dispatch(&mut self, chord: Chord) -> Dispatch {
+        self.pending.push(chord);
+        if let Some(action) = self.keymap.get(&self.pending) {
+            self.pending.clear();
+            return Dispatch::Action(action);
+        }
+        if self.keymap.is_prefix(&self.pending) {
+            return Dispatch::Pending;
+        }
+        // A chord that breaks a sequence is dropped with it, unless it is a
+        // binding in its own right.
+        let broken = self.pending.len() > 1;
+        let chord = self.pending.pop();
+        self.pending.clear();
+        match chord {
+            Some(chord) if broken => self.dispatch(chord),
+            _ => Dispatch::Unbound,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(s: &str) -> Chord {
+        s.parse().unwrap()
+    }
+
+    fn ctrl() -> Modifiers {
+        Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        }
+    }
+
+    #[test]
+    fn parses_chords() {
+        assert_eq!(
+            chord("Ctrl+Shift+T"),
+            Chord::character(
+                Modifiers {
+                    ctrl: true,
+                    shift: true,
+                    ..Modifiers::default()
+                },
+                "t"
+            )
+        );
+        assert_eq!(chord("control+t"), chord("Ctrl+T"));
+        assert_eq!(chord("ctrl+pageup"), Chord::named(ctrl(), "PageUp"));
+        assert_eq!(chord("Ctrl+PgDn"), Chord::named(ctrl(), "PageDown"));
+        assert_eq!(chord("Ctrl++"), Chord::character(ctrl(), "+"));
+        assert_eq!(chord("Ctrl+Plus"), Chord::character(ctrl(), "+"));
+        assert_eq!(chord("F5").to_string(), "F5");
+        assert_eq!(chord("shift+ctrl+t").to_string(), "Ctrl+Shift+T");
+
+        for bad in ["", "Ctrl+", "Hyper+T", "Ctrl+Ctrl+T", "Ctrl+Banana"] {
+            assert!(bad.parse::<Chord>().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_actions() {
+        for (name, action) in Action::NAMED {
+            assert_eq!(name.parse::<Action>(), Ok(*action));
+            assert_eq!(action.name(), *name);
+        }
+        assert_eq!("select-tab-3".parse(), Ok(Action::SelectTab(2)));
+        assert_eq!(Action::SelectTab(2).name(), "select-tab-3");
+        assert!("select-tab-9".parse::<Action>().is_err());
+        assert!("teleport".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn dispatches_default_bindings() {
+        let mut dispatcher = Dispatcher::default();
+        assert_eq!(
+            dispatcher.dispatch(chord("Ctrl+T")),
+            Dispatch::Action(Action::NewTab)
+        );
+        assert_eq!(
+            dispatcher.dispatch(Chord::character(ctrl(), "T")),
+            Dispatch::Action(Action::NewTab)
+        );
+        assert_eq!(
+            dispatcher.dispatch(chord("Ctrl+3")),
+            Dispatch::Action(Action::SelectTab(2))
+        );
+        assert_eq!(
+            dispatcher.dispatch(chord("Alt+Left")),
+            Dispatch::Action(Action::Back)
+        );
+        assert_eq!(
+            dispatcher.dispatch(chord("Ctrl+Shift+R")),
+            Dispatch::Action(Action::HardReload)
+        );
+        assert_eq!(dispatcher.dispatch(chord("Left")), Dispatch::Unbound);
+        assert_eq!(dispatcher.dispatch(chord("T")), Dispatch::Unbound);
+        assert!(Keymap::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn dispatches_sequences() {
+        let mut keymap = Keymap::default();
+        keymap.bind("Ctrl+K Ctrl+B".parse().unwrap(), Action::ToggleSidebar);
+        keymap.bind("Ctrl+K T".parse().unwrap(), Action::NewTab);
+        let mut dispatcher = Dispatcher::new(keymap);
+
+        assert_eq!(dispatcher.dispatch(chord("Ctrl+K")), Dispatch::Pending);
+        assert_eq!(dispatcher.pending(), [chord("Ctrl+K")]);
+        assert_eq!(
+            dispatcher.dispatch(chord("Ctrl+B")),
+            Dispatch::Action(Action::ToggleSidebar)
+        );
+        assert!(dispatcher.pending().is_empty());
+
+        // A wrong second key abandons the sequence; it still fires if it is
+        // bound on its own.
+        assert_eq!(dispatcher.dispatch(chord("Ctrl+K")), Dispatch::Pending);
+        assert_eq!(dispatcher.dispatch(chord("X")), Dispatch::Unbound);
+        assert_eq!(dispatcher.dispatch(chord("Ctrl+K")), Dispatch::Pending);
+        assert_eq!(
+            dispatcher.dispatch(chord("Ctrl+W")),
+            Dispatch::Action(Action::CloseTab)
+        );
+        assert!(dispatcher.pending().is_empty());
+    }
+
+    #[test]
+    fn applies_overrides_and_reports_problems() {
+        let overrides: BTreeMap<String, String> = [
+            ("Ctrl+Shift+T", "new-tab"),
+            ("Ctrl+T", "none"),
+            ("Ctrl+R", "hard-reload"),
+            ("ctrl+r", "reload"),
+            ("Ctrl+Banana", "reload"),
+            ("Ctrl+J", "teleport"),
+            ("Ctrl+D Ctrl+D", "toggle-sidebar"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let (keymap, problems) = Keymap::with_overrides(&overrides);
+
+        assert_eq!(keymap.get(&[chord("Ctrl+Shift+T")]), Some(Action::NewTab));
+        assert_eq!(keymap.get(&[chord("Ctrl+T")]), None);
+        assert_eq!(keymap.get(&[chord("Ctrl+R")]), Some(Action::HardReload));
+
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems.iter().any(|p| matches!(
+            p,
+            BindingProblem::InvalidKeys { keys, .. } if keys == "Ctrl+Banana"
+        )));
+        assert!(problems.iter().any(|p| matches!(
+            p,
+            BindingProblem::UnknownAction { keys, .. } if keys == "Ctrl+J"
+        )));
+        assert!(problems
+            .iter()
+            .any(|p| p.to_string() == "'Ctrl+R' conflicts with 'Ctrl+R'"));
+        assert!(problems
+            .iter()
+            .any(|p| p.to_string() == "'Ctrl+D' conflicts with 'Ctrl+D Ctrl+D'"));
+    }
+}
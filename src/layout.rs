@@ -0,0 +1,168 @@
+/// A rectangle in logical (DPI-independent) pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[cfg(feature = "browser")]
+impl From<Rect> for wry::Rect {
+    fn from(rect: Rect) -> Self {
+        use tao::dpi::{LogicalPosition, LogicalSize};
+        wry::Rect {
+            position: LogicalPosition::new(rect.x, rect.y).into(),
+            size: LogicalSize::new(rect.width, rect.height).into(),
+        }
+    }
+}
+
+/// Sizes of the fixed chrome around the content area, in logical pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutSpec {
+    pub toolbar_height: f64,
+    /// Width of the sidebar docked on the left, if shown.
+    pub sidebar_width: Option<f64>,
+    /// Height of the status bar along the bottom, if shown.
+    pub status_height: Option<f64>,
+}
+
+impl Default for LayoutSpec {
+    fn default() -> Self {
+        Self {
+            toolbar_height: 40.0,
+            sidebar_width: None,
+            status_height: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub toolbar: Rect,
+    pub content: Rect,
+    pub sidebar: Option<Rect>,
+    pub status_bar: Option<Rect>,
+}
+
+impl Layout {
+    /// Lays out a window whose inner size is given in physical pixels, as
+    /// reported by the windowing system, at the given scale factor. Chrome
+    /// that does not fit is clamped so no rectangle has a negative size.
+    pub fn compute(
+        physical_width: u32,
+        physical_height: u32,
+        scale_factor: f64,
+        spec: &LayoutSpec,
+    ) -> Self {
+        let scale = if scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+        let width = physical_width as f64 / scale;
+        let height = physical_height as f64 / scale;
+
+        let toolbar_height = spec.toolbar_height.clamp(0.0, height);
+        let status_height = spec
+            .status_height
+            .map(|h| h.clamp(0.0, height - toolbar_height));
+        let body_top = toolbar_height;
+        let body_height = height - toolbar_height - status_height.unwrap_or(0.0);
+        let sidebar_width = spec.sidebar_width.map(|w| w.clamp(0.0, width));
+
+        let toolbar = Rect {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height: toolbar_height,
+        };
+        let sidebar = sidebar_width.map(|w| Rect {
+            x: 0.0,
+            y: body_top,
+            width: w,
+            height: body_height,
+        });
+        let content_x = sidebar_width.unwrap_or(0.0);
+        let content = Rect {
+            x: content_x,
+            y: body_top,
+            width: width - content_x,
+            height: body_height,
+        };
+        let status_bar = status_height.map(|h| Rect {
+            x: 0.0,
+            y: height - h,
+            width,
+            height: h,
+        });
+
+        Self {
+            toolbar,
+            content,
+            sidebar,
+            status_bar,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn toolbar_and_content_split_the_window() {
+        let layout = Layout::compute(800, 600, 1.0, &LayoutSpec::default());
+        assert_eq!(layout.toolbar, rect(0.0, 0.0, 800.0, 40.0));
+        assert_eq!(layout.content, rect(0.0, 40.0, 800.0, 560.0));
+        assert_eq!(layout.sidebar, None);
+        assert_eq!(layout.status_bar, None);
+    }
+
+    #[test]
+    fn physical_size_is_converted_to_logical() {
+        let layout = Layout::compute(1600, 1200, 2.0, &LayoutSpec::default());
+        assert_eq!(layout.toolbar, rect(0.0, 0.0, 800.0, 40.0));
+        assert_eq!(layout.content, rect(0.0, 40.0, 800.0, 560.0));
+    }
+
+    #[test]
+    fn sidebar_and_status_bar_shrink_content() {
+        let spec = LayoutSpec {
+            toolbar_height: 40.0,
+            sidebar_width: Some(200.0),
+            status_height: Some(20.0),
+        };
+        let layout = Layout::compute(800, 600, 1.0, &spec);
+        assert_eq!(layout.sidebar, Some(rect(0.0, 40.0, 200.0, 540.0)));
+        assert_eq!(layout.content, rect(200.0, 40.0, 600.0, 540.0));
+        assert_eq!(layout.status_bar, Some(rect(0.0, 580.0, 800.0, 20.0)));
+    }
+
+    #[test]
+    fn tiny_windows_never_produce_negative_sizes() {
+        let spec = LayoutSpec {
+            toolbar_height: 40.0,
+            sidebar_width: Some(200.0),
+            status_height: Some(20.0),
+        };
+        let layout = Layout::compute(100, 30, 1.0, &spec);
+        assert_eq!(layout.toolbar.height, 30.0);
+        assert_eq!(layout.content.height, 0.0);
+        assert_eq!(layout.content.width, 0.0);
+        assert_eq!(layout.status_bar.unwrap().height, 0.0);
+
+        let layout = Layout::compute(0, 0, 0.0, &spec);
+        assert_eq!(layout.content, rect(0.0, 0.0, 0.0, 0.0));
+    }
+}
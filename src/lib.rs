@@ -1,236 +1,1192 @@
-use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+pub mod bookmarks;
+pub mod cli;
+pub mod config;
 #[cfg(feature = "browser")]
-use winit::{
-    application::ApplicationHandler,
-    event::{ElementState, WindowEvent},
-    event_loop::{ActiveEventLoop, EventLoop},
-    keyboard::{Key, NamedKey, ModifiersState},
-    window::{Window, WindowId},
+mod gui;
+pub mod headless;
+mod history;
+pub mod ipc;
+pub mod keymap;
+pub mod layout;
+mod loading;
+pub mod navigation;
+pub mod persist;
+pub mod popups;
+mod profile;
+pub mod session;
+pub mod tabs;
+pub mod time;
+pub mod url_fixup;
+pub mod view;
+pub mod visits;
+mod windows;
+
+pub use bookmarks::{BookmarkId, Bookmarks};
+pub use cli::LaunchOptions;
+pub use config::{Config, ConfigWatcher};
+pub use history::{
+    History, HistoryEntry, HistoryLock, MutexLock, NodeId, RefCellLock, ScrollPosition,
+    SharedHistory, Transition, DEFAULT_HISTORY_CAPACITY, HISTORY_FORMAT_VERSION,
 };
+pub use ipc::{CoreEvent, HistoryMenuEntry, IpcError, ToolbarCommand};
+pub use keymap::{Action, Chord, Dispatch, Dispatcher, Keymap};
+pub use layout::{Layout, LayoutSpec};
+pub use loading::LoadState;
+pub use navigation::{Commit, NavigationController, NavigationKind, SameDocumentNavigation};
+pub use persist::PersistError;
+pub use popups::{NewWindowPolicy, PopupDecision, PopupRequest};
+pub use profile::Profile;
+pub use session::{Session, TabSession, WindowGeometry, WindowSession};
+pub use tabs::{Tab, TabCommand, TabId, Tabs};
+pub use url_fixup::UrlFixup;
+pub use view::{PageView, RecordingView, ViewCall};
+pub use visits::{ClearRange, Retention, Visit, VisitId, VisitQuery, Visits, HISTORY_PAGE_URL};
+pub use windows::BrowserWindow;
+
 #[cfg(feature = "browser")]
-use wry::{PageLoadEvent, WebView, WebViewBuilder};
+pub use gui::UserEvent;
 #[cfg(feature = "browser")]
-use tao::dpi::{LogicalPosition, LogicalSize};
+use winit::{
+    event_loop::{EventLoop, EventLoopProxy},
+    keyboard::ModifiersState,
+    window::Window,
+};
 
-pub struct History {
-    entries: RefCell<Vec<String>>,
-    index: Cell<usize>,
-}
+/// What tabs and panels render into: a wry WebView in GUI builds, or any
+/// other [`PageView`] such as a [`RecordingView`] in tests.
+pub type ContentView = Box<dyn PageView>;
 
-impl History {
-    pub fn new(initial: String) -> Self {
-        Self {
-            entries: RefCell::new(vec![initial]),
-            index: Cell::new(0),
-        }
-    }
+/// Work to do on shutdown, registered with [`Browser::on_shutdown`].
+pub type ShutdownHook = Box<dyn FnOnce(&mut Browser)>;
 
-    pub fn push(&self, url: String) {
-        let mut entries = self.entries.borrow_mut();
-        let idx = self.index.get();
-        if entries.get(idx).map_or(false, |u| u == &url) {
-            return;
+/// Application name, shown in the window title.
+pub const APP_NAME: &str = "wrybrowser";
+
+/// Page opened when neither the command line nor a saved history names one.
+pub const DEFAULT_HOMEPAGE: &str = "https://example.com";
+
+/// Loads the profile's saved history, falling back to a fresh one seeded
+/// with `homepage`. `initial_url`, when given, is navigated to on top of
+/// whatever was restored.
+pub fn restore_history(
+    profile: Option<&Profile>,
+    initial_url: Option<String>,
+    homepage: &str,
+) -> History {
+    let restored = profile.and_then(|profile| match History::load(&profile.history_path()) {
+        Ok(history) => Some(history),
+        Err(err) if err.is_not_found() => None,
+        Err(err) => {
+            eprintln!("Ignoring saved history: {}", err);
+            None
+        }
+    });
+    match (restored, initial_url) {
+        (Some(history), Some(url)) => {
+            history.push(url);
+            history
         }
-        entries.truncate(idx + 1);
-        entries.push(url);
-        self.index.set(entries.len() - 1);
+        (Some(history), None) => history,
+        (None, url) => History::new(url.unwrap_or_else(|| homepage.into())),
     }
+}
 
-    pub fn current(&self) -> Option<String> {
-        self.entries
-            .borrow()
-            .get(self.index.get())
-            .cloned()
+/// Loads a profile file, falling back to the default value when it does not
+/// exist yet or cannot be read.
+fn load_or_default<T: Default>(
+    path: Option<PathBuf>,
+    load: impl FnOnce(&Path) -> Result<T, PersistError>,
+) -> T {
+    let Some(path) = path else {
+        return T::default();
+    };
+    match load(&path) {
+        Ok(value) => value,
+        Err(err) if err.is_not_found() => T::default(),
+        Err(err) => {
+            eprintln!("Ignoring {}: {}", path.display(), err);
+            T::default()
+        }
     }
+}
 
-    pub fn back(&self) -> Option<String> {
-        if self.index.get() > 0 {
-            self.index.set(self.index.get() - 1);
-            return self.current();
+/// Opens the visit log at `path`. One that cannot be read, say because it
+/// is damaged or from a newer version, is moved aside to `<path>.bad` for
+/// the user to recover and a new one started. If even that fails, visits
+/// are kept in memory only and the file is left alone.
+fn open_visits(path: &Path) -> Visits {
+    let err = match Visits::open(path) {
+        Ok(visits) => return visits,
+        Err(err) => err,
+    };
+    eprintln!("Ignoring {}: {}", path.display(), err);
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".bad");
+    match std::fs::rename(path, &aside) {
+        Ok(()) => {
+            eprintln!("Moved it to {}", Path::new(&aside).display());
+            Visits::create(path)
+        }
+        Err(err) => {
+            eprintln!("Not saving visits: cannot move it aside: {}", err);
+            Visits::new()
         }
-        None
     }
+}
 
-    pub fn forward(&self) -> Option<String> {
-        if self.index.get() + 1 < self.entries.borrow().len() {
-            self.index.set(self.index.get() + 1);
-            return self.current();
-        }
-        None
+/// Titles restored tabs after their current history entries, until their
+/// pages load and report titles of their own.
+fn restore_titles(tabs: &mut Tabs<ContentView>) {
+    for tab in tabs.iter_mut() {
+        tab.title = tab.history.current().title;
     }
 }
 
+/// What the window has to do after the browser handled some input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    None,
+    /// Tabs were opened, closed, moved or selected. New tabs need a view
+    /// (see [`Tabs::fill_views`]) before [`Browser::sync_tabs`] shows them.
+    TabsChanged,
+    /// Show or hide the bookmarks sidebar.
+    ToggleSidebar,
+    /// Windows were opened or restored, and the current window's tabs may
+    /// have changed. Windows without a window need one, with views for
+    /// their tabs.
+    WindowsChanged,
+    /// Close the current window; other windows stay open.
+    CloseWindow,
+    /// Shut down and close the last window: its last tab was closed, or the
+    /// user confirmed closing it.
+    Close,
+}
+
+/// The browser: state shared by every window, and the state of the current
+/// window, which is the one input is being handled for. Other windows are
+/// parked in `other_windows` (see [`BrowserWindow`]).
 pub struct Browser {
     #[cfg(feature = "browser")]
     pub window: Option<Window>,
+    pub toolbar: Option<ContentView>,
+    pub sidebar: Option<ContentView>,
+    pub tabs: Tabs<ContentView>,
+    pub other_windows: Vec<BrowserWindow>,
+    pub bookmarks: Bookmarks,
+    /// Every page visited, across tabs and sessions.
+    pub visits: Visits,
+    pub profile: Option<Profile>,
+    pub layout: LayoutSpec,
+    pub url_fixup: UrlFixup,
+    pub config: Config,
+    /// Key bindings from the config, applied to key presses in GUI builds.
+    pub keys: Dispatcher,
+    /// The config file in use, watched for changes.
+    pub config_path: Option<PathBuf>,
+    /// Initial inner window size in logical pixels, from `--window-size`.
+    pub window_size: Option<(u32, u32)>,
+    /// Where the window is, kept up to date for session snapshots. Set from
+    /// a restored session before the window exists.
+    pub geometry: Option<WindowGeometry>,
+    /// The last session, found at startup and not restored (yet).
+    pub previous_session: Option<Session>,
+    /// Downloads started and not finished yet.
+    pub active_downloads: usize,
+    shutdown_hooks: Vec<ShutdownHook>,
+    /// User agent from `--user-agent`, which takes precedence over the
+    /// config file.
+    pub user_agent: Option<String>,
+    /// Fullscreen, undecorated and without a toolbar.
+    pub kiosk: bool,
     #[cfg(feature = "browser")]
-    pub webview: Option<Rc<WebView>>,
-    #[cfg(feature = "browser")]
-    pub toolbar: Option<WebView>,
-    pub history: Rc<History>,
+    pub modifiers: ModifiersState,
     #[cfg(feature = "browser")]
-    pub modifiers: winit::keyboard::ModifiersState,
+    pub proxy: Option<EventLoopProxy<UserEvent>>,
 }
 
-#[cfg(feature = "browser")]
-impl ApplicationHandler for Browser {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = event_loop
-            .create_window(Window::default_attributes())
-            .unwrap();
-
-        let size = window.inner_size();
-        let toolbar_height = 40.0;
-
-        let content_bounds = wry::Rect {
-            position: LogicalPosition::new(0.0, toolbar_height).into(),
-            size: LogicalSize::new(size.width as f64, size.height as f64 - toolbar_height).into(),
-        };
-
-        let history = self.history.clone();
-        let current = history.current().unwrap_or_else(|| "about:blank".into());
-        let webview = Rc::new(
-            WebViewBuilder::new()
-                .with_url(&current)
-                .with_bounds(content_bounds)
-                .with_on_page_load_handler(move |event, url| {
-                    if let PageLoadEvent::Finished = event {
-                        history.push(url);
+impl Browser {
+    pub fn new(history: History, profile: Option<Profile>) -> Self {
+        let bookmarks = load_or_default(profile.as_ref().map(Profile::bookmarks_path), |path| {
+            Bookmarks::load(path)
+        });
+        let visits = match profile.as_ref().map(Profile::visits_path) {
+            Some(path) => open_visits(&path),
+            None => Visits::new(),
+        };
+        Self {
+            #[cfg(feature = "browser")]
+            window: None,
+            toolbar: None,
+            sidebar: None,
+            tabs: Tabs::new(history),
+            other_windows: Vec::new(),
+            bookmarks,
+            visits,
+            profile,
+            layout: LayoutSpec::default(),
+            url_fixup: UrlFixup::default(),
+            config: Config::default(),
+            keys: Dispatcher::default(),
+            config_path: None,
+            window_size: None,
+            geometry: None,
+            previous_session: None,
+            active_downloads: 0,
+            shutdown_hooks: Vec::new(),
+            user_agent: None,
+            kiosk: false,
+            #[cfg(feature = "browser")]
+            modifiers: ModifiersState::default(),
+            #[cfg(feature = "browser")]
+            proxy: None,
+        }
+    }
+
+    /// Builds the browser a launch asks for: the first tab restores the
+    /// profile's history and navigates to `options.url`, and every
+    /// `--new-tab` URL gets a tab of its own. With `--restore-session` the
+    /// last session's tabs come first and `options.url` opens in a new tab;
+    /// otherwise the last session is kept in `previous_session` to offer.
+    pub fn launch(options: LaunchOptions, profile: Option<Profile>, config: Config) -> Self {
+        let url_fixup = UrlFixup::new(config.search_engine.clone());
+        let initial_url = options.url.and_then(|url| url_fixup.fixup(&url));
+        let homepage = url_fixup
+            .fixup(&config.homepage)
+            .unwrap_or_else(|| DEFAULT_HOMEPAGE.into());
+        let history = restore_history(profile.as_ref(), initial_url.clone(), &homepage);
+        let session = load_or_default(profile.as_ref().map(Profile::session_path), |path| {
+            Session::load(path).map(Some)
+        });
+        let mut browser = Self::new(history, profile);
+        match session {
+            Some(session) if options.restore_session => {
+                browser.restore_session(session);
+                if let Some(url) = initial_url {
+                    browser.tabs.open(History::new(url));
+                }
+            }
+            session => browser.previous_session = session,
+        }
+        for url in options.new_tabs {
+            if let Some(url) = url_fixup.fixup(&url) {
+                browser.tabs.open(History::new(url));
+            }
+        }
+        browser.window_size = options.window_size;
+        browser.user_agent = options.user_agent;
+        browser.kiosk = options.kiosk;
+        browser.apply_config(config);
+        browser
+    }
+
+    /// Switches to new settings. Takes effect for the search engine, the
+    /// homepage of new tabs, key bindings, history retention, tab history
+    /// capacity and the toolbar height right away; a new user agent only
+    /// applies to tabs opened afterwards.
+    pub fn apply_config(&mut self, config: Config) {
+        let (keymap, problems) = Keymap::with_overrides(&config.keys);
+        for problem in problems {
+            eprintln!("Key bindings: {}", problem);
+        }
+        self.keys.set_keymap(keymap);
+        self.url_fixup = UrlFixup::new(config.search_engine.clone());
+        self.visits.set_retention(config.retention());
+        let toolbar_height = if self.kiosk {
+            0.0
+        } else {
+            config.toolbar_height
+        };
+        self.layout.toolbar_height = toolbar_height;
+        self.tabs.set_history_capacity(config.tab_history_capacity);
+        for window in &mut self.other_windows {
+            window.layout.toolbar_height = toolbar_height;
+            window
+                .tabs
+                .set_history_capacity(config.tab_history_capacity);
+        }
+        self.config = config;
+    }
+
+    /// How many entries the history of `tab` keeps: its own capacity, or
+    /// the configured one.
+    pub fn history_capacity(&self, tab: &Tab<ContentView>) -> usize {
+        tab.history_capacity
+            .unwrap_or(self.config.tab_history_capacity)
+    }
+
+    /// Gives tab `id` a history capacity of its own, or with `None` the
+    /// configured one back. Returns false if the window has no such tab.
+    pub fn set_history_capacity(&mut self, id: TabId, capacity: Option<usize>) -> bool {
+        let configured = self.config.tab_history_capacity;
+        let Some(tab) = self.tabs.by_id_mut(id) else {
+            return false;
+        };
+        tab.history_capacity = capacity;
+        tab.history.set_capacity(capacity.unwrap_or(configured));
+        self.sync_toolbar();
+        true
+    }
+
+    /// URL of the configured homepage, opened in new tabs.
+    pub fn homepage(&self) -> String {
+        self.url_fixup
+            .fixup(&self.config.homepage)
+            .unwrap_or_else(|| DEFAULT_HOMEPAGE.into())
+    }
+
+    /// User agent for new content views, `None` for the engine default.
+    pub fn effective_user_agent(&self) -> Option<&str> {
+        self.user_agent
+            .as_deref()
+            .or(self.config.user_agent.as_deref())
+    }
+
+    /// History of the active tab.
+    pub fn history(&self) -> Rc<History> {
+        self.tabs.active().history.clone()
+    }
+
+    /// Navigation state of the active tab, as shown by the toolbar.
+    pub fn navigation_state(&self) -> CoreEvent {
+        let tab = self.tabs.active();
+        // While a navigation is under way, the address bar shows where to.
+        let url = match tab.navigation.pending_url() {
+            Some(url) => url.to_owned(),
+            None => tab.history.current_url(),
+        };
+        CoreEvent::NavigationState {
+            bookmarked: self.bookmarks.is_bookmarked(&url),
+            url,
+            can_go_back: tab.history.can_go_back(),
+            can_go_forward: tab.history.can_go_forward(),
+            loading: tab.load_state.is_loading(),
+        }
+    }
+
+    /// The bookmark tree, as shown by the sidebar.
+    pub fn bookmarks_state(&self) -> CoreEvent {
+        CoreEvent::Bookmarks {
+            root: self.bookmarks.root().clone(),
+        }
+    }
+
+    /// Bookmarks the active page, or removes every bookmark of it when it is
+    /// already bookmarked. Returns whether the page is bookmarked afterwards.
+    pub fn toggle_bookmark(&mut self) -> bool {
+        let tab = self.tabs.active();
+        let url = tab.history.current_url();
+        let bookmarked = if self.bookmarks.remove_url(&url) > 0 {
+            false
+        } else {
+            let label = tab.label();
+            let root = self.bookmarks.root_id();
+            self.bookmarks.add_bookmark(root, label, url, Vec::new());
+            true
+        };
+        self.save_bookmarks();
+        bookmarked
+    }
+
+    pub fn save_bookmarks(&self) {
+        if let Some(profile) = &self.profile {
+            if let Err(err) = self.bookmarks.save(&profile.bookmarks_path()) {
+                eprintln!("Failed to save bookmarks: {}", err);
+            }
+        }
+    }
+
+    /// Writes all bookmarks as a Netscape bookmark file into the profile and
+    /// returns its path.
+    pub fn export_bookmarks(&self) -> Result<PathBuf, PersistError> {
+        let profile = self.profile.as_ref().ok_or_else(|| {
+            PersistError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "no profile directory to export into",
+            ))
+        })?;
+        let path = profile.bookmarks_export_path();
+        persist::write_atomic(&path, self.bookmarks.export_netscape().as_bytes())?;
+        Ok(path)
+    }
+
+    /// The tab strip, as shown by the toolbar.
+    pub fn tabs_state(&self) -> CoreEvent {
+        CoreEvent::Tabs {
+            tabs: self.tabs.iter().map(Tab::label).collect(),
+            active: self.tabs.active_index(),
+        }
+    }
+
+    /// Window title for the active tab: `"<page title> - wrybrowser"`.
+    pub fn window_title(&self) -> String {
+        match &self.tabs.active().title {
+            Some(title) if !title.trim().is_empty() => format!("{} - {}", title.trim(), APP_NAME),
+            _ => APP_NAME.to_string(),
+        }
+    }
+
+    /// Delivers `event` to the toolbar.
+    pub fn push_event(&self, event: &CoreEvent) {
+        if let Some(toolbar) = &self.toolbar {
+            toolbar.evaluate_script(&ipc::event_script(event));
+        }
+    }
+
+    pub fn push_sidebar_event(&self, event: &CoreEvent) {
+        if let Some(sidebar) = &self.sidebar {
+            sidebar.evaluate_script(&ipc::event_script(event));
+        }
+    }
+
+    /// Pushes the tab strip, the active tab's navigation state and any
+    /// session offer to the toolbar.
+    pub fn sync_toolbar(&self) {
+        self.push_event(&self.tabs_state());
+        self.push_event(&self.navigation_state());
+        if let Some(offer) = self.session_offer() {
+            self.push_event(&offer);
+        }
+    }
+
+    /// Pushes the bookmark tree to the sidebar, if it is open.
+    pub fn sync_sidebar(&self) {
+        if self.layout.sidebar_width.is_some() {
+            self.push_sidebar_event(&self.bookmarks_state());
+        }
+    }
+
+    /// Shows only the active tab's view and refreshes the toolbar.
+    pub fn sync_tabs(&self) {
+        let active = self.tabs.active().id();
+        for tab in self.tabs.iter() {
+            if let Some(view) = &tab.view {
+                view.set_visible(tab.id() == active);
+            }
+        }
+        self.sync_toolbar();
+    }
+
+    /// Loads `url` in the active tab as a navigation of `kind`. A tab with
+    /// no view yet records new entries straight away; it loads its current
+    /// entry once it gets one.
+    fn load_in_active(&mut self, kind: NavigationKind, url: String) {
+        let tab = self.tabs.active_mut();
+        match &tab.view {
+            Some(view) => {
+                tab.navigation.begin(kind, url.clone());
+                view.load_url(&url);
+            }
+            None => {
+                if let NavigationKind::NewEntry(transition) = kind {
+                    tab.history.visit(url, transition);
+                }
+            }
+        }
+    }
+
+    /// Loads whatever was typed into the address bar in the active tab. The
+    /// history gets the page once it has loaded, at the URL it ended up at.
+    pub fn navigate(&mut self, input: &str) {
+        if let Some(url) = self.url_fixup.fixup(input) {
+            self.load_in_active(NavigationKind::NewEntry(Transition::Typed), url);
+        }
+    }
+
+    /// Goes `delta` entries back (negative) or forward in the active tab.
+    /// An entry the page reached without loading, such as one it added with
+    /// `history.pushState`, is left to the page to go back to.
+    pub fn go(&mut self, delta: isize) {
+        let history = self.history();
+        let same_document = history.same_document(delta);
+        let Some(entry) = history.go(delta) else {
+            return;
+        };
+        let kind = NavigationKind::CurrentEntry(Transition::BackForward);
+        if same_document {
+            let tab = self.tabs.active_mut();
+            if let Some(view) = &tab.view {
+                // The page reports arriving, wherever it arrives, which ends
+                // the navigation; if the engine loads the page after all,
+                // the load commits it.
+                tab.navigation.begin_same_document(kind, entry.url);
+                view.evaluate_script(&format!("history.go({})", delta));
+            }
+        } else {
+            self.load_in_active(kind, entry.url);
+        }
+        self.push_event(&self.navigation_state());
+    }
+
+    pub fn go_back(&mut self) {
+        self.go(-1);
+    }
+
+    pub fn go_forward(&mut self) {
+        self.go(1);
+    }
+
+    /// The entries of the active tab behind its Back button, or its Forward
+    /// one, for the toolbar to list.
+    pub fn history_menu(&self, forward: bool) -> CoreEvent {
+        let history = self.history();
+        let index = history.index();
+        let entries = history.entries().into_iter().enumerate();
+        let mut entries: Vec<HistoryMenuEntry> = entries
+            .filter(|(i, _)| if forward { *i > index } else { *i < index })
+            .map(|(i, entry)| HistoryMenuEntry {
+                delta: i as isize - index as isize,
+                title: entry
+                    .title
+                    .filter(|title| !title.trim().is_empty())
+                    .unwrap_or_else(|| entry.url.clone()),
+                url: entry.url,
+            })
+            .collect();
+        if !forward {
+            entries.reverse();
+        }
+        CoreEvent::HistoryMenu { forward, entries }
+    }
+
+    pub fn reload(&mut self, bypass_cache: bool) {
+        let tab = self.tabs.active_mut();
+        if let Some(view) = &tab.view {
+            let url = tab.history.current_url();
+            tab.navigation
+                .begin(NavigationKind::CurrentEntry(Transition::Reload), url);
+            view.reload(bypass_cache);
+            tab.history.reloaded();
+        }
+    }
+
+    pub fn stop(&mut self) {
+        let tab = self.tabs.active_mut();
+        if tab.load_state.stop() {
+            tab.navigation.cancel();
+            if let Some(view) = &tab.view {
+                view.evaluate_script("window.stop()");
+            }
+            self.push_event(&self.navigation_state());
+        }
+    }
+
+    /// Applies a tab command. Closing the last tab closes the window
+    /// instead, so there is always a tab to show.
+    pub fn tab_command(&mut self, command: TabCommand) -> Effect {
+        let closes_last = self.tabs.len() == 1
+            && matches!(command, TabCommand::Close(_) | TabCommand::CloseActive);
+        if closes_last {
+            self.request_close()
+        } else if self.tabs.apply(command) {
+            Effect::TabsChanged
+        } else {
+            Effect::None
+        }
+    }
+
+    fn bookmarks_changed(&self) {
+        self.save_bookmarks();
+        self.sync_sidebar();
+    }
+
+    /// Handles one message posted by the toolbar or the sidebar, replying
+    /// with an ack or an error and refreshing the toolbar.
+    pub fn handle_toolbar_message(&mut self, body: &str) -> Effect {
+        let request = match ipc::parse_request(body) {
+            Ok(request) => request,
+            Err(err) => {
+                self.push_event(&CoreEvent::Error {
+                    id: err.request_id(),
+                    message: err.to_string(),
+                });
+                return Effect::None;
+            }
+        };
+        let mut effect = Effect::None;
+        match request.command {
+            ToolbarCommand::Back => self.go_back(),
+            ToolbarCommand::Forward => self.go_forward(),
+            ToolbarCommand::GoBy { delta } => self.go(delta),
+            ToolbarCommand::HistoryMenu { forward } => self.push_event(&self.history_menu(forward)),
+            ToolbarCommand::Go { url } | ToolbarCommand::OpenUrl { url } => self.navigate(&url),
+            ToolbarCommand::Reload => self.reload(false),
+            ToolbarCommand::HardReload => self.reload(true),
+            ToolbarCommand::Stop => self.stop(),
+            ToolbarCommand::NewTab => effect = self.tab_command(TabCommand::Open(self.homepage())),
+            ToolbarCommand::CloseTab { index } => {
+                effect = self.tab_command(TabCommand::Close(index))
+            }
+            ToolbarCommand::SelectTab { index } => {
+                effect = self.tab_command(TabCommand::Select(index))
+            }
+            ToolbarCommand::MoveTab { from, to } => {
+                effect = self.tab_command(TabCommand::Move { from, to })
+            }
+            ToolbarCommand::ToggleBookmark => {
+                self.toggle_bookmark();
+                self.sync_sidebar();
+            }
+            ToolbarCommand::ToggleSidebar => effect = Effect::ToggleSidebar,
+            ToolbarCommand::RestoreSession => {
+                if let Some(session) = self.previous_session.take() {
+                    effect = self.restore_session(session);
+                }
+            }
+            ToolbarCommand::DismissSession => self.previous_session = None,
+            ToolbarCommand::CloseWindow => effect = self.close_effect(),
+            ToolbarCommand::NewWindow => effect = self.new_window(),
+            ToolbarCommand::MoveTabToNewWindow { index } => {
+                effect = self.move_tab_to_new_window(index)
+            }
+            ToolbarCommand::OpenPopup { url } => {
+                effect = self.open_popup(popups::allow(self.config.new_window_policy, url))
+            }
+            ToolbarCommand::RemoveBookmark { bookmark } => {
+                if self.bookmarks.remove(BookmarkId(bookmark)).is_some() {
+                    self.bookmarks_changed();
+                }
+            }
+            ToolbarCommand::ImportBookmarks { html } => {
+                let root = self.bookmarks.root_id();
+                let message = match self.bookmarks.import_netscape(&html, root) {
+                    Some(count) => {
+                        self.bookmarks_changed();
+                        format!("Imported {} bookmarks", count)
                     }
+                    None => "Could not import bookmarks".to_string(),
+                };
+                self.push_event(&CoreEvent::Notice { message });
+            }
+            ToolbarCommand::ExportBookmarks => {
+                let message = match self.export_bookmarks() {
+                    Ok(path) => format!("Bookmarks exported to {}", path.display()),
+                    Err(err) => format!("Bookmark export failed: {}", err),
+                };
+                self.push_event(&CoreEvent::Notice { message });
+            }
+            command @ (ToolbarCommand::SearchHistory { .. }
+            | ToolbarCommand::RemoveVisit { .. }
+            | ToolbarCommand::ClearHistory { .. }) => {
+                if let Some(event) = self.history_command(command) {
+                    self.push_event(&event);
+                }
+            }
+        }
+        if let Some(id) = request.id {
+            self.push_event(&CoreEvent::Ack { id });
+        }
+        self.sync_toolbar();
+        effect
+    }
+
+    /// Carries out a command about the global history, returning the reply
+    /// for whoever sent it.
+    fn history_command(&mut self, command: ToolbarCommand) -> Option<CoreEvent> {
+        match command {
+            ToolbarCommand::SearchHistory { query } => Some(CoreEvent::Visits {
+                visits: self.visits.search(&query),
+            }),
+            ToolbarCommand::RemoveVisit { visit } => {
+                self.visits.remove(VisitId(visit));
+                None
+            }
+            ToolbarCommand::ClearHistory { range } => {
+                let count = self.visits.clear(range);
+                Some(CoreEvent::Notice {
+                    message: format!("Removed {} visits", count),
                 })
-                .build(&window)
-                .unwrap(),
-        );
+            }
+            _ => None,
+        }
+    }
 
-        let content_clone = webview.clone();
-        let hist = self.history.clone();
-        let toolbar_bounds = wry::Rect {
-            position: LogicalPosition::new(0.0, 0.0).into(),
-            size: LogicalSize::new(size.width as f64, toolbar_height).into(),
-        };
-
-        const TOOLBAR_HTML: &str = r#"<input id='addr' style='width:60%'>
-<button id='back'>Back</button>
-<button id='forward'>Forward</button>
-<script>
-document.getElementById('back').addEventListener('click',()=>window.ipc.postMessage('back'));
-document.getElementById('forward').addEventListener('click',()=>window.ipc.postMessage('forward'));
-document.getElementById('addr').addEventListener('keydown',e=>{if(e.key==='Enter'){window.ipc.postMessage('go:'+e.target.value)}});
-</script>"#;
-
-        let toolbar = WebViewBuilder::new()
-            .with_html(TOOLBAR_HTML)
-            .with_bounds(toolbar_bounds)
-            .with_ipc_handler(move |req| {
-                let body = req.body();
-                if body == "back" {
-                    if let Some(url) = hist.back() {
-                        content_clone.load_url(&url).ok();
-                    }
-                } else if body == "forward" {
-                    if let Some(url) = hist.forward() {
-                        content_clone.load_url(&url).ok();
+    /// Handles a message posted by the page at `origin` in tab `id`. Only
+    /// the history page may post, and only history commands; replies go to
+    /// that page. Messages sent while the tab is on its way to another page
+    /// are dropped, as the page they came from may be that one already.
+    pub fn handle_page_message(&mut self, id: TabId, origin: &str, body: &str) {
+        let settled = self
+            .tabs
+            .by_id(id)
+            .is_some_and(|tab| tab.navigation.pending_url().is_none());
+        if !settled || !visits::is_history_page(origin) {
+            return;
+        }
+        let mut replies = Vec::new();
+        match ipc::parse_request(body) {
+            Ok(request) => {
+                match request.command {
+                    command @ (ToolbarCommand::SearchHistory { .. }
+                    | ToolbarCommand::RemoveVisit { .. }
+                    | ToolbarCommand::ClearHistory { .. }) => {
+                        replies.extend(self.history_command(command));
                     }
-                } else if let Some(rest) = body.strip_prefix("go:") {
-                    content_clone.load_url(rest).ok();
-                    hist.push(rest.to_string());
+                    _ => replies.push(CoreEvent::Error {
+                        id: request.id,
+                        message: "pages may only send history commands".into(),
+                    }),
                 }
-            })
-            .build(&window)
-            .unwrap();
+                if let Some(id) = request.id {
+                    replies.push(CoreEvent::Ack { id });
+                }
+            }
+            Err(err) => replies.push(CoreEvent::Error {
+                id: err.request_id(),
+                message: err.to_string(),
+            }),
+        }
+        if let Some(view) = self.tabs.by_id(id).and_then(|tab| tab.view.as_ref()) {
+            for event in &replies {
+                view.evaluate_script(&ipc::event_script(event));
+            }
+        }
+    }
+
+    /// Handles a key press in the window, returning what the window has to
+    /// do about it. Unbound keys are left to the page.
+    pub fn handle_key(&mut self, chord: Chord) -> Effect {
+        match self.keys.dispatch(chord) {
+            Dispatch::Action(action) => self.perform(action),
+            Dispatch::Pending | Dispatch::Unbound => Effect::None,
+        }
+    }
+
+    pub fn perform(&mut self, action: Action) -> Effect {
+        let command = match action {
+            Action::NewTab => TabCommand::Open(self.homepage()),
+            Action::CloseTab => TabCommand::CloseActive,
+            Action::NextTab => TabCommand::Next,
+            Action::PreviousTab => TabCommand::Previous,
+            Action::SelectTab(index) => TabCommand::Select(index),
+            Action::SelectLastTab => TabCommand::SelectLast,
+            Action::MoveTabLeft => TabCommand::MoveActiveLeft,
+            Action::MoveTabRight => TabCommand::MoveActiveRight,
+            Action::ToggleSidebar => return Effect::ToggleSidebar,
+            Action::ShowHistory => TabCommand::Open(HISTORY_PAGE_URL.into()),
+            Action::NewWindow => return self.new_window(),
+            Action::MoveTabToNewWindow => {
+                return self.move_tab_to_new_window(self.tabs.active_index())
+            }
+            Action::Back => {
+                self.go_back();
+                return Effect::None;
+            }
+            Action::Forward => {
+                self.go_forward();
+                return Effect::None;
+            }
+            Action::Reload | Action::HardReload => {
+                self.reload(action == Action::HardReload);
+                return Effect::None;
+            }
+            Action::Stop => {
+                self.stop();
+                return Effect::None;
+            }
+            Action::ToggleBookmark => {
+                self.toggle_bookmark();
+                self.sync_sidebar();
+                self.push_event(&self.navigation_state());
+                return Effect::None;
+            }
+        };
+        self.tab_command(command)
+    }
 
-        self.window = Some(window);
-        self.webview = Some(webview);
-        self.toolbar = Some(toolbar);
+    /// The view of tab `id` started loading `url`.
+    pub fn page_load_started(&mut self, id: TabId, url: String) {
+        if let Some(tab) = self.tabs.by_id_mut(id) {
+            tab.navigation.started(url.clone());
+            tab.load_state.started(url);
+            tab.title = None;
+            self.sync_toolbar();
+        }
+    }
+
+    /// The view of tab `id` finished loading `url`, which commits the
+    /// navigation under way there, if any.
+    pub fn page_load_finished(&mut self, id: TabId, url: String) {
+        let Some(tab) = self.tabs.by_id_mut(id) else {
+            return;
+        };
+        // A load the user stopped has nothing left to commit.
+        if !tab.load_state.finished() {
+            return;
+        }
+        if let Some(commit) = tab.navigation.finished(url) {
+            self.commit_navigation(id, commit);
+        }
+        self.sync_toolbar();
+    }
+
+    /// Records a finished navigation in tab `id`: a new entry, or the
+    /// current one at its final URL, with every redirect hop in the global
+    /// history. A page reached by going back or forward is scrolled to
+    /// where it was left.
+    fn commit_navigation(&mut self, id: TabId, commit: Commit) {
+        let Some(tab) = self.tabs.by_id(id) else {
+            return;
+        };
+        match commit.kind {
+            NavigationKind::NewEntry(transition) => {
+                tab.history.visit(commit.url.clone(), transition)
+            }
+            NavigationKind::CurrentEntry(_) => tab.history.replace_current(commit.url.clone()),
+        }
+        tab.history.loaded_document();
+        let entry = tab.history.current();
+        let back_forward = NavigationKind::CurrentEntry(Transition::BackForward);
+        if let Some(view) = &tab.view {
+            if commit.kind == back_forward && entry.scroll != Default::default() {
+                view.evaluate_script(&format!(
+                    "window.scrollTo({}, {})",
+                    entry.scroll.x, entry.scroll.y
+                ));
+            }
+        }
+        for hop in commit.redirects {
+            self.visits.record(hop, Transition::Redirect);
+        }
+        self.visits.record(commit.url, commit.kind.transition());
     }
 
-    fn window_event(
+    /// The page in tab `id` moved to `url` without loading a new document.
+    /// Reports for another site, or while the page is loading, are ignored:
+    /// the load reports where the page ends up. A page asked to go back or
+    /// forward that arrives somewhere else is followed there.
+    pub fn same_document_navigation(
         &mut self,
-        _event_loop: &ActiveEventLoop,
-        _id: WindowId,
-        event: WindowEvent,
+        id: TabId,
+        kind: SameDocumentNavigation,
+        url: String,
     ) {
-        match event {
-            WindowEvent::KeyboardInput { event, .. } => {
-                if event.state == ElementState::Pressed {
-                    match event.logical_key {
-                        Key::Named(NamedKey::BrowserBack)
-                        | Key::Named(NamedKey::ArrowLeft)
-                            if self.modifiers.alt_key() =>
-                        {
-                            if let Some(url) = self.history.back() {
-                                if let Some(webview) = &self.webview {
-                                    webview.load_url(&url).ok();
-                                }
-                            }
-                        }
-                        Key::Named(NamedKey::BrowserForward)
-                        | Key::Named(NamedKey::ArrowRight)
-                            if self.modifiers.alt_key() =>
-                        {
-                            if let Some(url) = self.history.forward() {
-                                if let Some(webview) = &self.webview {
-                                    webview.load_url(&url).ok();
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+        let Some(tab) = self.tabs.by_id_mut(id) else {
+            return;
+        };
+        let asked = tab.navigation.is_same_document();
+        match tab.navigation.pending_url() {
+            // The page went back or forward as asked.
+            Some(pending)
+                if asked && kind == SameDocumentNavigation::Traverse && pending == url =>
+            {
+                tab.navigation.cancel();
+                self.sync_toolbar();
+                return;
+            }
+            Some(_) if !asked => return,
+            // Its history and ours disagree; the page knows where it is.
+            Some(_) => tab.navigation.cancel(),
+            None => {}
+        }
+        let history = Rc::clone(&tab.history);
+        let current = history.current_url();
+        if !navigation::same_origin(&current, &url) {
+            self.sync_toolbar();
+            return;
+        }
+        let neighbour = |delta: isize| {
+            let index = history.index().checked_add_signed(delta);
+            let entries = history.entries();
+            index
+                .and_then(|index| entries.get(index))
+                .map(|entry| &entry.url)
+                == Some(&url)
+                && history.same_document(delta)
+        };
+        match kind {
+            // The page went back or forward by itself.
+            SameDocumentNavigation::Traverse if neighbour(-1) => drop(history.go(-1)),
+            SameDocumentNavigation::Traverse if neighbour(1) => drop(history.go(1)),
+            // Only `history.pushState` adds an entry for the URL shown already.
+            SameDocumentNavigation::Replace | SameDocumentNavigation::Traverse
+                if url == current =>
+            {
+                self.sync_toolbar();
+                return;
+            }
+            SameDocumentNavigation::Replace => history.replace_current(url),
+            SameDocumentNavigation::Push | SameDocumentNavigation::Traverse => {
+                history.visit_same_document(url.clone());
+                self.visits.record(url, Transition::Link);
+            }
+        }
+        self.sync_toolbar();
+    }
+
+    pub fn title_changed(&mut self, id: TabId, title: String) {
+        if let Some(tab) = self.tabs.by_id_mut(id) {
+            tab.history.set_title(title.clone());
+            self.visits
+                .set_title(&tab.history.current_url(), title.clone());
+            tab.title = Some(title);
+            self.sync_toolbar();
+        }
+    }
+
+    /// The page shown in tab `id`, at `url`, was scrolled. Reports for a
+    /// page the tab has left, or is leaving, are ignored.
+    pub fn scrolled(&mut self, id: TabId, url: &str, scroll: ScrollPosition) {
+        if let Some(tab) = self.tabs.by_id(id) {
+            let leaving = tab.navigation.pending_url().is_some();
+            if !leaving && tab.history.current_url() == url {
+                tab.history.set_scroll(scroll);
+            }
+        }
+    }
+
+    /// Re-reads the config file after it changed, keeping the current
+    /// settings if it no longer parses. The caller re-applies the layout.
+    pub fn reload_config(&mut self) {
+        let Some(path) = self.config_path.clone() else {
+            return;
+        };
+        let config = match Config::load_or_default(&path, false) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("Keeping the current settings: {}", err);
+                return;
+            }
+        };
+        let zoom_changed = config.default_zoom != self.config.default_zoom;
+        self.apply_config(config);
+        if zoom_changed {
+            for view in self.all_tabs().filter_map(|tab| tab.view.as_ref()) {
+                view.zoom(self.config.default_zoom);
+            }
+        }
+        self.push_event(&CoreEvent::Notice {
+            message: "Settings reloaded".into(),
+        });
+    }
+
+    /// A page in tab `id` asked to open a new window, which is opened as
+    /// the config says, or blocked with a notice in the toolbar.
+    pub fn new_window_requested(&mut self, id: TabId, request: PopupRequest) -> Effect {
+        let Some(tab) = self.tabs.by_id(id) else {
+            return Effect::None;
+        };
+        let opener = tab.history.current_url();
+        let config = &self.config;
+        let decision = popups::decide(
+            config.new_window_policy,
+            config.block_popups,
+            &opener,
+            request,
+        );
+        self.open_popup(decision)
+    }
+
+    fn open_popup(&mut self, decision: PopupDecision) -> Effect {
+        match decision {
+            PopupDecision::OpenTab(url) => self.tab_command(TabCommand::Open(url)),
+            PopupDecision::OpenWindow(url) => self.open_window(Tabs::new(History::new(url))),
+            PopupDecision::Blocked(url) => {
+                self.push_event(&CoreEvent::PopupBlocked { url });
+                Effect::None
+            }
+            PopupDecision::Ignored => Effect::None,
+        }
+    }
+
+    /// The user asked to close the current window. Closing several tabs or
+    /// cancelling downloads is confirmed through the toolbar first, unless
+    /// there is no toolbar to ask with.
+    pub fn request_close(&mut self) -> Effect {
+        let tabs = self.tabs.len();
+        let downloads = self.active_downloads;
+        if (tabs > 1 || downloads > 0) && self.toolbar.is_some() && !self.kiosk {
+            self.push_event(&CoreEvent::ConfirmClose { tabs, downloads });
+            Effect::None
+        } else {
+            self.close_effect()
+        }
+    }
+
+    /// Registers `hook` to run when the browser shuts down, before anything
+    /// is saved or torn down. Hooks run in the order they were added.
+    pub fn on_shutdown(&mut self, hook: impl FnOnce(&mut Browser) + 'static) {
+        self.shutdown_hooks.push(Box::new(hook));
+    }
+
+    /// The orderly part of closing the last window: runs the shutdown hooks,
+    /// saves everything the profile keeps and drops every view. The caller
+    /// then ends its event loop. The config file belongs to the user and is
+    /// never written.
+    pub fn shutdown(&mut self) {
+        for hook in std::mem::take(&mut self.shutdown_hooks) {
+            hook(self);
+        }
+        self.save_history();
+        self.save_session(true);
+        self.visits.flush();
+        self.save_bookmarks();
+        self.drop_views();
+    }
+
+    /// Snapshot of the windows and their tabs. `clean_exit` marks the
+    /// snapshot taken on shutdown.
+    pub fn session(&self, clean_exit: bool) -> Session {
+        Session {
+            windows: self.window_sessions(),
+            clean_exit,
+        }
+    }
+
+    /// Writes a session snapshot into the profile, if there is one.
+    pub fn save_session(&self, clean_exit: bool) {
+        if let Some(profile) = &self.profile {
+            if let Err(err) = self.session(clean_exit).save(&profile.session_path()) {
+                eprintln!("Failed to save the session: {}", err);
             }
-            WindowEvent::ModifiersChanged(mods) => {
-                self.modifiers = mods.state();
+        }
+    }
+
+    /// Takes the periodic snapshot of the session. While the last session
+    /// is still on offer the file keeps holding it, so that crashing again
+    /// before the user answers does not lose it.
+    pub fn snapshot_session(&self) {
+        if self.previous_session.is_none() {
+            self.save_session(false);
+        }
+    }
+
+    /// Replaces the current window's tabs with those of the session's first
+    /// window and takes over its geometry. The session's other windows open
+    /// alongside.
+    pub fn restore_session(&mut self, session: Session) -> Effect {
+        self.previous_session = None;
+        let mut windows = session.windows.into_iter();
+        let Some(first) = windows.next() else {
+            return Effect::None;
+        };
+        if !self.tabs.replace_all(first.tabs, first.active) {
+            return Effect::None;
+        }
+        restore_titles(&mut self.tabs);
+        self.geometry = first.geometry.or(self.geometry);
+        let mut effect = Effect::TabsChanged;
+        for window in windows {
+            let Some(mut tabs) = Tabs::restored(window.tabs, window.active) else {
+                continue;
+            };
+            tabs.set_history_capacity(self.config.tab_history_capacity);
+            restore_titles(&mut tabs);
+            let layout = LayoutSpec {
+                toolbar_height: self.layout.toolbar_height,
+                ..LayoutSpec::default()
+            };
+            let mut restored = BrowserWindow::new(tabs, layout);
+            restored.geometry = window.geometry;
+            self.other_windows.push(restored);
+            effect = Effect::WindowsChanged;
+        }
+        effect
+    }
+
+    /// The offer to restore the last session, while there is one.
+    pub fn session_offer(&self) -> Option<CoreEvent> {
+        let session = self.previous_session.as_ref()?;
+        Some(CoreEvent::SessionAvailable {
+            tabs: session.windows.iter().map(|window| window.tabs.len()).sum(),
+            crashed: !session.clean_exit,
+        })
+    }
+
+    /// Persists the active tab's history into the profile, if there is one,
+    /// for the next launch to reopen. Only done on shutdown: while the
+    /// browser runs, the session snapshots keep every tab's history.
+    pub fn save_history(&self) {
+        if let Some(profile) = &self.profile {
+            if let Err(err) = self.history().save(&profile.history_path()) {
+                eprintln!("Failed to save history: {}", err);
             }
-            WindowEvent::CloseRequested => std::process::exit(0),
-            _ => {}
         }
     }
 }
 
-#[cfg(feature = "browser")]
-pub fn run(initial_url: String) -> Result<(), Box<dyn std::error::Error>> {
-    let event_loop = EventLoop::new().unwrap();
-    let mut browser = Browser {
-        window: None,
-        webview: None,
-        toolbar: None,
-        history: Rc::new(History::new(initial_url)),
-        modifiers: ModifiersState::default(),
+/// Opens the profile a launch asks for. Private launches have none; a
+/// `--profile` directory that cannot be opened is an error, while a broken
+/// default profile only costs persistence.
+fn open_profile(options: &LaunchOptions) -> std::io::Result<Option<Profile>> {
+    if options.private {
+        return Ok(None);
+    }
+    if let Some(dir) = &options.profile {
+        return Profile::open(dir).map(Some);
+    }
+    match Profile::open_default() {
+        Ok(profile) => Ok(Some(profile)),
+        Err(err) => {
+            eprintln!("Running without a profile: {}", err);
+            Ok(None)
+        }
+    }
+}
+
+/// Reads `--config`, or else the profile's config file if there is one.
+fn load_config(
+    options: &LaunchOptions,
+    profile: Option<&Profile>,
+) -> Result<(Config, Option<PathBuf>), PersistError> {
+    let path = options
+        .config
+        .clone()
+        .or_else(|| profile.map(Profile::config_path));
+    let config = match &path {
+        Some(path) => Config::load_or_default(path, options.config.is_some())?,
+        None => Config::default(),
     };
+    Ok((config, path))
+}
+
+#[cfg(feature = "browser")]
+pub fn run(options: LaunchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if options.headless {
+        return run_headless(options);
+    }
+    let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+    let profile = open_profile(&options)?;
+    let (config, config_path) = load_config(&options, profile.as_ref())?;
+    let mut browser = Browser::launch(options, profile, config);
+    let proxy = event_loop.create_proxy();
+    if let Some(path) = &config_path {
+        gui::watch_config(ConfigWatcher::new(path), proxy.clone());
+    }
+    gui::save_session_periodically(proxy.clone());
+    browser.config_path = config_path;
+    browser.proxy = Some(proxy);
     event_loop.run_app(&mut browser).unwrap();
     Ok(())
 }
 
 #[cfg(not(feature = "browser"))]
-pub fn run(initial_url: String) -> Result<(), Box<dyn std::error::Error>> {
-    eprintln!("Headless mode: would navigate to {}", initial_url);
-    eprintln!("Browser features not enabled. Build with --features browser to run the GUI.");
-    Ok(())
+pub fn run(options: LaunchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    run_headless(options)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::History;
-
-    #[test]
-    fn history_navigation() {
-        let history = History::new("a".into());
-        history.push("b".into());
-        history.push("c".into());
-
-        assert_eq!(history.current().as_deref(), Some("c"));
-
-        assert_eq!(history.back(), Some("b".into()));
-        assert_eq!(history.current().as_deref(), Some("b"));
-        assert_eq!(history.back(), Some("a".into()));
-        assert_eq!(history.back(), None);
-        assert_eq!(history.current().as_deref(), Some("a"));
-
-        assert_eq!(history.forward(), Some("b".into()));
-        assert_eq!(history.forward(), Some("c".into()));
-        assert_eq!(history.forward(), None);
-        assert_eq!(history.current().as_deref(), Some("c"));
-    }
+/// Runs the launch's navigation script without a window and prints the
+/// resulting state as JSON on stdout.
+fn run_headless(options: LaunchOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let script = match options.script.as_deref() {
+        Some(path) if path == Path::new("-") => std::io::read_to_string(std::io::stdin())?,
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?,
+        None => String::new(),
+    };
+    let commands = headless::parse_script(&script)?;
+    // Scripted runs leave the user's browsing data alone: only a profile
+    // named with `--profile` is read and saved.
+    let profile = match options.profile {
+        Some(_) => open_profile(&options)?,
+        None => None,
+    };
+    let (config, _) = load_config(&options, profile.as_ref())?;
+    let mut driver = headless::Driver::new(Browser::launch(options, profile, config));
+    driver.run_script(&commands);
+    driver.shutdown();
+    println!("{}", serde_json::to_string_pretty(&driver.report())?);
+    Ok(())
 }
@@ -0,0 +1,86 @@
+/// Per-tab page loading state, driven by the content view's page load
+/// events and by the user stopping a load.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LoadState {
+    /// Nothing loading; the last load (if any) finished.
+    #[default]
+    Idle,
+    Loading {
+        url: String,
+    },
+    /// The user stopped a load before it finished.
+    Stopped {
+        url: String,
+    },
+}
+
+impl LoadState {
+    pub fn is_loading(&self) -> bool {
+        matches!(self, LoadState::Loading { .. })
+    }
+
+    /// A page load started, whatever the previous state.
+    pub fn started(&mut self, url: String) {
+        *self = LoadState::Loading { url };
+    }
+
+    /// A page load finished. Returns `false` when the load had been stopped,
+    /// in which case the state stays `Stopped`.
+    pub fn finished(&mut self) -> bool {
+        match self {
+            LoadState::Stopped { .. } => false,
+            _ => {
+                *self = LoadState::Idle;
+                true
+            }
+        }
+    }
+
+    /// The user pressed stop. Returns whether there was a load to stop.
+    pub fn stop(&mut self) -> bool {
+        match std::mem::take(self) {
+            LoadState::Loading { url } => {
+                *self = LoadState::Stopped { url };
+                true
+            }
+            other => {
+                *self = other;
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_cycle() {
+        let mut state = LoadState::default();
+        assert!(!state.is_loading());
+        state.started("a".into());
+        assert!(state.is_loading());
+        assert!(state.finished());
+        assert_eq!(state, LoadState::Idle);
+    }
+
+    #[test]
+    fn stop_only_applies_while_loading() {
+        let mut state = LoadState::default();
+        assert!(!state.stop());
+        assert_eq!(state, LoadState::Idle);
+
+        state.started("a".into());
+        assert!(state.stop());
+        assert_eq!(state, LoadState::Stopped { url: "a".into() });
+        assert!(!state.is_loading());
+
+        // The engine may still report the aborted load as finished.
+        assert!(!state.finished());
+        assert_eq!(state, LoadState::Stopped { url: "a".into() });
+
+        state.started("b".into());
+        assert!(state.is_loading());
+    }
+}
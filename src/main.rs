@@ -1,6 +1,28 @@
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let url = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "https://example.com".to_string());
-    Ok(wrybrowser::run(url)?)
+use std::process::ExitCode;
+
+use wrybrowser::cli::{self, Command};
+
+fn main() -> ExitCode {
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Launch(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("{}", cli::version());
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("wrybrowser: {}\n\n{}", err, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match wrybrowser::run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("wrybrowser: {}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,259 @@
+use serde::Deserialize;
+use url::Url;
+
+use crate::Transition;
+
+/// What a navigation does to the tab's history once it commits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavigationKind {
+    /// Adds an entry for the page, reached this way.
+    NewEntry(Transition),
+    /// Shows the current entry again: after going back or forward, on a
+    /// reload, or in a new view. Reached this way.
+    CurrentEntry(Transition),
+}
+
+impl NavigationKind {
+    pub fn transition(self) -> Transition {
+        match self {
+            NavigationKind::NewEntry(transition) | NavigationKind::CurrentEntry(transition) => {
+                transition
+            }
+        }
+    }
+}
+
+/// How a page moved to another URL without loading a new document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SameDocumentNavigation {
+    /// `history.pushState`.
+    Push,
+    /// `history.replaceState`.
+    Replace,
+    /// The URL changed some other way: going back or forward within the
+    /// page, or following a link to an anchor in it.
+    Traverse,
+}
+
+/// Whether `a` and `b` have the same scheme, host and port, so a page at
+/// one may move to the other within the same document.
+pub(crate) fn same_origin(a: &str, b: &str) -> bool {
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => {
+            a.scheme() == b.scheme()
+                && a.host_str() == b.host_str()
+                && a.port_or_known_default() == b.port_or_known_default()
+        }
+        _ => false,
+    }
+}
+
+/// A navigation that finished: the page ended up at `url`, after passing
+/// through `redirects` (oldest first).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub url: String,
+    pub kind: NavigationKind,
+    pub redirects: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pending {
+    kind: NavigationKind,
+    /// Every URL the navigation went through so far, the requested one
+    /// first.
+    chain: Vec<String>,
+    /// Left to the page, which moves within its document, until the view
+    /// starts loading after all.
+    same_document: bool,
+}
+
+impl Pending {
+    fn reached(&mut self, url: String) {
+        if self.chain.last() != Some(&url) {
+            self.chain.push(url);
+        }
+    }
+}
+
+/// Tracks the navigation under way in one tab, so its history gets exactly
+/// one entry per navigation, for the URL the page finally landed on.
+///
+/// The browser announces the loads it asks for with [`begin`]; a load the
+/// view starts on its own (a link, a form, a script) is a new
+/// [`Transition::Link`] entry. Page load events then move the navigation
+/// along until [`finished`] hands back what to commit.
+///
+/// [`begin`]: NavigationController::begin
+/// [`finished`]: NavigationController::finished
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NavigationController {
+    pending: Option<Pending>,
+}
+
+impl NavigationController {
+    /// The browser asked the view to load `url`. Replaces any navigation
+    /// still under way, which the view abandons.
+    pub fn begin(&mut self, kind: NavigationKind, url: String) {
+        self.pending = Some(Pending {
+            kind,
+            chain: vec![url],
+            same_document: false,
+        });
+    }
+
+    /// The browser asked the page to move to `url` within its document.
+    /// The page reports where it arrives; should the view load it instead,
+    /// this goes on as a navigation [`begin`] started.
+    ///
+    /// [`begin`]: NavigationController::begin
+    pub fn begin_same_document(&mut self, kind: NavigationKind, url: String) {
+        self.begin(kind, url);
+        if let Some(pending) = &mut self.pending {
+            pending.same_document = true;
+        }
+    }
+
+    /// The view started loading `url`. Starting again before the load
+    /// finished is a redirect, by the server or by a script on the page; a
+    /// start with no navigation under way is the page navigating by itself.
+    pub fn started(&mut self, url: String) {
+        match &mut self.pending {
+            Some(pending) => {
+                pending.same_document = false;
+                pending.reached(url);
+            }
+            None => {
+                self.pending = Some(Pending {
+                    kind: NavigationKind::NewEntry(Transition::Link),
+                    chain: vec![url],
+                    same_document: false,
+                });
+            }
+        }
+    }
+
+    /// The view finished loading `url`. Returns the navigation to commit,
+    /// or `None` when none was under way, e.g. because it was stopped.
+    pub fn finished(&mut self, url: String) -> Option<Commit> {
+        let mut pending = self.pending.take()?;
+        pending.reached(url);
+        let url = pending.chain.pop()?;
+        Some(Commit {
+            url,
+            kind: pending.kind,
+            redirects: pending.chain,
+        })
+    }
+
+    /// Forgets the navigation under way, which will not commit.
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    /// The URL being navigated to, as last reported.
+    pub fn pending_url(&self) -> Option<&str> {
+        let pending = self.pending.as_ref()?;
+        pending.chain.last().map(String::as_str)
+    }
+
+    /// Whether the navigation under way is one the page makes within its
+    /// document, with no load started.
+    pub fn is_same_document(&self) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|pending| pending.same_document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPED: NavigationKind = NavigationKind::NewEntry(Transition::Typed);
+
+    #[test]
+    fn commits_the_final_url_once() {
+        let mut nav = NavigationController::default();
+        nav.begin(TYPED, "https://a.example/".into());
+        assert_eq!(nav.pending_url(), Some("https://a.example/"));
+        nav.started("https://a.example/".into());
+        assert_eq!(
+            nav.finished("https://a.example/".into()),
+            Some(Commit {
+                url: "https://a.example/".into(),
+                kind: TYPED,
+                redirects: Vec::new(),
+            })
+        );
+        assert_eq!(nav.finished("https://a.example/".into()), None);
+        assert_eq!(nav.pending_url(), None);
+    }
+
+    #[test]
+    fn records_redirect_hops() {
+        let mut nav = NavigationController::default();
+        nav.begin(TYPED, "http://a.example/".into());
+        nav.started("https://a.example/".into());
+        let commit = nav.finished("https://www.a.example/".into()).unwrap();
+        assert_eq!(commit.url, "https://www.a.example/");
+        assert_eq!(
+            commit.redirects,
+            ["http://a.example/", "https://a.example/"]
+        );
+    }
+
+    #[test]
+    fn page_initiated_loads_are_links() {
+        let mut nav = NavigationController::default();
+        nav.started("https://a.example/next".into());
+        let commit = nav.finished("https://a.example/next".into()).unwrap();
+        assert_eq!(commit.kind, NavigationKind::NewEntry(Transition::Link));
+
+        // Starting again mid-load redirects the navigation under way.
+        let back = NavigationKind::CurrentEntry(Transition::BackForward);
+        nav.begin(back, "https://a.example/".into());
+        nav.started("https://a.example/".into());
+        nav.started("https://b.example/".into());
+        let commit = nav.finished("https://b.example/".into()).unwrap();
+        assert_eq!(commit.kind, back);
+        assert_eq!(commit.redirects, ["https://a.example/"]);
+    }
+
+    #[test]
+    fn compares_origins() {
+        assert!(same_origin(
+            "https://a.example/x",
+            "https://a.example:443/y#z"
+        ));
+        assert!(same_origin("file:///tmp/a.html", "file:///tmp/a.html#b"));
+        assert!(!same_origin("https://a.example/", "http://a.example/"));
+        assert!(!same_origin("https://a.example/", "https://b.example/"));
+        assert!(!same_origin("https://a.example/", "not a url"));
+    }
+
+    #[test]
+    fn cancelled_navigations_do_not_commit() {
+        let mut nav = NavigationController::default();
+        nav.begin(TYPED, "https://a.example/".into());
+        nav.started("https://a.example/".into());
+        nav.cancel();
+        assert_eq!(nav.finished("https://a.example/".into()), None);
+    }
+
+    #[test]
+    fn same_document_navigations_become_loads_once_the_view_loads() {
+        let mut nav = NavigationController::default();
+        let back = NavigationKind::CurrentEntry(Transition::BackForward);
+        nav.begin_same_document(back, "https://a.example/#top".into());
+        assert!(nav.is_same_document());
+        nav.started("https://a.example/#top".into());
+        assert!(!nav.is_same_document());
+        assert_eq!(
+            nav.finished("https://a.example/#top".into()).unwrap().kind,
+            back
+        );
+        assert!(!nav.is_same_document());
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Errors raised while reading or writing a versioned profile file.
+#[derive(Debug)]
+pub enum PersistError {
+    Io(io::Error),
+    Corrupt {
+        path: PathBuf,
+        reason: String,
+    },
+    UnsupportedVersion {
+        path: PathBuf,
+        found: u32,
+        expected: u32,
+    },
+    /// The data could not be encoded for writing to `path`.
+    Encode {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(err) => write!(f, "I/O error: {}", err),
+            PersistError::Corrupt { path, reason } => {
+                write!(f, "{} is corrupt: {}", path.display(), reason)
+            }
+            PersistError::UnsupportedVersion {
+                path,
+                found,
+                expected,
+            } => write!(
+                f,
+                "{} has format version {}, expected {}",
+                path.display(),
+                found,
+                expected
+            ),
+            PersistError::Encode { path, reason } => {
+                write!(f, "cannot encode {}: {}", path.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PersistError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PersistError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PersistError {
+    fn from(err: io::Error) -> Self {
+        PersistError::Io(err)
+    }
+}
+
+impl PersistError {
+    /// True when the file simply does not exist yet.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, PersistError::Io(err) if err.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+#[derive(Serialize)]
+struct EnvelopeOut<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeIn<T> {
+    data: T,
+}
+
+/// Serializes `data` inside a `{"version": .., "data": ..}` envelope and
+/// writes it atomically to `path`.
+pub fn save<T: Serialize>(path: &Path, version: u32, data: &T) -> Result<(), PersistError> {
+    let bytes = serde_json::to_vec_pretty(&EnvelopeOut { version, data }).map_err(|err| {
+        PersistError::Encode {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        }
+    })?;
+    write_atomic(path, &bytes)
+}
+
+/// Reads a file written by [`save`], rejecting any version other than
+/// `expected`.
+pub fn load<T: DeserializeOwned>(path: &Path, expected: u32) -> Result<T, PersistError> {
+    let bytes = fs::read(path)?;
+    let corrupt = |err: serde_json::Error| PersistError::Corrupt {
+        path: path.to_path_buf(),
+        reason: err.to_string(),
+    };
+    let probe: VersionProbe = serde_json::from_slice(&bytes).map_err(corrupt)?;
+    if probe.version != expected {
+        return Err(PersistError::UnsupportedVersion {
+            path: path.to_path_buf(),
+            found: probe.version,
+            expected,
+        });
+    }
+    let envelope: EnvelopeIn<T> = serde_json::from_slice(&bytes).map_err(corrupt)?;
+    Ok(envelope.data)
+}
+
+/// Writes `bytes` to a sibling temporary file, syncs it and renames it over
+/// `path`, so readers never observe a half-written file.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), PersistError> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        fs::remove_file(&tmp).ok();
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-persist-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn round_trip_and_version_check() {
+        let path = temp_path("numbers.json");
+        save(&path, 3, &vec![1u32, 2, 3]).unwrap();
+        assert_eq!(load::<Vec<u32>>(&path, 3).unwrap(), vec![1, 2, 3]);
+
+        match load::<Vec<u32>>(&path, 4) {
+            Err(PersistError::UnsupportedVersion {
+                found: 3,
+                expected: 4,
+                ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        fs::write(&path, b"{not json").unwrap();
+        assert!(matches!(
+            load::<Vec<u32>>(&path, 3),
+            Err(PersistError::Corrupt { .. })
+        ));
+
+        fs::remove_file(&path).unwrap();
+        assert!(load::<Vec<u32>>(&path, 3).unwrap_err().is_not_found());
+
+        // JSON object keys must be strings.
+        let unencodable = std::collections::HashMap::from([((1u32, 2u32), 3u32)]);
+        assert!(matches!(
+            save(&path, 3, &unencodable),
+            Err(PersistError::Encode { .. })
+        ));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Where pages that ask for a new window (`target=_blank` links,
+/// `window.open`) are opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NewWindowPolicy {
+    #[default]
+    NewTab,
+    NewWindow,
+    /// Never open them.
+    Block,
+}
+
+/// A page asking for `url` to open in a new window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PopupRequest {
+    pub url: String,
+    /// Whether the user clicked or typed just before: a link click, or a
+    /// script reacting to one.
+    pub user_gesture: bool,
+}
+
+/// What becomes of a [`PopupRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PopupDecision {
+    OpenTab(String),
+    OpenWindow(String),
+    /// Not opened; the user is told and can open it anyway.
+    Blocked(String),
+    /// Not a page a popup may show, such as a `javascript:` URL.
+    Ignored,
+}
+
+/// Decides a popup request from a page at `opener`. With `block_popups`,
+/// only requests made in response to a user gesture get through.
+pub fn decide(
+    policy: NewWindowPolicy,
+    block_popups: bool,
+    opener: &str,
+    request: PopupRequest,
+) -> PopupDecision {
+    let local_opener = Url::parse(opener).is_ok_and(|url| url.scheme() == "file");
+    if !may_open(&request.url, local_opener) {
+        return PopupDecision::Ignored;
+    }
+    if policy == NewWindowPolicy::Block || (block_popups && !request.user_gesture) {
+        return PopupDecision::Blocked(request.url);
+    }
+    open(policy, request.url)
+}
+
+/// Opens a popup the user asked for after it was blocked. Under
+/// [`NewWindowPolicy::Block`] it opens in a new tab. Only popups [`decide`]
+/// blocked are offered, so a local file is taken to come from a local page.
+pub fn allow(policy: NewWindowPolicy, url: String) -> PopupDecision {
+    if !may_open(&url, true) {
+        return PopupDecision::Ignored;
+    }
+    open(policy, url)
+}
+
+fn open(policy: NewWindowPolicy, url: String) -> PopupDecision {
+    match policy {
+        NewWindowPolicy::NewWindow => PopupDecision::OpenWindow(url),
+        NewWindowPolicy::NewTab | NewWindowPolicy::Block => PopupDecision::OpenTab(url),
+    }
+}
+
+/// Web pages may be opened from anywhere, local files only from other
+/// local files.
+fn may_open(url: &str, local_opener: bool) -> bool {
+    Url::parse(url).is_ok_and(|url| match url.scheme() {
+        "http" | "https" => true,
+        "file" => local_opener,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(url: &str, user_gesture: bool) -> PopupRequest {
+        PopupRequest {
+            url: url.into(),
+            user_gesture,
+        }
+    }
+
+    const OPENER: &str = "https://opener.example/";
+
+    #[test]
+    fn follows_the_policy() {
+        let url = "https://a.example/";
+        assert_eq!(
+            decide(NewWindowPolicy::NewTab, true, OPENER, request(url, true)),
+            PopupDecision::OpenTab(url.into())
+        );
+        assert_eq!(
+            decide(NewWindowPolicy::NewWindow, true, OPENER, request(url, true)),
+            PopupDecision::OpenWindow(url.into())
+        );
+        assert_eq!(
+            decide(NewWindowPolicy::Block, false, OPENER, request(url, true)),
+            PopupDecision::Blocked(url.into())
+        );
+        assert_eq!(
+            allow(NewWindowPolicy::Block, url.into()),
+            PopupDecision::OpenTab(url.into())
+        );
+    }
+
+    #[test]
+    fn blocks_popups_without_a_gesture() {
+        let url = "https://ads.example/";
+        assert_eq!(
+            decide(NewWindowPolicy::NewTab, true, OPENER, request(url, false)),
+            PopupDecision::Blocked(url.into())
+        );
+        assert_eq!(
+            decide(
+                NewWindowPolicy::NewWindow,
+                false,
+                OPENER,
+                request(url, false)
+            ),
+            PopupDecision::OpenWindow(url.into())
+        );
+        for url in [
+            "javascript:alert(1)",
+            "data:text/html,x",
+            "about:blank",
+            "not a url",
+        ] {
+            assert_eq!(
+                decide(NewWindowPolicy::NewTab, false, OPENER, request(url, true)),
+                PopupDecision::Ignored
+            );
+            assert_eq!(
+                allow(NewWindowPolicy::NewTab, url.into()),
+                PopupDecision::Ignored
+            );
+        }
+    }
+
+    #[test]
+    fn only_local_pages_open_local_files() {
+        let file = "file:///etc/passwd";
+        assert_eq!(
+            decide(NewWindowPolicy::NewTab, false, OPENER, request(file, true)),
+            PopupDecision::Ignored
+        );
+        assert_eq!(
+            decide(
+                NewWindowPolicy::NewTab,
+                false,
+                "file:///home/me/index.html",
+                request(file, true)
+            ),
+            PopupDecision::OpenTab(file.into())
+        );
+    }
+}
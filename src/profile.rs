@@ -0,0 +1,85 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "wrybrowser";
+
+/// A per-user directory holding everything the browser persists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    root: PathBuf,
+}
+
+impl Profile {
+    /// Opens (creating if needed) the profile rooted at `root`.
+    pub fn open(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    /// Opens the platform default profile directory.
+    pub fn open_default() -> io::Result<Self> {
+        let root = default_dir().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no home directory for the default profile",
+            )
+        })?;
+        Self::open(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn history_path(&self) -> PathBuf {
+        self.root.join("history.json")
+    }
+
+    /// The global history: every visit, across tabs and sessions.
+    pub fn visits_path(&self) -> PathBuf {
+        self.root.join("visits.log")
+    }
+
+    /// Snapshot of the open windows and tabs, for restoring them.
+    pub fn session_path(&self) -> PathBuf {
+        self.root.join("session.json")
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.root.join("config.toml")
+    }
+
+    pub fn bookmarks_path(&self) -> PathBuf {
+        self.root.join("bookmarks.json")
+    }
+
+    /// Where "Export bookmarks" writes the Netscape HTML file.
+    pub fn bookmarks_export_path(&self) -> PathBuf {
+        self.root.join("bookmarks.html")
+    }
+}
+
+/// Platform data directory for the default profile, e.g.
+/// `~/.local/share/wrybrowser/default` on Linux.
+pub fn default_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(APP_DIR).join("default"))
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let from_env = |key: &str| {
+        env::var_os(key)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+    if cfg!(target_os = "windows") {
+        from_env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        from_env("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        from_env("XDG_DATA_HOME")
+            .or_else(|| from_env("HOME").map(|home| home.join(".local").join("share")))
+    }
+}
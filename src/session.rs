@@ -0,0 +1,241 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::persist::{self, PersistError};
+use crate::History;
+
+/// On-disk format version of the session file.
+pub const SESSION_FORMAT_VERSION: u32 = 2;
+
+/// Where a window was and how large, in logical pixels. The position is
+/// unknown on platforms that do not report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub position: Option<(i32, i32)>,
+    pub size: (u32, u32),
+}
+
+/// One tab of a session: its whole history, scroll offsets included.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabSession {
+    pub history: History,
+    /// The tab's own history capacity, if it does not follow the config.
+    pub history_capacity: Option<usize>,
+}
+
+impl From<History> for TabSession {
+    fn from(history: History) -> Self {
+        Self {
+            history,
+            history_capacity: None,
+        }
+    }
+}
+
+/// One window of a session: its tabs, left to right, and which one was
+/// active.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowSession {
+    pub geometry: Option<WindowGeometry>,
+    pub tabs: Vec<TabSession>,
+    pub active: usize,
+}
+
+/// Everything needed to bring the browser back as it was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub windows: Vec<WindowSession>,
+    /// Set by a clean shutdown. Snapshots written while the browser runs
+    /// leave it unset, so finding it unset at startup means a crash.
+    pub clean_exit: bool,
+}
+
+impl Session {
+    /// Writes the session atomically to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), PersistError> {
+        persist::save(path, SESSION_FORMAT_VERSION, self)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PersistError> {
+        let session = match persist::load::<Self>(path, SESSION_FORMAT_VERSION) {
+            Err(PersistError::UnsupportedVersion { found: 1, .. }) => {
+                persist::load::<SessionV1>(path, 1)?.into()
+            }
+            result => result?,
+        };
+        session.validate().map_err(|reason| PersistError::Corrupt {
+            path: PathBuf::from(path),
+            reason,
+        })?;
+        Ok(session)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.windows.is_empty() {
+            return Err("no windows".into());
+        }
+        for (i, window) in self.windows.iter().enumerate() {
+            if window.active >= window.tabs.len() {
+                return Err(format!(
+                    "window {} has active tab {} of {}",
+                    i,
+                    window.active,
+                    window.tabs.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format version 1: tabs were bare histories.
+#[derive(Deserialize)]
+struct SessionV1 {
+    windows: Vec<WindowSessionV1>,
+    clean_exit: bool,
+}
+
+#[derive(Deserialize)]
+struct WindowSessionV1 {
+    geometry: Option<WindowGeometry>,
+    tabs: Vec<History>,
+    active: usize,
+}
+
+impl From<SessionV1> for Session {
+    fn from(session: SessionV1) -> Self {
+        let windows = session
+            .windows
+            .into_iter()
+            .map(|window| WindowSession {
+                geometry: window.geometry,
+                tabs: window.tabs.into_iter().map(TabSession::from).collect(),
+                active: window.active,
+            })
+            .collect();
+        Self {
+            windows,
+            clean_exit: session.clean_exit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScrollPosition;
+    use std::fs;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wrybrowser-session-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn round_trips() {
+        let first = History::new("https://a.example/".into());
+        first.push("https://b.example/".into());
+        first.set_title("B".into());
+        first.set_scroll(ScrollPosition { x: 0.0, y: 120.0 });
+        first.back();
+        let second = History::branching("https://c.example/".into());
+        let session = Session {
+            windows: vec![
+                WindowSession {
+                    geometry: Some(WindowGeometry {
+                        position: Some((-20, 40)),
+                        size: (1280, 800),
+                    }),
+                    tabs: vec![
+                        first.into(),
+                        TabSession {
+                            history: second,
+                            history_capacity: Some(5),
+                        },
+                    ],
+                    active: 1,
+                },
+                WindowSession {
+                    geometry: None,
+                    tabs: vec![History::new("https://d.example/".into()).into()],
+                    active: 0,
+                },
+            ],
+            clean_exit: false,
+        };
+        let path = temp_path("session.json");
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path).unwrap();
+        assert_eq!(loaded, session);
+        let tabs = &loaded.windows[0].tabs;
+        assert_eq!(tabs[0].history.index(), 0);
+        assert_eq!(
+            tabs[0].history.forward().unwrap().scroll,
+            ScrollPosition { x: 0.0, y: 120.0 }
+        );
+        assert!(tabs[1].history.is_branching());
+        assert_eq!(tabs[1].history_capacity, Some(5));
+    }
+
+    #[test]
+    fn rejects_bad_sessions() {
+        let path = temp_path("empty.json");
+        fs::write(
+            &path,
+            r#"{"version":1,"data":{"windows":[],"clean_exit":true}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Session::load(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+
+        let path = temp_path("active.json");
+        let session = Session {
+            windows: vec![WindowSession {
+                geometry: None,
+                tabs: vec![History::new("a".into()).into()],
+                active: 1,
+            }],
+            clean_exit: true,
+        };
+        session.save(&path).unwrap();
+        assert!(matches!(
+            Session::load(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+
+        let path = temp_path("bad-history.json");
+        fs::write(
+            &path,
+            r#"{"version":1,"data":{"windows":[{"geometry":null,"active":0,
+                "tabs":[{"branching":false,"current":3,"nodes":[]}]}],"clean_exit":true}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            Session::load(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn loads_version_1() {
+        let path = temp_path("v1.json");
+        let history = History::new("https://a.example/".into());
+        let tabs = serde_json::to_string(&[&history]).unwrap();
+        fs::write(
+            &path,
+            format!(
+                r#"{{"version":1,"data":{{"windows":[{{"geometry":null,"active":0,
+                    "tabs":{}}}],"clean_exit":false}}}}"#,
+                tabs
+            ),
+        )
+        .unwrap();
+        let loaded = Session::load(&path).unwrap();
+        assert_eq!(loaded.windows[0].tabs, [TabSession::from(history)]);
+        assert!(!loaded.clean_exit);
+    }
+}
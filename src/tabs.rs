@@ -0,0 +1,399 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::session::TabSession;
+use crate::{History, LoadState, NavigationController, NavigationKind, DEFAULT_HISTORY_CAPACITY};
+
+/// Identifies a tab for its whole lifetime, independent of its position in
+/// the strip and of the window it is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TabId(u64);
+
+impl TabId {
+    /// An id no other tab has had.
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        TabId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// One tab: its own back/forward history and, once the window exists, the
+/// content view showing it.
+pub struct Tab<V> {
+    id: TabId,
+    pub history: Rc<History>,
+    pub view: Option<V>,
+    pub load_state: LoadState,
+    pub navigation: NavigationController,
+    /// Most entries `history` keeps, if not the browser-wide setting.
+    pub history_capacity: Option<usize>,
+    /// Document title reported by the page, if it has one.
+    pub title: Option<String>,
+}
+
+impl<V> Tab<V> {
+    pub fn id(&self) -> TabId {
+        self.id
+    }
+
+    /// Text to show for this tab: the page title, or the URL while the page
+    /// has no title.
+    pub fn label(&self) -> String {
+        match &self.title {
+            Some(title) if !title.trim().is_empty() => title.clone(),
+            _ => self.history.current_url(),
+        }
+    }
+}
+
+/// Operations on the tab strip, shared by the toolbar and keyboard shortcuts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TabCommand {
+    /// Open a new tab right after the active one and switch to it.
+    Open(String),
+    Close(usize),
+    CloseActive,
+    Select(usize),
+    SelectLast,
+    Next,
+    Previous,
+    Move {
+        from: usize,
+        to: usize,
+    },
+    MoveActiveLeft,
+    MoveActiveRight,
+}
+
+/// An ordered list of tabs with exactly one active tab. The strip is never
+/// empty: closing the only tab is refused so the caller can close the window
+/// instead.
+pub struct Tabs<V> {
+    tabs: Vec<Tab<V>>,
+    active: usize,
+    /// Most entries a tab's history keeps, unless the tab has a capacity
+    /// of its own.
+    history_capacity: usize,
+}
+
+impl<V> Tabs<V> {
+    pub fn new(history: History) -> Self {
+        let mut tabs = Self {
+            tabs: Vec::new(),
+            active: 0,
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+        };
+        tabs.open(history);
+        tabs
+    }
+
+    /// A strip holding just `tab`, e.g. one moved out of another window.
+    pub fn from_tab(tab: Tab<V>) -> Self {
+        Self {
+            tabs: vec![tab],
+            active: 0,
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+        }
+    }
+
+    /// A strip with a tab for each of `saved`, selecting the one at
+    /// `active`, or `None` if `saved` is empty.
+    pub fn restored(saved: Vec<TabSession>, active: usize) -> Option<Self> {
+        let mut tabs = Self {
+            tabs: Vec::new(),
+            active: 0,
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+        };
+        tabs.replace_all(saved, active).then_some(tabs)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &Tab<V> {
+        &self.tabs[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Tab<V> {
+        &mut self.tabs[self.active]
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Tab<V>> {
+        self.tabs.get(index)
+    }
+
+    pub fn position(&self, id: TabId) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.id == id)
+    }
+
+    pub fn by_id(&self, id: TabId) -> Option<&Tab<V>> {
+        self.tabs.iter().find(|tab| tab.id == id)
+    }
+
+    pub fn by_id_mut(&mut self, id: TabId) -> Option<&mut Tab<V>> {
+        self.tabs.iter_mut().find(|tab| tab.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tab<V>> {
+        self.tabs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Tab<V>> {
+        self.tabs.iter_mut()
+    }
+
+    /// Gives every tab that has no view one made by `make`, which loads the
+    /// tab's current entry.
+    pub fn fill_views(&mut self, mut make: impl FnMut(&Tab<V>) -> V) {
+        for tab in &mut self.tabs {
+            if tab.view.is_none() {
+                let entry = tab.history.current();
+                let kind = NavigationKind::CurrentEntry(entry.transition);
+                tab.navigation.begin(kind, entry.url);
+                let view = make(tab);
+                tab.view = Some(view);
+            }
+        }
+    }
+
+    pub fn history_capacity(&self) -> usize {
+        self.history_capacity
+    }
+
+    /// Makes the histories of tabs without a capacity of their own, and of
+    /// tabs opened from now on, keep at most `capacity` entries.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history_capacity = capacity;
+        for tab in &self.tabs {
+            if tab.history_capacity.is_none() {
+                tab.history.set_capacity(capacity);
+            }
+        }
+    }
+
+    /// Inserts a tab after the active one, activates it and returns its id.
+    /// Its history keeps as many entries as the strip's tabs do.
+    pub fn open(&mut self, history: History) -> TabId {
+        self.open_with_capacity(history, None)
+    }
+
+    /// Like [`open`], but the tab's history keeps `history_capacity`
+    /// entries, when given, whatever the strip's tabs keep.
+    ///
+    /// [`open`]: Tabs::open
+    pub fn open_with_capacity(
+        &mut self,
+        history: History,
+        history_capacity: Option<usize>,
+    ) -> TabId {
+        let id = TabId::next();
+        history.set_capacity(history_capacity.unwrap_or(self.history_capacity));
+        let tab = Tab {
+            id,
+            history: Rc::new(history),
+            view: None,
+            load_state: LoadState::Idle,
+            navigation: NavigationController::default(),
+            history_capacity,
+            title: None,
+        };
+        let at = if self.tabs.is_empty() {
+            0
+        } else {
+            self.active + 1
+        };
+        self.tabs.insert(at, tab);
+        self.active = at;
+        id
+    }
+
+    /// Replaces every tab with one for each of `saved`, selecting the one
+    /// at `active`. The new tabs get new ids, so events meant for the old
+    /// tabs never reach them. Does nothing if `saved` is empty.
+    pub fn replace_all(&mut self, saved: Vec<TabSession>, active: usize) -> bool {
+        if saved.is_empty() {
+            return false;
+        }
+        self.tabs.clear();
+        for tab in saved {
+            self.open_with_capacity(tab.history, tab.history_capacity);
+        }
+        self.active = active.min(self.tabs.len() - 1);
+        true
+    }
+
+    /// Removes the tab at `index`. The tab to its right (or left, if it was
+    /// the last one) becomes active when the active tab is closed.
+    pub fn close(&mut self, index: usize) -> Option<Tab<V>> {
+        if self.tabs.len() <= 1 || index >= self.tabs.len() {
+            return None;
+        }
+        let tab = self.tabs.remove(index);
+        if index < self.active || self.active == self.tabs.len() {
+            self.active -= 1;
+        }
+        Some(tab)
+    }
+
+    pub fn select(&mut self, index: usize) -> bool {
+        if index >= self.tabs.len() || index == self.active {
+            return false;
+        }
+        self.active = index;
+        true
+    }
+
+    /// Moves the tab at `from` to `to`; the active tab stays active.
+    pub fn move_tab(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.tabs.len() || to >= self.tabs.len() || from == to {
+            return false;
+        }
+        let active_id = self.active().id;
+        let tab = self.tabs.remove(from);
+        self.tabs.insert(to, tab);
+        self.active = self.position(active_id).unwrap_or(0);
+        true
+    }
+
+    /// Applies `command`, returning whether the strip changed.
+    pub fn apply(&mut self, command: TabCommand) -> bool {
+        let len = self.tabs.len();
+        match command {
+            TabCommand::Open(url) => {
+                self.open(History::new(url));
+                true
+            }
+            TabCommand::Close(index) => self.close(index).is_some(),
+            TabCommand::CloseActive => self.close(self.active).is_some(),
+            TabCommand::Select(index) => self.select(index),
+            TabCommand::SelectLast => self.select(len - 1),
+            TabCommand::Next => self.select((self.active + 1) % len),
+            TabCommand::Previous => self.select((self.active + len - 1) % len),
+            TabCommand::Move { from, to } => self.move_tab(from, to),
+            TabCommand::MoveActiveLeft => {
+                self.active > 0 && self.move_tab(self.active, self.active - 1)
+            }
+            TabCommand::MoveActiveRight => self.move_tab(self.active, self.active + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(tabs: &Tabs<()>) -> Vec<String> {
+        tabs.iter().map(|tab| tab.history.current_url()).collect()
+    }
+
+    fn strip(names: &[&str]) -> Tabs<()> {
+        let mut tabs = Tabs::new(History::new(names[0].into()));
+        for name in &names[1..] {
+            tabs.apply(TabCommand::Open(name.to_string()));
+        }
+        tabs
+    }
+
+    #[test]
+    fn open_inserts_after_active() {
+        let mut tabs = strip(&["a", "b"]);
+        tabs.select(0);
+        tabs.apply(TabCommand::Open("c".into()));
+        assert_eq!(urls(&tabs), ["a", "c", "b"]);
+        assert_eq!(tabs.active_index(), 1);
+    }
+
+    #[test]
+    fn close_picks_neighbour_and_keeps_last_tab() {
+        let mut tabs = strip(&["a", "b", "c"]);
+        tabs.select(1);
+        assert!(tabs.apply(TabCommand::CloseActive));
+        assert_eq!(urls(&tabs), ["a", "c"]);
+        assert_eq!(tabs.active().history.current_url(), "c");
+
+        assert!(tabs.apply(TabCommand::CloseActive));
+        assert_eq!(tabs.active().history.current_url(), "a");
+
+        assert!(!tabs.apply(TabCommand::CloseActive));
+        assert_eq!(tabs.len(), 1);
+    }
+
+    #[test]
+    fn close_before_active_shifts_index() {
+        let mut tabs = strip(&["a", "b", "c"]);
+        assert!(tabs.apply(TabCommand::Close(0)));
+        assert_eq!(tabs.active_index(), 1);
+        assert_eq!(tabs.active().history.current_url(), "c");
+    }
+
+    #[test]
+    fn next_and_previous_wrap() {
+        let mut tabs = strip(&["a", "b", "c"]);
+        tabs.apply(TabCommand::Next);
+        assert_eq!(tabs.active_index(), 0);
+        tabs.apply(TabCommand::Previous);
+        assert_eq!(tabs.active_index(), 2);
+        tabs.apply(TabCommand::Select(1));
+        assert_eq!(tabs.active_index(), 1);
+        tabs.apply(TabCommand::SelectLast);
+        assert_eq!(tabs.active_index(), 2);
+    }
+
+    #[test]
+    fn reorder_keeps_active_tab() {
+        let mut tabs = strip(&["a", "b", "c"]);
+        let active = tabs.active().id();
+        assert!(tabs.apply(TabCommand::Move { from: 2, to: 0 }));
+        assert_eq!(urls(&tabs), ["c", "a", "b"]);
+        assert_eq!(tabs.active().id(), active);
+
+        assert!(tabs.apply(TabCommand::MoveActiveRight));
+        assert_eq!(urls(&tabs), ["a", "c", "b"]);
+        assert!(!tabs.apply(TabCommand::Move { from: 0, to: 3 }));
+        tabs.apply(TabCommand::Select(0));
+        assert!(!tabs.apply(TabCommand::MoveActiveLeft));
+    }
+
+    #[test]
+    fn replace_all_keeps_ids_unique() {
+        let mut tabs = strip(&["a", "b"]);
+        let old: Vec<TabId> = tabs.iter().map(Tab::id).collect();
+        let saved = ["c", "d", "e"].map(|url| History::new(url.into()).into());
+        assert!(tabs.replace_all(saved.into(), 1));
+        assert_eq!(urls(&tabs), ["c", "d", "e"]);
+        assert_eq!(tabs.active_index(), 1);
+        assert!(tabs.iter().all(|tab| !old.contains(&tab.id())));
+
+        assert!(!tabs.replace_all(Vec::new(), 0));
+        assert!(tabs.replace_all(vec![History::new("f".into()).into()], 5));
+        assert_eq!(tabs.active_index(), 0);
+    }
+
+    #[test]
+    fn label_prefers_title() {
+        let mut tabs = strip(&["a"]);
+        assert_eq!(tabs.active().label(), "a");
+        tabs.active_mut().title = Some("Page A".into());
+        assert_eq!(tabs.active().label(), "Page A");
+        tabs.active_mut().title = Some("  ".into());
+        assert_eq!(tabs.active().label(), "a");
+    }
+
+    #[test]
+    fn tabs_have_independent_history() {
+        let tabs = strip(&["a", "b"]);
+        tabs.get(0).unwrap().history.push("a2".into());
+        assert_eq!(tabs.get(1).unwrap().history.back(), None);
+        let back = tabs.get(0).unwrap().history.back();
+        assert_eq!(back.map(|entry| entry.url), Some("a".into()));
+    }
+}
@@ -0,0 +1,248 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+use url::{form_urlencoded, Url};
+
+/// Placeholder replaced by the encoded query in a search template, as in
+/// OpenSearch descriptions.
+pub const SEARCH_TERMS: &str = "{searchTerms}";
+
+pub const DEFAULT_SEARCH_TEMPLATE: &str = "https://duckduckgo.com/?q={searchTerms}";
+
+/// Schemes that are used without `//`, so `about:blank` is never mistaken
+/// for a host with a port.
+const OPAQUE_SCHEMES: &[&str] = &["about", "data", "javascript", "mailto", "view-source"];
+
+/// Turns whatever was typed into the address bar into a loadable URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlFixup {
+    search_template: String,
+}
+
+impl Default for UrlFixup {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEARCH_TEMPLATE)
+    }
+}
+
+impl UrlFixup {
+    /// `search_template` must contain [`SEARCH_TERMS`]; if it does not, the
+    /// query is appended to it.
+    pub fn new(search_template: impl Into<String>) -> Self {
+        Self {
+            search_template: search_template.into(),
+        }
+    }
+
+    pub fn search_template(&self) -> &str {
+        &self.search_template
+    }
+
+    /// Returns the URL to load for `input`, or `None` for blank input.
+    ///
+    /// Full URLs pass through (with IDN hosts converted to punycode), file
+    /// paths become `file://` URLs, `localhost` and IP literals get `http://`,
+    /// anything that looks like a host name gets `https://`, and everything
+    /// else is sent to the search engine.
+    pub fn fixup(&self, input: &str) -> Option<String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        if let Some(url) = absolute_url(input) {
+            return Some(url);
+        }
+        if let Some(path) = file_path(input) {
+            if let Ok(url) = Url::from_file_path(&path) {
+                return Some(url.into());
+            }
+        }
+        if !input.contains(char::is_whitespace) {
+            let host = host_part(input);
+            let scheme = if is_local_host(host) {
+                Some("http")
+            } else if looks_like_domain(host) {
+                Some("https")
+            } else {
+                None
+            };
+            if let Some(url) = scheme.and_then(|s| Url::parse(&format!("{}://{}", s, input)).ok()) {
+                return Some(url.into());
+            }
+        }
+        Some(self.search_url(input))
+    }
+
+    pub fn search_url(&self, query: &str) -> String {
+        let encoded: String = form_urlencoded::byte_serialize(query.as_bytes()).collect();
+        if self.search_template.contains(SEARCH_TERMS) {
+            self.search_template.replace(SEARCH_TERMS, &encoded)
+        } else {
+            format!("{}{}", self.search_template, encoded)
+        }
+    }
+}
+
+fn absolute_url(input: &str) -> Option<String> {
+    let (scheme, rest) = input.split_once(':')?;
+    let valid_scheme = scheme
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    let scheme = scheme.to_ascii_lowercase();
+    if !valid_scheme || !(rest.starts_with("//") || OPAQUE_SCHEMES.contains(&scheme.as_str())) {
+        return None;
+    }
+    Url::parse(input).ok().map(Into::into)
+}
+
+fn file_path(input: &str) -> Option<PathBuf> {
+    if let Some(rest) = input.strip_prefix("~/") {
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+        return Some(Path::new(&home).join(rest));
+    }
+    let bytes = input.as_bytes();
+    let windows_drive = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    if input.starts_with('/') || input.starts_with("\\\\") || windows_drive {
+        return Some(PathBuf::from(input));
+    }
+    None
+}
+
+/// The `host[:port]` part of a scheme-less input.
+fn host_part(input: &str) -> &str {
+    let end = input.find(['/', '?', '#']).unwrap_or(input.len());
+    &input[..end]
+}
+
+fn strip_port(host: &str) -> Option<&str> {
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') || name.ends_with(']') => {
+            port.parse::<u16>().ok().map(|_| name)
+        }
+        Some(_) => None,
+        None => Some(host),
+    }
+}
+
+fn is_local_host(host: &str) -> bool {
+    let Some(name) = strip_port(host) else {
+        return false;
+    };
+    if name.eq_ignore_ascii_case("localhost") || name.parse::<Ipv4Addr>().is_ok() {
+        return true;
+    }
+    name.strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .is_some_and(|inner| inner.parse::<Ipv6Addr>().is_ok())
+}
+
+fn looks_like_domain(host: &str) -> bool {
+    let Some(name) = strip_port(host) else {
+        return false;
+    };
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.len() < 2 {
+        return false;
+    }
+    let label_ok = |label: &str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || !c.is_ascii())
+    };
+    let tld = labels[labels.len() - 1];
+    labels.iter().all(|label| label_ok(label)) && !tld.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixup_table() {
+        let fixup = UrlFixup::default();
+        let cases: &[(&str, &str)] = &[
+            ("https://example.com", "https://example.com/"),
+            ("  HTTP://Example.COM/a?b#c ", "http://example.com/a?b#c"),
+            ("example.com", "https://example.com/"),
+            (
+                "www.example.co.uk/path?q=1",
+                "https://www.example.co.uk/path?q=1",
+            ),
+            ("example.com:8443", "https://example.com:8443/"),
+            ("localhost", "http://localhost/"),
+            ("localhost:3000/app", "http://localhost:3000/app"),
+            ("127.0.0.1", "http://127.0.0.1/"),
+            (
+                "192.168.1.10:8080/status",
+                "http://192.168.1.10:8080/status",
+            ),
+            ("[::1]:8080", "http://[::1]:8080/"),
+            ("bücher.de", "https://xn--bcher-kva.de/"),
+            ("xn--bcher-kva.de", "https://xn--bcher-kva.de/"),
+            (
+                "https://münchen.de/straße",
+                "https://xn--mnchen-3ya.de/stra%C3%9Fe",
+            ),
+            ("about:blank", "about:blank"),
+            ("data:text/plain,hi", "data:text/plain,hi"),
+            (
+                "rust borrow checker",
+                "https://duckduckgo.com/?q=rust+borrow+checker",
+            ),
+            ("rust", "https://duckduckgo.com/?q=rust"),
+            ("what is 1.5", "https://duckduckgo.com/?q=what+is+1.5"),
+            ("1.5", "https://duckduckgo.com/?q=1.5"),
+            ("c++ & go?", "https://duckduckgo.com/?q=c%2B%2B+%26+go%3F"),
+            ("-bad-.com", "https://duckduckgo.com/?q=-bad-.com"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                fixup.fixup(input).as_deref(),
+                Some(*expected),
+                "input {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn blank_input_is_ignored() {
+        assert_eq!(UrlFixup::default().fixup("   "), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_paths_become_file_urls() {
+        let fixup = UrlFixup::default();
+        assert_eq!(
+            fixup.fixup("/tmp/page one.html").as_deref(),
+            Some("file:///tmp/page%20one.html")
+        );
+        assert_eq!(
+            fixup.fixup("file:///etc/hosts").as_deref(),
+            Some("file:///etc/hosts")
+        );
+    }
+
+    #[test]
+    fn custom_search_templates() {
+        let fixup = UrlFixup::new("https://search.example/find?query={searchTerms}&lang=en");
+        assert_eq!(
+            fixup.fixup("hello world").as_deref(),
+            Some("https://search.example/find?query=hello+world&lang=en")
+        );
+        let fixup = UrlFixup::new("https://search.example/?q=");
+        assert_eq!(fixup.search_url("a b"), "https://search.example/?q=a+b");
+    }
+}
@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::layout::Rect;
+
+/// What the browser needs from a web view: the toolbar, the sidebar and
+/// every tab's content are driven through this, so the logic around them
+/// runs without a window in tests and headless builds.
+///
+/// Failures are not reported; like a page that fails to load, they only
+/// show up in what the view renders.
+pub trait PageView {
+    fn load_url(&self, url: &str);
+    fn evaluate_script(&self, script: &str);
+    fn set_bounds(&self, bounds: Rect);
+    fn set_visible(&self, visible: bool);
+    /// Reloads the current page, from the network when `bypass_cache`.
+    fn reload(&self, bypass_cache: bool);
+    /// Sets the page zoom, 1.0 being 100%.
+    fn zoom(&self, factor: f64);
+}
+
+#[cfg(feature = "browser")]
+impl PageView for wry::WebView {
+    fn load_url(&self, url: &str) {
+        wry::WebView::load_url(self, url).ok();
+    }
+
+    fn evaluate_script(&self, script: &str) {
+        wry::WebView::evaluate_script(self, script).ok();
+    }
+
+    fn set_bounds(&self, bounds: Rect) {
+        wry::WebView::set_bounds(self, bounds.into()).ok();
+    }
+
+    fn set_visible(&self, visible: bool) {
+        wry::WebView::set_visible(self, visible).ok();
+    }
+
+    fn reload(&self, bypass_cache: bool) {
+        use wry::http::{header, HeaderMap, HeaderValue};
+
+        if !bypass_cache {
+            wry::WebView::evaluate_script(self, "location.reload()").ok();
+            return;
+        }
+        let Ok(url) = self.url() else {
+            return;
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+        self.load_url_with_headers(&url, headers).ok();
+    }
+
+    fn zoom(&self, factor: f64) {
+        wry::WebView::zoom(self, factor).ok();
+    }
+}
+
+/// A call made on a [`RecordingView`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViewCall {
+    LoadUrl(String),
+    EvaluateScript(String),
+    SetBounds(Rect),
+    SetVisible(bool),
+    Reload { bypass_cache: bool },
+    Zoom(f64),
+}
+
+/// A view that renders nothing and records every call made on it. Clones
+/// share the same log, so a test can keep one clone and hand the other to
+/// the browser.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingView {
+    calls: Rc<RefCell<Vec<ViewCall>>>,
+}
+
+impl RecordingView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn calls(&self) -> Vec<ViewCall> {
+        self.calls.borrow().clone()
+    }
+
+    /// Returns the calls recorded so far and forgets them.
+    pub fn take_calls(&self) -> Vec<ViewCall> {
+        self.calls.take()
+    }
+
+    /// URLs passed to `load_url`, in order.
+    pub fn loaded_urls(&self) -> Vec<String> {
+        self.calls
+            .borrow()
+            .iter()
+            .filter_map(|call| match call {
+                ViewCall::LoadUrl(url) => Some(url.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the last `set_visible` call showed the view.
+    pub fn is_visible(&self) -> bool {
+        self.calls
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|call| match call {
+                ViewCall::SetVisible(visible) => Some(*visible),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+
+    fn record(&self, call: ViewCall) {
+        self.calls.borrow_mut().push(call);
+    }
+}
+
+impl PageView for RecordingView {
+    fn load_url(&self, url: &str) {
+        self.record(ViewCall::LoadUrl(url.to_string()));
+    }
+
+    fn evaluate_script(&self, script: &str) {
+        self.record(ViewCall::EvaluateScript(script.to_string()));
+    }
+
+    fn set_bounds(&self, bounds: Rect) {
+        self.record(ViewCall::SetBounds(bounds));
+    }
+
+    fn set_visible(&self, visible: bool) {
+        self.record(ViewCall::SetVisible(visible));
+    }
+
+    fn reload(&self, bypass_cache: bool) {
+        self.record(ViewCall::Reload { bypass_cache });
+    }
+
+    fn zoom(&self, factor: f64) {
+        self.record(ViewCall::Zoom(factor));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_log() {
+        let view = RecordingView::new();
+        let boxed: Box<dyn PageView> = Box::new(view.clone());
+        boxed.load_url("a");
+        boxed.set_visible(true);
+        boxed.reload(true);
+        assert_eq!(view.loaded_urls(), ["a"]);
+        assert!(view.is_visible());
+        assert_eq!(
+            view.take_calls(),
+            [
+                ViewCall::LoadUrl("a".into()),
+                ViewCall::SetVisible(true),
+                ViewCall::Reload { bypass_cache: true },
+            ]
+        );
+        assert!(view.calls().is_empty());
+    }
+}
@@ -0,0 +1,837 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write as _};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::history::Transition;
+use crate::persist::{self, PersistError};
+use crate::time::{now, Timestamp};
+
+/// On-disk format version of the visit log.
+pub const VISITS_FORMAT_VERSION: u32 = 2;
+
+/// URL of the internal page listing the visit log.
+pub const HISTORY_PAGE_URL: &str = "wry://history";
+
+const HOUR: Timestamp = 60 * 60;
+const DAY: Timestamp = 24 * HOUR;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct VisitId(pub u64);
+
+/// Orders visits by time, then by id among visits in the same second.
+type VisitKey = (Timestamp, VisitId);
+
+/// One page load, in any tab of any session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Visit {
+    pub id: VisitId,
+    pub url: String,
+    pub title: Option<String>,
+    pub time: Timestamp,
+    pub transition: Transition,
+}
+
+/// Which visits [`Visits::search`] returns. Every field is optional and
+/// all given ones must match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisitQuery {
+    /// Case-insensitive substring of the URL or the title.
+    pub text: Option<String>,
+    /// Host name; subdomains match too.
+    pub domain: Option<String>,
+    /// Earliest visit time, inclusive.
+    pub since: Option<Timestamp>,
+    /// Latest visit time, exclusive.
+    pub until: Option<Timestamp>,
+    pub limit: Option<usize>,
+}
+
+impl VisitQuery {
+    fn matches_text(&self, visit: &Visit) -> bool {
+        let text = self.text.as_deref().map(str::trim).unwrap_or_default();
+        if text.is_empty() {
+            return true;
+        }
+        let text = text.to_lowercase();
+        let in_title = visit
+            .title
+            .as_ref()
+            .is_some_and(|title| title.to_lowercase().contains(&text));
+        in_title || visit.url.to_lowercase().contains(&text)
+    }
+
+    /// The domain asked for, normalized like host names are.
+    fn domain(&self) -> Option<String> {
+        let domain = self.domain.as_deref()?.trim().trim_start_matches('.');
+        (!domain.is_empty()).then(|| domain.to_ascii_lowercase())
+    }
+
+    fn time_range(&self) -> (Bound<VisitKey>, Bound<VisitKey>) {
+        let first = VisitId(0);
+        (
+            self.since
+                .map_or(Bound::Unbounded, |since| Bound::Included((since, first))),
+            self.until
+                .map_or(Bound::Unbounded, |until| Bound::Excluded((until, first))),
+        )
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    url.host_str().map(str::to_ascii_lowercase)
+}
+
+fn in_domain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// How long visits are kept. Zero means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age_days: u32,
+    pub max_visits: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            max_age_days: 90,
+            max_visits: 10_000,
+        }
+    }
+}
+
+/// The "Clear history" choices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClearRange {
+    LastHour,
+    LastDay,
+    All,
+}
+
+impl ClearRange {
+    /// Time of the oldest visit the range covers.
+    fn start(self, now: Timestamp) -> Timestamp {
+        match self {
+            ClearRange::LastHour => now.saturating_sub(HOUR),
+            ClearRange::LastDay => now.saturating_sub(DAY),
+            ClearRange::All => 0,
+        }
+    }
+}
+
+/// The global visit log: every page loaded, across tabs and sessions.
+/// Unlike a tab's [`History`](crate::History) it is never truncated by
+/// navigation, only by [`Retention`] and by clearing it.
+///
+/// Visits are indexed by time, URL and host, so searches only look at the
+/// visits in the range and domain asked for. A log opened from a file with
+/// [`Visits::open`] keeps that file up to date as it changes, writing from
+/// a thread of its own.
+#[derive(Debug, Default)]
+pub struct Visits {
+    next_id: u64,
+    visits: BTreeMap<VisitKey, Visit>,
+    /// When each visit happened, to find it by id.
+    times: HashMap<VisitId, Timestamp>,
+    by_url: HashMap<String, BTreeSet<VisitKey>>,
+    /// Visits by lowercase host name, for domain searches.
+    by_host: HashMap<String, BTreeSet<VisitKey>>,
+    retention: Retention,
+    log: Option<Log>,
+}
+
+/// One change to the visit log, as written to its file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Change {
+    Record(Visit),
+    Title {
+        id: VisitId,
+        title: String,
+    },
+    Remove(VisitId),
+    ClearSince(Timestamp),
+    /// Retention dropped the visits before `before`, then all but the
+    /// newest `keep` (zero for no limit).
+    Expire {
+        before: Timestamp,
+        keep: usize,
+    },
+}
+
+/// The first line of a log file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+}
+
+/// Format version 1: the whole log as one JSON file, where the log file is
+/// now but with a `.json` extension.
+#[derive(Deserialize)]
+struct VisitsV1 {
+    visits: Vec<Visit>,
+}
+
+/// Lines a log file may hold beyond one per visit before it is compacted.
+const STALE_LINES: usize = 1000;
+
+impl Visits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.visits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visits.is_empty()
+    }
+
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Switches to `retention`, dropping whatever it no longer keeps.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+        self.expire(now());
+    }
+
+    /// Records a visit to `url` now. Internal `wry:` pages are not recorded.
+    pub fn record(&mut self, url: String, transition: Transition) -> Option<VisitId> {
+        self.record_at(url, transition, now())
+    }
+
+    /// Records a visit to `url` at `time`.
+    pub fn record_at(
+        &mut self,
+        url: String,
+        transition: Transition,
+        time: Timestamp,
+    ) -> Option<VisitId> {
+        if url.starts_with("wry:") {
+            return None;
+        }
+        let visit = Visit {
+            id: VisitId(self.next_id),
+            url,
+            title: None,
+            time,
+            transition,
+        };
+        let id = visit.id;
+        self.change(Change::Record(visit));
+        self.expire(time);
+        Some(id)
+    }
+
+    /// Titles the latest visit to `url`.
+    pub fn set_title(&mut self, url: &str, title: String) {
+        let latest = self.by_url.get(url).and_then(|keys| keys.last());
+        if let Some(&(_, id)) = latest {
+            self.change(Change::Title { id, title });
+        }
+    }
+
+    /// Visits matching `query`, newest first.
+    pub fn search(&self, query: &VisitQuery) -> Vec<Visit> {
+        let range = query.time_range();
+        let keys: Box<dyn Iterator<Item = &VisitKey>> = match query.domain() {
+            Some(domain) => {
+                let mut keys: Vec<&VisitKey> = self
+                    .by_host
+                    .iter()
+                    .filter(|(host, _)| in_domain(host, &domain))
+                    .flat_map(|(_, keys)| keys.range(range))
+                    .collect();
+                keys.sort_unstable();
+                Box::new(keys.into_iter().rev())
+            }
+            None => Box::new(self.visits.range(range).rev().map(|(key, _)| key)),
+        };
+        keys.map(|key| &self.visits[key])
+            .filter(|visit| query.matches_text(visit))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    pub fn remove(&mut self, id: VisitId) -> Option<Visit> {
+        let time = *self.times.get(&id)?;
+        let visit = self.visits.get(&(time, id)).cloned();
+        self.change(Change::Remove(id));
+        // Removed visits do not linger in the file.
+        self.compact();
+        visit
+    }
+
+    /// Removes the visits in `range`, returning how many there were.
+    pub fn clear(&mut self, range: ClearRange) -> usize {
+        self.clear_since(range.start(now()))
+    }
+
+    /// Removes every visit at or after `since`.
+    pub fn clear_since(&mut self, since: Timestamp) -> usize {
+        let removed = self.change(Change::ClearSince(since));
+        self.compact();
+        removed
+    }
+
+    /// Drops the visits the retention limits no longer keep, as of `now`.
+    /// Returns how many were dropped.
+    pub fn expire(&mut self, now: Timestamp) -> usize {
+        let before = match self.retention.max_age_days {
+            0 => 0,
+            days => now.saturating_sub(days as Timestamp * DAY),
+        };
+        let keep = self.retention.max_visits;
+        let stale = self.visits.range(..(before, VisitId(0))).count();
+        if stale == 0 && (keep == 0 || self.visits.len() <= keep) {
+            return 0;
+        }
+        self.change(Change::Expire { before, keep })
+    }
+
+    /// Applies `change` and appends it to the log file, if there is one.
+    /// Returns how many visits it removed.
+    fn change(&mut self, change: Change) -> usize {
+        if let Some(log) = &mut self.log {
+            log.append(&change);
+        }
+        let removed = self.apply(change);
+        if self
+            .log
+            .as_ref()
+            .is_some_and(|log| log.lines > 2 * self.visits.len() + STALE_LINES)
+        {
+            self.compact();
+        }
+        removed
+    }
+
+    fn apply(&mut self, change: Change) -> usize {
+        match change {
+            Change::Record(visit) => {
+                self.insert(visit);
+                0
+            }
+            Change::Title { id, title } => {
+                if let Some(time) = self.times.get(&id) {
+                    if let Some(visit) = self.visits.get_mut(&(*time, id)) {
+                        visit.title = Some(title);
+                    }
+                }
+                0
+            }
+            Change::Remove(id) => {
+                let time = self.times.get(&id).copied();
+                time.and_then(|time| self.take((time, id))).map_or(0, |_| 1)
+            }
+            Change::ClearSince(since) => {
+                let keys: Vec<VisitKey> = self
+                    .visits
+                    .range((since, VisitId(0))..)
+                    .map(|(key, _)| *key)
+                    .collect();
+                self.take_all(keys)
+            }
+            Change::Expire { before, keep } => {
+                let mut keys: Vec<VisitKey> = self
+                    .visits
+                    .range(..(before, VisitId(0)))
+                    .map(|(key, _)| *key)
+                    .collect();
+                let left = self.visits.len() - keys.len();
+                if keep > 0 && left > keep {
+                    let oldest = self.visits.keys().skip(keys.len()).take(left - keep);
+                    keys.extend(oldest);
+                }
+                self.take_all(keys)
+            }
+        }
+    }
+
+    fn insert(&mut self, visit: Visit) {
+        let key = (visit.time, visit.id);
+        self.next_id = self.next_id.max(visit.id.0 + 1);
+        self.times.insert(visit.id, visit.time);
+        self.by_url
+            .entry(visit.url.clone())
+            .or_default()
+            .insert(key);
+        if let Some(host) = host(&visit.url) {
+            self.by_host.entry(host).or_default().insert(key);
+        }
+        self.visits.insert(key, visit);
+    }
+
+    fn take(&mut self, key: VisitKey) -> Option<Visit> {
+        let visit = self.visits.remove(&key)?;
+        self.times.remove(&visit.id);
+        unindex(&mut self.by_url, &visit.url, key);
+        if let Some(host) = host(&visit.url) {
+            unindex(&mut self.by_host, &host, key);
+        }
+        Some(visit)
+    }
+
+    fn take_all(&mut self, keys: Vec<VisitKey>) -> usize {
+        keys.into_iter().filter_map(|key| self.take(key)).count()
+    }
+
+    /// Opens the visit log kept in the file at `path`, with the default
+    /// retention, and keeps the file up to date from then on. A missing
+    /// file starts an empty log, or one imported from the single JSON file
+    /// earlier versions kept.
+    pub fn open(path: &Path) -> Result<Self, PersistError> {
+        let (mut visits, lines) = match fs::read_to_string(path) {
+            Ok(text) => Self::replay(path, &text)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let mut visits = Self::new();
+                match persist::load::<VisitsV1>(&path.with_extension("json"), 1) {
+                    Ok(old) => old
+                        .visits
+                        .into_iter()
+                        .for_each(|visit| visits.insert(visit)),
+                    Err(err) if err.is_not_found() => {}
+                    Err(err) => return Err(err),
+                }
+                (visits, None)
+            }
+            Err(err) => return Err(err.into()),
+        };
+        visits.log = Some(Log::start(path.to_path_buf(), lines.unwrap_or(0)));
+        if lines.is_none() {
+            visits.compact();
+        }
+        Ok(visits)
+    }
+
+    /// An empty visit log kept in the file at `path`, replacing whatever
+    /// the file held.
+    pub fn create(path: &Path) -> Self {
+        let mut visits = Self::new();
+        visits.log = Some(Log::start(path.to_path_buf(), 0));
+        visits.compact();
+        visits
+    }
+
+    /// Rebuilds a log from its file. Returns it with the number of lines
+    /// the file holds, or `None` if it ends in a torn line and needs
+    /// rewriting.
+    fn replay(path: &Path, text: &str) -> Result<(Self, Option<usize>), PersistError> {
+        let corrupt = |reason: String| PersistError::Corrupt {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let lines: Vec<&str> = text.lines().collect();
+        let header: Header = serde_json::from_str(lines.first().copied().unwrap_or_default())
+            .map_err(|err| corrupt(err.to_string()))?;
+        if header.version != VISITS_FORMAT_VERSION {
+            return Err(PersistError::UnsupportedVersion {
+                path: path.to_path_buf(),
+                found: header.version,
+                expected: VISITS_FORMAT_VERSION,
+            });
+        }
+        let mut visits = Self::new();
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            match serde_json::from_str(line) {
+                Ok(change) => {
+                    visits.apply(change);
+                }
+                // The last write was cut short, e.g. by a crash.
+                Err(_) if i + 1 == lines.len() && !text.ends_with('\n') => {
+                    return Ok((visits, None));
+                }
+                Err(err) => return Err(corrupt(format!("line {}: {}", i + 1, err))),
+            }
+        }
+        Ok((visits, Some(lines.len())))
+    }
+
+    /// Rewrites the log file with one line per visit left.
+    fn compact(&mut self) {
+        let Some(log) = &mut self.log else {
+            return;
+        };
+        let mut text = line(&Header {
+            version: VISITS_FORMAT_VERSION,
+        });
+        for visit in self.visits.values() {
+            text.push_str(&line(&Change::Record(visit.clone())));
+        }
+        log.replace(text, self.visits.len() + 1);
+    }
+
+    /// Waits until every change so far is in the log file.
+    pub fn flush(&self) {
+        if let Some(log) = &self.log {
+            log.flush();
+        }
+    }
+}
+
+fn unindex(index: &mut HashMap<String, BTreeSet<VisitKey>>, name: &str, key: VisitKey) {
+    if let Some(keys) = index.get_mut(name) {
+        keys.remove(&key);
+        if keys.is_empty() {
+            index.remove(name);
+        }
+    }
+}
+
+/// `value` as one line of JSON.
+fn line(value: &impl Serialize) -> String {
+    let mut line = serde_json::to_string(value).expect("visit log lines are plain JSON");
+    line.push('\n');
+    line
+}
+
+/// The file a [`Visits`] log is kept in: a [`Header`] line, then one
+/// [`Change`] per line. A background thread appends the lines, so the event
+/// loop never waits for the disk, syncing once per batch of changes. Once
+/// most lines are stale, or visits were removed, the file is rewritten with
+/// a line per visit left.
+#[derive(Debug)]
+struct Log {
+    /// Lines in the file, once the thread has written them all.
+    lines: usize,
+    writes: Option<Sender<Write>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+enum Write {
+    Append(String),
+    Replace(String),
+    /// Answers once everything sent before is written.
+    Flush(Sender<()>),
+}
+
+impl Log {
+    fn start(path: PathBuf, lines: usize) -> Self {
+        let (writes, received) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("visit log".into())
+            .spawn(move || write_log(&path, received))
+            .map_err(|err| eprintln!("Not saving history: {}", err))
+            .ok();
+        Self {
+            lines,
+            writes: writer.is_some().then_some(writes),
+            writer,
+        }
+    }
+
+    fn send(&self, write: Write) {
+        if let Some(writes) = &self.writes {
+            writes.send(write).ok();
+        }
+    }
+
+    fn append(&mut self, change: &Change) {
+        self.send(Write::Append(line(change)));
+        self.lines += 1;
+    }
+
+    fn replace(&mut self, text: String, lines: usize) {
+        self.send(Write::Replace(text));
+        self.lines = lines;
+    }
+
+    fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        self.send(Write::Flush(done));
+        flushed.recv().ok();
+    }
+}
+
+impl Drop for Log {
+    fn drop(&mut self) {
+        // Lets the thread finish what was sent, then stop.
+        self.writes = None;
+        if let Some(writer) = self.writer.take() {
+            writer.join().ok();
+        }
+    }
+}
+
+/// The log's writer thread: carries out `writes` on the file at `path`
+/// until the log is dropped.
+fn write_log(path: &Path, writes: Receiver<Write>) {
+    let mut file: Option<File> = None;
+    while let Ok(first) = writes.recv() {
+        let mut flushed = Vec::new();
+        let batch = || -> Result<(), PersistError> {
+            for write in std::iter::once(first).chain(writes.try_iter()) {
+                match write {
+                    Write::Append(line) => {
+                        let file = match &mut file {
+                            Some(file) => file,
+                            None => file.insert(OpenOptions::new().append(true).open(path)?),
+                        };
+                        file.write_all(line.as_bytes())?;
+                    }
+                    Write::Replace(text) => {
+                        file = None;
+                        persist::write_atomic(path, text.as_bytes())?;
+                    }
+                    Write::Flush(done) => flushed.push(done),
+                }
+            }
+            if let Some(file) = &file {
+                file.sync_data()?;
+            }
+            Ok(())
+        };
+        if let Err(err) = batch() {
+            eprintln!("Failed to save history: {}", err);
+        }
+        for done in flushed {
+            done.send(()).ok();
+        }
+    }
+}
+
+/// Whether `url` is the internal history page, with or without a query.
+pub fn is_history_page(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| url.scheme() == "wry" && url.host_str() == Some("history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const NOW: Timestamp = 1_700_000_000;
+
+    fn log() -> Visits {
+        let mut visits = Visits::new();
+        fill(&mut visits);
+        visits
+    }
+
+    fn fill(visits: &mut Visits) {
+        visits.retention = Retention {
+            max_age_days: 0,
+            max_visits: 0,
+        };
+        let pages = [
+            ("https://docs.rs/serde", "serde - Rust", NOW - 3 * DAY),
+            (
+                "https://www.rust-lang.org/",
+                "Rust Programming Language",
+                NOW - DAY - 1,
+            ),
+            ("https://blog.rust-lang.org/", "Rust Blog", NOW - 2 * HOUR),
+            ("https://example.com/", "Example Domain", NOW - 10),
+        ];
+        for (url, title, time) in pages {
+            visits.record_at(url.into(), Transition::Link, time);
+            visits.set_title(url, title.into());
+        }
+    }
+
+    fn urls(visits: Vec<Visit>) -> Vec<String> {
+        visits.into_iter().map(|visit| visit.url).collect()
+    }
+
+    #[test]
+    fn searches_by_text_domain_and_date() {
+        let visits = log();
+        let query = |query: VisitQuery| urls(visits.search(&query));
+
+        assert_eq!(query(VisitQuery::default()).len(), 4);
+        assert_eq!(
+            query(VisitQuery {
+                text: Some("RUST".into()),
+                ..VisitQuery::default()
+            }),
+            [
+                "https://blog.rust-lang.org/",
+                "https://www.rust-lang.org/",
+                "https://docs.rs/serde"
+            ]
+        );
+        assert_eq!(
+            query(VisitQuery {
+                text: Some("domain".into()),
+                ..VisitQuery::default()
+            }),
+            ["https://example.com/"]
+        );
+        assert_eq!(
+            query(VisitQuery {
+                domain: Some("rust-lang.org".into()),
+                limit: Some(1),
+                ..VisitQuery::default()
+            }),
+            ["https://blog.rust-lang.org/"]
+        );
+        assert!(query(VisitQuery {
+            domain: Some("lang.org".into()),
+            ..VisitQuery::default()
+        })
+        .is_empty());
+        assert_eq!(
+            query(VisitQuery {
+                since: Some(NOW - DAY),
+                until: Some(NOW - 10),
+                ..VisitQuery::default()
+            }),
+            ["https://blog.rust-lang.org/"]
+        );
+    }
+
+    #[test]
+    fn removes_and_clears() {
+        let mut visits = log();
+        let newest = visits.search(&VisitQuery::default())[0].id;
+        assert_eq!(visits.remove(newest).unwrap().url, "https://example.com/");
+        assert_eq!(visits.remove(newest), None);
+        let example = VisitQuery {
+            domain: Some("example.com".into()),
+            ..VisitQuery::default()
+        };
+        assert!(visits.search(&example).is_empty());
+
+        assert_eq!(visits.clear_since(ClearRange::LastHour.start(NOW)), 0);
+        assert_eq!(visits.clear_since(ClearRange::LastDay.start(NOW)), 1);
+        assert_eq!(visits.len(), 2);
+        assert_eq!(visits.clear(ClearRange::All), 2);
+        assert!(visits.is_empty());
+    }
+
+    #[test]
+    fn retention_limits_age_and_count() {
+        let mut visits = log();
+        visits.retention = Retention {
+            max_age_days: 2,
+            max_visits: 0,
+        };
+        assert_eq!(visits.expire(NOW), 1);
+        visits.retention.max_visits = 2;
+        assert_eq!(visits.expire(NOW), 1);
+        assert_eq!(
+            urls(visits.search(&VisitQuery::default())),
+            ["https://example.com/", "https://blog.rust-lang.org/"]
+        );
+
+        // Recording applies the limits too.
+        visits.record_at("https://new.example/".into(), Transition::Typed, NOW);
+        assert_eq!(visits.len(), 2);
+        assert_eq!(
+            visits.record_at("wry://history".into(), Transition::Typed, NOW),
+            None
+        );
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wrybrowser-visits-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keeps_its_file_up_to_date() {
+        let dir = temp_dir("log");
+        let path = dir.join("visits.log");
+        let mut visits = Visits::open(&path).unwrap();
+        fill(&mut visits);
+        let removed = visits.search(&VisitQuery::default())[0].id;
+        visits.remove(removed);
+        visits.record_at("https://a.example/".into(), Transition::Link, NOW);
+        visits.flush();
+        // Removed visits are gone from the file straight away.
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("https://example.com/"));
+
+        let mut loaded = Visits::open(&path).unwrap();
+        assert_eq!(
+            loaded.search(&VisitQuery::default()),
+            visits.search(&VisitQuery::default())
+        );
+        let id = loaded.record_at("https://b.example/".into(), Transition::Link, NOW);
+        assert_eq!(id, Some(VisitId(removed.0 + 2)));
+        drop(loaded);
+
+        // A line cut short by a crash is dropped, and the file repaired.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"record":{"id":7,"#).unwrap();
+        let mut loaded = Visits::open(&path).unwrap();
+        assert_eq!(loaded.len(), 5);
+        loaded.record_at("https://c.example/".into(), Transition::Link, NOW);
+        drop(loaded);
+        assert_eq!(Visits::open(&path).unwrap().len(), 6);
+
+        fs::write(&path, "{\"version\":2}\nnot json\n{}\n").unwrap();
+        assert!(matches!(
+            Visits::open(&path),
+            Err(PersistError::Corrupt { .. })
+        ));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn compacts_its_file() {
+        let dir = temp_dir("compact");
+        let path = dir.join("visits.log");
+        let mut visits = Visits::open(&path).unwrap();
+        visits.record_at("https://a.example/".into(), Transition::Link, NOW);
+        for i in 0..2 * STALE_LINES {
+            visits.set_title("https://a.example/", format!("Title {}", i));
+        }
+        visits.flush();
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines <= STALE_LINES + 3, "{} lines", lines);
+        let loaded = Visits::open(&path).unwrap();
+        assert_eq!(
+            loaded.search(&VisitQuery::default())[0].title.as_deref(),
+            Some(format!("Title {}", 2 * STALE_LINES - 1).as_str())
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn imports_the_single_file_of_version_1() {
+        let dir = temp_dir("import");
+        let visits = log().search(&VisitQuery::default());
+        let old = serde_json::json!({ "next_id": 4, "visits": visits });
+        persist::save(&dir.join("visits.json"), 1, &old).unwrap();
+
+        let path = dir.join("visits.log");
+        let mut imported = Visits::open(&path).unwrap();
+        assert_eq!(imported.len(), 4);
+        let id = imported.record_at("https://a.example/".into(), Transition::Link, NOW);
+        assert_eq!(id, Some(VisitId(4)));
+        drop(imported);
+        assert_eq!(Visits::open(&path).unwrap().len(), 5);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn recognizes_the_history_page() {
+        assert!(is_history_page(HISTORY_PAGE_URL));
+        assert!(is_history_page("wry://history?q=rust"));
+        assert!(!is_history_page("https://history.example/"));
+        assert!(!is_history_page("wry://settings"));
+    }
+}
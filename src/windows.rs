@@ -0,0 +1,187 @@
+#[cfg(feature = "browser")]
+use winit::window::Window;
+
+use crate::{
+    Browser, ContentView, Effect, History, LayoutSpec, Tab, TabId, TabSession, Tabs,
+    WindowGeometry, WindowSession,
+};
+
+/// Everything that belongs to one window. The browser keeps the state of
+/// the window it is working on in its own fields (`tabs`, `toolbar`,
+/// `layout`, ...) and parks the other windows in
+/// [`Browser::other_windows`]; [`Browser::focus_window`] trades places.
+pub struct BrowserWindow {
+    pub toolbar: Option<ContentView>,
+    pub sidebar: Option<ContentView>,
+    pub tabs: Tabs<ContentView>,
+    pub layout: LayoutSpec,
+    pub geometry: Option<WindowGeometry>,
+    /// Declared last so the views above are dropped before their window.
+    #[cfg(feature = "browser")]
+    pub window: Option<Window>,
+}
+
+impl BrowserWindow {
+    /// A window that has no views, nor a window to show them in, yet.
+    pub fn new(tabs: Tabs<ContentView>, layout: LayoutSpec) -> Self {
+        Self {
+            toolbar: None,
+            sidebar: None,
+            tabs,
+            layout,
+            geometry: None,
+            #[cfg(feature = "browser")]
+            window: None,
+        }
+    }
+
+    fn session(&self) -> WindowSession {
+        window_session(&self.tabs, self.geometry)
+    }
+
+    fn drop_views(&mut self) {
+        for tab in self.tabs.iter_mut() {
+            tab.view = None;
+        }
+        self.toolbar = None;
+        self.sidebar = None;
+    }
+}
+
+impl Browser {
+    /// Number of open windows, the current one included.
+    pub fn window_count(&self) -> usize {
+        1 + self.other_windows.len()
+    }
+
+    /// Makes the window parked at `index` in [`Browser::other_windows`]
+    /// the current one, parking the current one in its place.
+    pub fn focus_window(&mut self, index: usize) -> bool {
+        if index >= self.other_windows.len() {
+            return false;
+        }
+        let mut other = self.other_windows.remove(index);
+        self.swap_window(&mut other);
+        self.other_windows.insert(index, other);
+        true
+    }
+
+    /// Makes the window holding tab `id` the current one, so events from
+    /// that tab are handled there. Returns false if no window has the tab.
+    pub fn focus_tab(&mut self, id: TabId) -> bool {
+        if self.tabs.position(id).is_some() {
+            return true;
+        }
+        let index = self
+            .other_windows
+            .iter()
+            .position(|window| window.tabs.position(id).is_some());
+        index.is_some_and(|index| self.focus_window(index))
+    }
+
+    fn swap_window(&mut self, other: &mut BrowserWindow) {
+        std::mem::swap(&mut self.toolbar, &mut other.toolbar);
+        std::mem::swap(&mut self.sidebar, &mut other.sidebar);
+        std::mem::swap(&mut self.tabs, &mut other.tabs);
+        std::mem::swap(&mut self.layout, &mut other.layout);
+        std::mem::swap(&mut self.geometry, &mut other.geometry);
+        #[cfg(feature = "browser")]
+        std::mem::swap(&mut self.window, &mut other.window);
+    }
+
+    /// Every tab of every window.
+    pub fn all_tabs(&self) -> impl Iterator<Item = &Tab<ContentView>> {
+        self.tabs.iter().chain(
+            self.other_windows
+                .iter()
+                .flat_map(|window| window.tabs.iter()),
+        )
+    }
+
+    /// Opens a window showing `tabs` and makes it the current one. It gets
+    /// a window and views on [`Effect::WindowsChanged`].
+    pub fn open_window(&mut self, mut tabs: Tabs<ContentView>) -> Effect {
+        tabs.set_history_capacity(self.config.tab_history_capacity);
+        let mut window = BrowserWindow::new(
+            tabs,
+            LayoutSpec {
+                toolbar_height: self.layout.toolbar_height,
+                ..LayoutSpec::default()
+            },
+        );
+        self.swap_window(&mut window);
+        self.other_windows.push(window);
+        Effect::WindowsChanged
+    }
+
+    /// Opens a window with one tab showing the homepage.
+    pub fn new_window(&mut self) -> Effect {
+        self.open_window(Tabs::new(History::new(self.homepage())))
+    }
+
+    /// Moves the tab at `index` into a window of its own. The tab keeps its
+    /// history but gets a new view, so the page loads again. A window's only
+    /// tab stays where it is.
+    pub fn move_tab_to_new_window(&mut self, index: usize) -> Effect {
+        let Some(mut tab) = self.tabs.close(index) else {
+            return Effect::None;
+        };
+        tab.view = None;
+        self.sync_tabs();
+        self.open_window(Tabs::from_tab(tab))
+    }
+
+    /// Closes the current window, dropping its views, and makes another one
+    /// current. Returns the closed window, whose window the caller drops, or
+    /// `None` if it is the last window: closing that one shuts down instead.
+    pub fn close_window(&mut self) -> Option<BrowserWindow> {
+        let mut closed = self.other_windows.pop()?;
+        self.swap_window(&mut closed);
+        closed.drop_views();
+        Some(closed)
+    }
+
+    /// What closing the current window amounts to: shutting down when it is
+    /// the last one.
+    pub(crate) fn close_effect(&self) -> Effect {
+        if self.other_windows.is_empty() {
+            Effect::Close
+        } else {
+            Effect::CloseWindow
+        }
+    }
+
+    /// Drops the views of every window.
+    pub(crate) fn drop_views(&mut self) {
+        for window in &mut self.other_windows {
+            window.drop_views();
+        }
+        for tab in self.tabs.iter_mut() {
+            tab.view = None;
+        }
+        self.toolbar = None;
+        self.sidebar = None;
+    }
+
+    /// Every window, the current one first, for a session snapshot.
+    pub(crate) fn window_sessions(&self) -> Vec<WindowSession> {
+        let current = window_session(&self.tabs, self.geometry);
+        std::iter::once(current)
+            .chain(self.other_windows.iter().map(BrowserWindow::session))
+            .collect()
+    }
+}
+
+fn window_session(tabs: &Tabs<ContentView>, geometry: Option<WindowGeometry>) -> WindowSession {
+    WindowSession {
+        geometry,
+        tabs: tabs
+            .iter()
+            .map(|tab| TabSession {
+                history: History::clone(&tab.history),
+                history_capacity: tab.history_capacity,
+            })
+            .collect(),
+        active: tabs.active_index(),
+    }
+}
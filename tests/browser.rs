@@ -1,31 +1,1124 @@
-use std::rc::Rc;
+use wrybrowser::{
+    ipc, restore_history, Browser, ClearRange, Config, CoreEvent, Effect, History, HistoryEntry,
+    HistoryMenuEntry, LaunchOptions, PopupRequest, Profile, RecordingView, SameDocumentNavigation,
+    ScrollPosition, Session, TabCommand, ToolbarCommand, Transition, ViewCall, VisitQuery,
+    DEFAULT_HOMEPAGE, HISTORY_PAGE_URL,
+};
 
-use wrybrowser::{Browser, History};
-#[cfg(feature = "browser")]
-use winit::keyboard::ModifiersState;
+fn url(entry: Option<HistoryEntry>) -> Option<String> {
+    entry.map(|entry| entry.url)
+}
 
 #[test]
 fn browser_history_navigation() {
-    let history = Rc::new(History::new("first".into()));
-    let browser = Browser {
-        #[cfg(feature = "browser")]
-        window: None,
-        #[cfg(feature = "browser")]
-        webview: None,
-        history: history.clone(),
-        #[cfg(feature = "browser")]
-        modifiers: ModifiersState::default(),
-    };
+    let browser = Browser::new(History::new("first".into()), None);
 
     // simulate loading another page
-    browser.history.push("second".into());
-    assert_eq!(browser.history.current().as_deref(), Some("second"));
+    browser.history().push("second".into());
+    assert_eq!(browser.history().current_url(), "second");
 
     // navigate back
-    assert_eq!(browser.history.back(), Some("first".into()));
-    assert_eq!(browser.history.current().as_deref(), Some("first"));
+    assert_eq!(url(browser.history().back()), Some("first".into()));
+    assert_eq!(browser.history().current_url(), "first");
 
     // navigate forward
-    assert_eq!(browser.history.forward(), Some("second".into()));
-    assert_eq!(browser.history.current().as_deref(), Some("second"));
+    assert_eq!(url(browser.history().forward()), Some("second".into()));
+    assert_eq!(browser.history().current_url(), "second");
+}
+
+#[test]
+fn browser_tabs_keep_separate_history() {
+    let mut browser = Browser::new(History::new("first".into()), None);
+    browser.history().push("second".into());
+
+    assert!(browser.tabs.apply(TabCommand::Open("other".into())));
+    assert_eq!(browser.tabs.len(), 2);
+    assert_eq!(browser.history().current_url(), "other");
+    assert_eq!(browser.history().back(), None);
+
+    assert!(browser.tabs.apply(TabCommand::Previous));
+    assert_eq!(browser.history().current_url(), "second");
+    assert_eq!(url(browser.history().back()), Some("first".into()));
+}
+
+#[test]
+fn browser_reports_toolbar_state() {
+    let mut browser = Browser::new(History::new("first".into()), None);
+    browser.history().push("second".into());
+    browser.tabs.apply(TabCommand::Open("other".into()));
+    browser.tabs.apply(TabCommand::Previous);
+
+    assert_eq!(
+        browser.navigation_state(),
+        CoreEvent::NavigationState {
+            url: "second".into(),
+            can_go_back: true,
+            can_go_forward: false,
+            loading: false,
+            bookmarked: false,
+        }
+    );
+    assert_eq!(
+        browser.tabs_state(),
+        CoreEvent::Tabs {
+            tabs: vec!["second".into(), "other".into()],
+            active: 0,
+        }
+    );
+}
+
+#[test]
+fn browser_window_title_follows_active_tab() {
+    let mut browser = Browser::new(History::new("first".into()), None);
+    assert_eq!(browser.window_title(), "wrybrowser");
+
+    browser.tabs.active_mut().title = Some("First page".into());
+    assert_eq!(browser.window_title(), "First page - wrybrowser");
+
+    browser.tabs.apply(TabCommand::Open("other".into()));
+    assert_eq!(browser.window_title(), "wrybrowser");
+    assert_eq!(
+        browser.tabs_state(),
+        CoreEvent::Tabs {
+            tabs: vec!["First page".into(), "other".into()],
+            active: 1,
+        }
+    );
+}
+
+#[test]
+fn browser_toggles_bookmarks_and_persists_them() {
+    let dir = std::env::temp_dir().join(format!("wrybrowser-bookmarks-{}", std::process::id()));
+    let profile = Profile::open(&dir).unwrap();
+
+    let mut browser = Browser::new(
+        History::new("https://a.example/".into()),
+        Some(profile.clone()),
+    );
+    browser.tabs.active_mut().title = Some("Page A".into());
+    assert!(browser.toggle_bookmark());
+    assert!(matches!(
+        browser.navigation_state(),
+        CoreEvent::NavigationState {
+            bookmarked: true,
+            ..
+        }
+    ));
+
+    let reopened = Browser::new(
+        History::new("https://a.example/".into()),
+        Some(profile.clone()),
+    );
+    let saved = reopened.bookmarks.find_by_url("https://a.example/");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].title, "Page A");
+
+    let html = std::fs::read_to_string(reopened.export_bookmarks().unwrap()).unwrap();
+    assert!(html.contains(r#"<A HREF="https://a.example/""#));
+
+    let mut browser = reopened;
+    assert!(!browser.toggle_bookmark());
+    assert!(!browser.bookmarks.is_bookmarked("https://a.example/"));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn history_restored_from_profile() {
+    let dir = std::env::temp_dir().join(format!("wrybrowser-profile-{}", std::process::id()));
+    let profile = Profile::open(&dir).unwrap();
+
+    let fresh = restore_history(Some(&profile), None, DEFAULT_HOMEPAGE);
+    assert_eq!(fresh.current_url(), DEFAULT_HOMEPAGE);
+
+    fresh.push("second".into());
+    fresh.save(&profile.history_path()).unwrap();
+
+    let restored = restore_history(Some(&profile), Some("third".into()), DEFAULT_HOMEPAGE);
+    assert_eq!(restored.current_url(), "third");
+    assert_eq!(url(restored.back()), Some("second".into()));
+    assert_eq!(url(restored.back()), Some(DEFAULT_HOMEPAGE.into()));
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn tab_history_is_saved_on_shutdown_only() {
+    let dir = std::env::temp_dir().join(format!("wrybrowser-shutdown-{}", std::process::id()));
+    let profile = Profile::open(&dir).unwrap();
+    let mut browser = Browser::new(
+        History::new("https://a.example/".into()),
+        Some(profile.clone()),
+    );
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let id = browser.tabs.active().id();
+    browser.navigate("https://b.example/");
+    browser.page_load_finished(id, "https://b.example/".into());
+    assert!(!profile.history_path().exists());
+
+    browser.shutdown();
+    let restored = restore_history(Some(&profile), None, DEFAULT_HOMEPAGE);
+    assert_eq!(restored.current_url(), "https://b.example/");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn unreadable_visit_logs_are_moved_aside() {
+    let dir = std::env::temp_dir().join(format!("wrybrowser-visits-{}", std::process::id()));
+    let profile = Profile::open(&dir).unwrap();
+    let log = profile.visits_path();
+    std::fs::write(&log, "{\"version\":99}\n").unwrap();
+
+    let browser = Browser::new(History::new("https://a.example/".into()), Some(profile));
+    assert!(browser.visits.is_empty());
+    let aside = dir.join("visits.log.bad");
+    assert_eq!(
+        std::fs::read_to_string(&aside).unwrap(),
+        "{\"version\":99}\n"
+    );
+    drop(browser);
+    assert!(log.exists());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn browser_launch_opens_requested_tabs() {
+    let options = LaunchOptions {
+        url: Some("example.org".into()),
+        new_tabs: vec!["a.example".into(), "   ".into(), "b.example".into()],
+        kiosk: true,
+        ..LaunchOptions::default()
+    };
+    let browser = Browser::launch(options, None, Config::default());
+    let urls: Vec<String> = browser
+        .tabs
+        .iter()
+        .map(|tab| tab.history.current_url())
+        .collect();
+    assert_eq!(
+        urls,
+        [
+            "https://example.org/",
+            "https://a.example/",
+            "https://b.example/"
+        ]
+    );
+    assert_eq!(browser.tabs.active_index(), 2);
+    assert!(browser.kiosk);
+    assert_eq!(browser.layout.toolbar_height, 0.0);
+
+    let browser = Browser::launch(LaunchOptions::default(), None, Config::default());
+    assert_eq!(browser.tabs.len(), 1);
+    assert_eq!(browser.homepage(), "https://example.com/");
+    assert_eq!(browser.history().current_url(), browser.homepage());
+}
+
+#[test]
+fn session_is_offered_and_restored() {
+    let dir = std::env::temp_dir().join(format!("wrybrowser-session-{}", std::process::id()));
+    let profile = Profile::open(&dir).unwrap();
+
+    let mut browser = Browser::new(
+        History::new("https://a.example/".into()),
+        Some(profile.clone()),
+    );
+    browser.history().push("https://a2.example/".into());
+    browser
+        .history()
+        .set_scroll(ScrollPosition { x: 0.0, y: 300.0 });
+    browser.history().set_title("A2".into());
+    browser.history().back();
+    browser
+        .tabs
+        .apply(TabCommand::Open("https://b.example/".into()));
+    browser.tabs.apply(TabCommand::Select(0));
+    // A periodic snapshot with no clean exit after it looks like a crash.
+    browser.snapshot_session();
+
+    let mut offered = Browser::launch(
+        LaunchOptions::default(),
+        Some(profile.clone()),
+        Config::default(),
+    );
+    assert_eq!(offered.tabs.len(), 1);
+    // Its own snapshots wait for the user to answer the offer, so crashing
+    // again meanwhile keeps the session.
+    offered.snapshot_session();
+    let again = Browser::launch(
+        LaunchOptions::default(),
+        Some(profile.clone()),
+        Config::default(),
+    );
+    assert_eq!(
+        again.session_offer(),
+        Some(CoreEvent::SessionAvailable {
+            tabs: 2,
+            crashed: true
+        })
+    );
+    assert_eq!(
+        offered.session_offer(),
+        Some(CoreEvent::SessionAvailable {
+            tabs: 2,
+            crashed: true
+        })
+    );
+    assert_eq!(
+        send(&mut offered, ToolbarCommand::RestoreSession),
+        Effect::TabsChanged
+    );
+    assert_eq!(offered.tabs.len(), 2);
+    assert_eq!(offered.session_offer(), None);
+
+    browser.save_session(true);
+    let options = LaunchOptions {
+        url: Some("c.example".into()),
+        restore_session: true,
+        ..LaunchOptions::default()
+    };
+    let restored = Browser::launch(options, Some(profile.clone()), Config::default());
+    assert_eq!(restored.session_offer(), None);
+    let urls: Vec<String> = restored
+        .tabs
+        .iter()
+        .map(|tab| tab.history.current_url())
+        .collect();
+    assert_eq!(
+        urls,
+        [
+            "https://a.example/",
+            "https://c.example/",
+            "https://b.example/"
+        ]
+    );
+    let first = restored.tabs.get(0).unwrap();
+    let forward = first.history.forward().unwrap();
+    assert_eq!(forward.scroll, ScrollPosition { x: 0.0, y: 300.0 });
+    assert_eq!(forward.title.as_deref(), Some("A2"));
+
+    let mut dismissed = Browser::launch(LaunchOptions::default(), Some(profile), Config::default());
+    assert_eq!(
+        dismissed.session_offer(),
+        Some(CoreEvent::SessionAvailable {
+            tabs: 2,
+            crashed: false
+        })
+    );
+    send(&mut dismissed, ToolbarCommand::DismissSession);
+    assert_eq!(dismissed.session_offer(), None);
+    assert_eq!(dismissed.tabs.len(), 1);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn browser_launch_applies_config() {
+    let (config, _) = Config::parse(
+        "homepage = \"https://home.example/\"\nsearch_engine = \"https://s.example/?q=\"\ntoolbar_height = 30\nuser_agent = \"FromConfig\"",
+    )
+    .unwrap();
+    let options = LaunchOptions {
+        new_tabs: vec!["two words".into()],
+        ..LaunchOptions::default()
+    };
+    let mut browser = Browser::launch(options, None, config);
+    assert_eq!(
+        browser.tabs.get(0).unwrap().history.current_url(),
+        "https://home.example/"
+    );
+    assert_eq!(
+        browser.history().current_url(),
+        "https://s.example/?q=two+words"
+    );
+    assert_eq!(browser.layout.toolbar_height, 30.0);
+    assert_eq!(browser.effective_user_agent(), Some("FromConfig"));
+
+    browser.user_agent = Some("FromCli".into());
+    browser.apply_config(Config::default());
+    assert_eq!(browser.effective_user_agent(), Some("FromCli"));
+    assert_eq!(browser.layout.toolbar_height, 40.0);
+}
+
+/// Gives every tab without a view a recording one, as the GUI would after
+/// `Effect::TabsChanged`, and returns the views of all tabs in order.
+fn attach_views(browser: &mut Browser, views: &mut Vec<RecordingView>) {
+    browser.tabs.fill_views(|_| {
+        let view = RecordingView::new();
+        views.push(view.clone());
+        Box::new(view)
+    });
+    browser.sync_tabs();
+}
+
+fn send(browser: &mut Browser, command: ToolbarCommand) -> Effect {
+    let request = ipc::Request {
+        id: Some(1),
+        command,
+    };
+    browser.handle_toolbar_message(&ipc::encode_request(&request))
+}
+
+#[test]
+fn toolbar_navigation_drives_the_active_view() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let toolbar = RecordingView::new();
+    browser.toolbar = Some(Box::new(toolbar.clone()));
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let view = views[0].clone();
+    assert!(view.is_visible());
+
+    let effect = send(
+        &mut browser,
+        ToolbarCommand::Go {
+            url: "b.example".into(),
+        },
+    );
+    assert_eq!(effect, Effect::None);
+    assert_eq!(view.loaded_urls(), ["https://b.example/"]);
+    // The page is not in the history until it has loaded.
+    assert_eq!(browser.history().current_url(), "https://a.example/");
+    let scripts: Vec<ViewCall> = toolbar.take_calls();
+    assert!(
+        scripts.contains(&ViewCall::EvaluateScript(ipc::event_script(
+            &CoreEvent::Ack { id: 1 }
+        )))
+    );
+    assert!(
+        scripts.contains(&ViewCall::EvaluateScript(ipc::event_script(
+            &browser.navigation_state()
+        )))
+    );
+    let id = browser.tabs.active().id();
+    browser.page_load_finished(id, "https://b.example/".into());
+    assert_eq!(browser.history().current_url(), "https://b.example/");
+
+    send(&mut browser, ToolbarCommand::Back);
+    send(&mut browser, ToolbarCommand::Forward);
+    assert_eq!(
+        view.loaded_urls(),
+        [
+            "https://b.example/",
+            "https://a.example/",
+            "https://b.example/"
+        ]
+    );
+
+    send(&mut browser, ToolbarCommand::HardReload);
+    assert_eq!(
+        view.calls().last(),
+        Some(&ViewCall::Reload { bypass_cache: true })
+    );
+
+    assert_eq!(
+        send(&mut browser, ToolbarCommand::ToggleSidebar),
+        Effect::ToggleSidebar
+    );
+
+    toolbar.take_calls();
+    browser.handle_toolbar_message("not json");
+    match toolbar.calls().as_slice() {
+        [ViewCall::EvaluateScript(script)] => assert!(script.contains(r#""type":"error""#)),
+        calls => panic!("unexpected calls {:?}", calls),
+    }
+}
+
+#[test]
+fn back_and_forward_menus_jump_several_steps() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let toolbar = RecordingView::new();
+    browser.toolbar = Some(Box::new(toolbar.clone()));
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let id = browser.tabs.active().id();
+    browser.page_load_finished(id, "https://a.example/".into());
+    for page in ["b", "c", "d"] {
+        let url = format!("https://{}.example/", page);
+        browser.navigate(&url);
+        browser.page_load_finished(id, url);
+        if page == "b" {
+            browser.title_changed(id, "Page B".into());
+        }
+    }
+
+    let entry = |delta: isize, title: &str, url: &str| HistoryMenuEntry {
+        delta,
+        title: title.into(),
+        url: url.into(),
+    };
+    let back = CoreEvent::HistoryMenu {
+        forward: false,
+        entries: vec![
+            entry(-1, "https://c.example/", "https://c.example/"),
+            entry(-2, "Page B", "https://b.example/"),
+            entry(-3, "https://a.example/", "https://a.example/"),
+        ],
+    };
+    toolbar.take_calls();
+    send(&mut browser, ToolbarCommand::HistoryMenu { forward: false });
+    assert_eq!(
+        toolbar.take_calls().first(),
+        Some(&ViewCall::EvaluateScript(ipc::event_script(&back)))
+    );
+
+    send(&mut browser, ToolbarCommand::GoBy { delta: -2 });
+    assert_eq!(views[0].loaded_urls().last().unwrap(), "https://b.example/");
+    browser.page_load_finished(id, "https://b.example/".into());
+    assert_eq!(browser.history().index(), 1);
+    assert_eq!(browser.history().len(), 4);
+    assert_eq!(
+        browser.history_menu(true),
+        CoreEvent::HistoryMenu {
+            forward: true,
+            entries: vec![
+                entry(1, "https://c.example/", "https://c.example/"),
+                entry(2, "https://d.example/", "https://d.example/"),
+            ],
+        }
+    );
+
+    // Too far, in either direction, goes nowhere.
+    let loads = views[0].loaded_urls().len();
+    send(&mut browser, ToolbarCommand::GoBy { delta: -2 });
+    send(&mut browser, ToolbarCommand::GoBy { delta: 3 });
+    assert_eq!(views[0].loaded_urls().len(), loads);
+    assert_eq!(browser.history().index(), 1);
+}
+
+#[test]
+fn key_presses_switch_tabs_and_views() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+
+    let key = |s: &str| s.parse().unwrap();
+    assert_eq!(browser.handle_key(key("Ctrl+T")), Effect::TabsChanged);
+    attach_views(&mut browser, &mut views);
+    assert_eq!(views.len(), 2);
+    assert!(!views[0].is_visible());
+    assert!(views[1].is_visible());
+    assert_eq!(browser.history().current_url(), browser.homepage());
+
+    assert_eq!(browser.handle_key(key("Ctrl+1")), Effect::TabsChanged);
+    browser.sync_tabs();
+    assert!(views[0].is_visible());
+    assert!(!views[1].is_visible());
+
+    let id = browser.tabs.active().id();
+    browser.navigate("c.example");
+    browser.page_load_finished(id, "https://c.example/".into());
+    assert_eq!(browser.handle_key(key("Alt+Left")), Effect::None);
+    assert_eq!(
+        views[0].loaded_urls(),
+        ["https://c.example/", "https://a.example/"]
+    );
+
+    assert_eq!(browser.handle_key(key("F5")), Effect::None);
+    assert_eq!(
+        views[0].calls().last(),
+        Some(&ViewCall::Reload {
+            bypass_cache: false
+        })
+    );
+
+    browser.page_load_started(id, "https://slow.example/".into());
+    assert!(browser.tabs.active().load_state.is_loading());
+    assert_eq!(browser.handle_key(key("Escape")), Effect::None);
+    assert_eq!(
+        views[0].calls().last(),
+        Some(&ViewCall::EvaluateScript("window.stop()".into()))
+    );
+    assert!(!browser.tabs.active().load_state.is_loading());
+
+    assert_eq!(browser.handle_key(key("Ctrl+W")), Effect::TabsChanged);
+    assert_eq!(browser.tabs.len(), 1);
+    assert_eq!(browser.handle_key(key("Ctrl+W")), Effect::Close);
+    assert_eq!(browser.handle_key(key("Q")), Effect::None);
+}
+
+#[test]
+fn closing_the_window_asks_first_when_it_would_lose_work() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let toolbar = RecordingView::new();
+    browser.toolbar = Some(Box::new(toolbar.clone()));
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    assert_eq!(browser.request_close(), Effect::Close);
+
+    browser.tab_command(TabCommand::Open("https://b.example/".into()));
+    toolbar.take_calls();
+    assert_eq!(browser.request_close(), Effect::None);
+    assert_eq!(
+        toolbar.take_calls(),
+        [ViewCall::EvaluateScript(ipc::event_script(
+            &CoreEvent::ConfirmClose {
+                tabs: 2,
+                downloads: 0
+            }
+        ))]
+    );
+    assert_eq!(
+        send(&mut browser, ToolbarCommand::CloseWindow),
+        Effect::Close
+    );
+
+    browser.tab_command(TabCommand::CloseActive);
+    browser.active_downloads = 1;
+    assert_eq!(browser.handle_key("Ctrl+W".parse().unwrap()), Effect::None);
+    assert!(toolbar.calls().iter().any(|call| matches!(
+        call,
+        ViewCall::EvaluateScript(script) if script.contains(r#""downloads":1"#)
+    )));
+
+    // Nobody could answer in kiosk mode.
+    browser.kiosk = true;
+    assert_eq!(browser.request_close(), Effect::Close);
+    browser.shutdown();
+    assert!(browser.toolbar.is_none());
+    assert!(browser.tabs.iter().all(|tab| tab.view.is_none()));
+}
+
+#[test]
+fn windows_keep_their_own_tabs_and_route_events() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    browser.tab_command(TabCommand::Open("https://b.example/".into()));
+    attach_views(&mut browser, &mut views);
+    let (a, b) = (
+        browser.tabs.get(0).unwrap().id(),
+        browser.tabs.get(1).unwrap().id(),
+    );
+
+    assert_eq!(
+        browser.handle_key("Ctrl+N".parse().unwrap()),
+        Effect::WindowsChanged
+    );
+    attach_views(&mut browser, &mut views);
+    assert_eq!(browser.window_count(), 2);
+    assert_eq!(browser.tabs.len(), 1);
+    assert_eq!(browser.history().current_url(), browser.homepage());
+    let home = browser.tabs.active().id();
+
+    // A page event is handled in the window of its tab.
+    assert!(browser.focus_tab(b));
+    browser.title_changed(b, "Page B".into());
+    assert_eq!(browser.window_title(), "Page B - wrybrowser");
+    assert!(browser.focus_tab(home));
+    assert_eq!(browser.window_title(), "wrybrowser");
+    assert!(browser.focus_tab(a));
+    assert_eq!(browser.tabs.len(), 2);
+
+    // Moving a tab out keeps its id and history but not its view.
+    assert_eq!(
+        send(
+            &mut browser,
+            ToolbarCommand::MoveTabToNewWindow { index: 1 }
+        ),
+        Effect::WindowsChanged
+    );
+    assert_eq!(browser.window_count(), 3);
+    assert_eq!(browser.tabs.active().id(), b);
+    assert!(browser.tabs.active().view.is_none());
+    assert_eq!(browser.tabs.active().label(), "Page B");
+    attach_views(&mut browser, &mut views);
+    assert_eq!(views.last().unwrap().loaded_urls(), Vec::<String>::new());
+    assert!(browser.focus_tab(a));
+    assert_eq!(browser.tabs.len(), 1);
+    assert_eq!(
+        send(
+            &mut browser,
+            ToolbarCommand::MoveTabToNewWindow { index: 0 }
+        ),
+        Effect::None
+    );
+
+    let session = browser.session(false);
+    assert_eq!(session.windows.len(), 3);
+    assert_eq!(
+        session.windows[0].tabs[0].history.current_url(),
+        "https://a.example/"
+    );
+
+    // Closing the last tab of a window closes just that window, until it
+    // is the last window.
+    assert_eq!(
+        browser.handle_key("Ctrl+W".parse().unwrap()),
+        Effect::CloseWindow
+    );
+    assert!(browser.close_window().is_some());
+    assert_eq!(browser.window_count(), 2);
+    assert!(!browser.focus_tab(a));
+    assert_eq!(browser.request_close(), Effect::CloseWindow);
+    browser.close_window();
+    assert_eq!(browser.window_count(), 1);
+    assert_eq!(browser.request_close(), Effect::Close);
+    assert!(browser.close_window().is_none());
+
+    let mut restored = Browser::new(History::new("https://c.example/".into()), None);
+    assert_eq!(restored.restore_session(session), Effect::WindowsChanged);
+    assert_eq!(restored.window_count(), 3);
+    assert_eq!(restored.tabs.active().label(), "https://a.example/");
+    assert!(restored.focus_window(1));
+    assert_eq!(restored.tabs.active().label(), "Page B");
+}
+
+#[test]
+fn new_window_requests_follow_the_policy_and_the_popup_blocker() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let toolbar = RecordingView::new();
+    browser.toolbar = Some(Box::new(toolbar.clone()));
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let opener = browser.tabs.active().id();
+    let request = |url: &str, user_gesture| PopupRequest {
+        url: url.into(),
+        user_gesture,
+    };
+
+    assert_eq!(
+        browser.new_window_requested(opener, request("https://b.example/", true)),
+        Effect::TabsChanged
+    );
+    assert_eq!(browser.tabs.len(), 2);
+    assert_eq!(browser.history().current_url(), "https://b.example/");
+
+    toolbar.take_calls();
+    assert_eq!(
+        browser.new_window_requested(opener, request("https://ads.example/", false)),
+        Effect::None
+    );
+    assert_eq!(browser.tabs.len(), 2);
+    assert_eq!(
+        toolbar.take_calls(),
+        [ViewCall::EvaluateScript(ipc::event_script(
+            &CoreEvent::PopupBlocked {
+                url: "https://ads.example/".into()
+            }
+        ))]
+    );
+    assert_eq!(
+        send(
+            &mut browser,
+            ToolbarCommand::OpenPopup {
+                url: "https://ads.example/".into()
+            }
+        ),
+        Effect::TabsChanged
+    );
+    assert_eq!(browser.tabs.len(), 3);
+    assert_eq!(
+        browser.new_window_requested(opener, request("javascript:alert(1)", true)),
+        Effect::None
+    );
+    // Web pages cannot open local files.
+    assert_eq!(
+        browser.new_window_requested(opener, request("file:///etc/passwd", true)),
+        Effect::None
+    );
+    assert_eq!(browser.tabs.len(), 3);
+
+    let (config, _) =
+        Config::parse("new_window_policy = \"new-window\"\nblock_popups = false").unwrap();
+    browser.apply_config(config);
+    assert_eq!(
+        browser.new_window_requested(opener, request("https://c.example/", false)),
+        Effect::WindowsChanged
+    );
+    assert_eq!(browser.window_count(), 2);
+    assert_eq!(browser.tabs.len(), 1);
+
+    // Requests from tabs of other windows are handled there.
+    assert!(browser.focus_tab(opener));
+    browser.apply_config(Config::parse("new_window_policy = \"block\"").unwrap().0);
+    assert_eq!(
+        browser.new_window_requested(opener, request("https://d.example/", true)),
+        Effect::None
+    );
+    assert_eq!(browser.window_count(), 2);
+}
+
+#[test]
+fn page_load_events_update_tab_state() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let id = browser.tabs.active().id();
+
+    browser.title_changed(id, "A".into());
+    browser.page_load_started(id, "https://b.example/".into());
+    assert!(browser.tabs.active().load_state.is_loading());
+    assert_eq!(browser.tabs.active().title, None);
+
+    browser.page_load_finished(id, "https://b.example/".into());
+    assert!(!browser.tabs.active().load_state.is_loading());
+    assert_eq!(browser.history().current_url(), "https://b.example/");
+    browser.title_changed(id, "B".into());
+    assert_eq!(browser.window_title(), "B - wrybrowser");
+}
+
+#[test]
+fn history_entries_track_titles_transitions_and_scrolling() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let id = browser.tabs.active().id();
+
+    browser.page_load_finished(id, "https://a.example/".into());
+    browser.title_changed(id, "A".into());
+    browser.scrolled(
+        id,
+        "https://a.example/",
+        ScrollPosition { x: 0.0, y: 640.0 },
+    );
+    browser.navigate("b.example");
+    // A late report from the page being left must not overwrite its offset.
+    browser.scrolled(
+        id,
+        "https://a.example/",
+        ScrollPosition { x: 0.0, y: 900.0 },
+    );
+    browser.page_load_started(id, "https://b.example/".into());
+    browser.page_load_finished(id, "https://b.example/landing".into());
+
+    // The redirect leaves one entry, at the page it landed on.
+    let entries = browser.history().entries();
+    let pages: Vec<(&str, Transition)> = entries
+        .iter()
+        .map(|entry| (entry.url.as_str(), entry.transition))
+        .collect();
+    assert_eq!(
+        pages,
+        [
+            ("https://a.example/", Transition::Typed),
+            ("https://b.example/landing", Transition::Typed)
+        ]
+    );
+    assert_eq!(entries[0].title.as_deref(), Some("A"));
+    assert_eq!(entries[0].scroll, ScrollPosition { x: 0.0, y: 640.0 });
+    assert_eq!(entries[1].scroll, ScrollPosition::default());
+    let visits = browser.visits.search(&VisitQuery::default());
+    let hops: Vec<(&str, Transition)> = visits
+        .iter()
+        .map(|visit| (visit.url.as_str(), visit.transition))
+        .collect();
+    assert_eq!(
+        hops,
+        [
+            ("https://b.example/landing", Transition::Typed),
+            ("https://b.example/", Transition::Redirect),
+            ("https://a.example/", Transition::Typed)
+        ]
+    );
+
+    browser.go_back();
+    let view = &views[0];
+    view.take_calls();
+    browser.page_load_finished(id, "https://a.example/".into());
+    assert_eq!(
+        view.calls(),
+        [ViewCall::EvaluateScript("window.scrollTo(0, 640)".into())]
+    );
+    let current = browser.history().current();
+    assert_eq!(current.transition, Transition::BackForward);
+    assert_eq!(current.visit_count, 2);
+
+    browser.reload(false);
+    assert_eq!(browser.history().current().transition, Transition::Reload);
+    view.take_calls();
+    browser.page_load_finished(id, "https://a.example/".into());
+    assert!(view.calls().is_empty());
+}
+
+#[test]
+fn tab_histories_keep_their_newest_entries() {
+    let mut browser = Browser::new(History::new("https://p0.example/".into()), None);
+    browser.apply_config(Config {
+        tab_history_capacity: 3,
+        ..Config::default()
+    });
+    browser.tab_command(TabCommand::Open("https://q0.example/".into()));
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let visit_pages = |browser: &mut Browser, site: &str| {
+        let id = browser.tabs.active().id();
+        for i in 1..=5 {
+            let url = format!("https://{}{}.example/", site, i);
+            browser.navigate(&url);
+            browser.page_load_finished(id, url);
+        }
+    };
+    let second = browser.tabs.active().id();
+    assert!(browser.set_history_capacity(second, Some(5)));
+    visit_pages(&mut browser, "q");
+    browser.tab_command(TabCommand::Select(0));
+    visit_pages(&mut browser, "p");
+
+    let lengths = |browser: &Browser| -> Vec<usize> {
+        browser.tabs.iter().map(|tab| tab.history.len()).collect()
+    };
+    assert_eq!(lengths(&browser), [3, 5]);
+    assert_eq!(
+        url(browser.history().entries().into_iter().next()),
+        Some("https://p3.example/".into())
+    );
+
+    // The configured capacity applies at once, except to the tab with its own.
+    browser.apply_config(Config {
+        tab_history_capacity: 2,
+        ..Config::default()
+    });
+    assert_eq!(lengths(&browser), [2, 5]);
+    assert!(browser.set_history_capacity(second, None));
+    assert_eq!(lengths(&browser), [2, 2]);
+    assert_eq!(browser.history().index(), 1);
+
+    // Tabs opened afterwards get it as they are created.
+    browser.tab_command(TabCommand::Open("https://r0.example/".into()));
+    assert_eq!(browser.history().capacity(), 2);
+
+    // A tab's own capacity is saved with the session and restored, and goes
+    // with the tab to another window.
+    assert!(browser.set_history_capacity(second, Some(4)));
+    let path =
+        std::env::temp_dir().join(format!("wrybrowser-capacity-{}.json", std::process::id()));
+    browser.session(true).save(&path).unwrap();
+    let session = Session::load(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    let mut restored = Browser::new(History::new("https://s0.example/".into()), None);
+    restored.apply_config(Config {
+        tab_history_capacity: 2,
+        ..Config::default()
+    });
+    restored.restore_session(session);
+    let capacities = |browser: &Browser| -> Vec<(Option<usize>, usize)> {
+        browser
+            .tabs
+            .iter()
+            .map(|tab| (tab.history_capacity, tab.history.capacity()))
+            .collect()
+    };
+    assert_eq!(capacities(&restored), [(None, 2), (None, 2), (Some(4), 4)]);
+    assert_eq!(restored.move_tab_to_new_window(2), Effect::WindowsChanged);
+    assert_eq!(capacities(&restored), [(Some(4), 4)]);
+}
+
+#[test]
+fn same_document_navigations_keep_the_history_in_step_with_the_page() {
+    use SameDocumentNavigation::{Push, Replace, Traverse};
+
+    let mut browser = Browser::new(History::new("https://app.example/".into()), None);
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let view = views[0].clone();
+    let id = browser.tabs.active().id();
+    let urls = |browser: &Browser| -> Vec<String> {
+        browser
+            .history()
+            .entries()
+            .into_iter()
+            .map(|entry| entry.url)
+            .collect()
+    };
+
+    // Reports while the page loads wait for the load to say where it is.
+    browser.same_document_navigation(id, Push, "https://app.example/early".into());
+    browser.page_load_finished(id, "https://app.example/".into());
+    browser.same_document_navigation(id, Push, "https://app.example/inbox".into());
+    browser.same_document_navigation(id, Replace, "https://app.example/inbox?page=2".into());
+    browser.same_document_navigation(id, Traverse, "https://app.example/inbox?page=2#mail".into());
+    // A page cannot make the address bar show another site.
+    browser.same_document_navigation(id, Push, "https://bank.example/".into());
+    assert_eq!(
+        urls(&browser),
+        [
+            "https://app.example/",
+            "https://app.example/inbox?page=2",
+            "https://app.example/inbox?page=2#mail"
+        ]
+    );
+    assert!(view.loaded_urls().is_empty());
+
+    // Going back within the page is up to the page, which reports arriving.
+    view.take_calls();
+    browser.go_back();
+    assert_eq!(
+        view.take_calls(),
+        [ViewCall::EvaluateScript("history.go(-1)".into())]
+    );
+    assert_eq!(browser.history().index(), 1);
+    browser.same_document_navigation(id, Traverse, "https://app.example/inbox?page=2".into());
+    assert_eq!(browser.history().index(), 1);
+
+    // So is the page going back by itself.
+    browser.same_document_navigation(id, Traverse, "https://app.example/".into());
+    assert_eq!(browser.history().index(), 0);
+    assert_eq!(urls(&browser).len(), 3);
+
+    // A new page is a new document: going back to the app loads it again.
+    browser.navigate("https://other.example/");
+    browser.page_load_finished(id, "https://other.example/".into());
+    assert_eq!(urls(&browser).len(), 2);
+    browser.go_back();
+    assert_eq!(
+        view.loaded_urls().last().map(String::as_str),
+        Some("https://app.example/")
+    );
+    browser.page_load_finished(id, "https://app.example/".into());
+    browser.same_document_navigation(id, Push, "https://app.example/drafts".into());
+    browser.go_back();
+    assert_eq!(
+        view.calls().last(),
+        Some(&ViewCall::EvaluateScript("history.go(-1)".into()))
+    );
+    // Had the engine loaded the page instead, the load commits it as usual.
+    browser.page_load_started(id, "https://app.example/".into());
+    browser.page_load_finished(id, "https://app.example/".into());
+    assert_eq!(
+        urls(&browser),
+        ["https://app.example/", "https://app.example/drafts"]
+    );
+    assert_eq!(browser.history().index(), 0);
+
+    // Pushing the URL shown adds an entry in the page, so it does here too.
+    browser.same_document_navigation(id, Push, "https://app.example/".into());
+    assert_eq!(urls(&browser), ["https://app.example/"; 2]);
+    // Going back to it by itself moves to the entry before.
+    browser.same_document_navigation(id, Traverse, "https://app.example/".into());
+    assert_eq!(browser.history().index(), 0);
+
+    // A page that goes somewhere other than asked is followed, and what it
+    // reports afterwards still counts.
+    browser.same_document_navigation(id, Push, "https://app.example/a".into());
+    browser.same_document_navigation(id, Push, "https://app.example/b".into());
+    browser.go_back();
+    assert_eq!(browser.history().current_url(), "https://app.example/a");
+    browser.same_document_navigation(id, Traverse, "https://app.example/c".into());
+    assert_eq!(browser.history().current_url(), "https://app.example/c");
+    browser.same_document_navigation(id, Push, "https://app.example/d".into());
+    assert_eq!(
+        urls(&browser),
+        [
+            "https://app.example/",
+            "https://app.example/a",
+            "https://app.example/c",
+            "https://app.example/d"
+        ]
+    );
+    browser.scrolled(
+        id,
+        "https://app.example/d",
+        ScrollPosition { x: 0.0, y: 40.0 },
+    );
+    assert_eq!(browser.history().current().scroll.y, 40.0);
+}
+
+#[test]
+fn page_loads_fill_the_global_history_and_the_history_page_queries_it() {
+    let mut browser = Browser::new(History::new("https://a.example/".into()), None);
+    let mut views = Vec::new();
+    attach_views(&mut browser, &mut views);
+    let id = browser.tabs.active().id();
+
+    browser.page_load_finished(id, "https://a.example/".into());
+    browser.title_changed(id, "Page A".into());
+    browser.navigate("b.example");
+    browser.page_load_finished(id, "https://b.example/".into());
+    let visits = browser.visits.search(&VisitQuery::default());
+    let urls: Vec<&str> = visits.iter().map(|visit| visit.url.as_str()).collect();
+    assert_eq!(urls, ["https://b.example/", "https://a.example/"]);
+    assert_eq!(visits[0].transition, Transition::Typed);
+    assert_eq!(visits[1].title.as_deref(), Some("Page A"));
+
+    let search = ipc::encode_request(&ipc::Request {
+        id: Some(1),
+        command: ToolbarCommand::SearchHistory {
+            query: VisitQuery {
+                text: Some("page a".into()),
+                ..VisitQuery::default()
+            },
+        },
+    });
+    // Ordinary pages cannot read the history.
+    browser.handle_page_message(id, "https://a.example/", &search);
+    assert!(!views[0]
+        .take_calls()
+        .iter()
+        .any(|call| matches!(call, ViewCall::EvaluateScript(_))));
+
+    assert_eq!(
+        browser.handle_key("Ctrl+H".parse().unwrap()),
+        Effect::TabsChanged
+    );
+    attach_views(&mut browser, &mut views);
+    assert_eq!(browser.history().current_url(), HISTORY_PAGE_URL);
+    let page = browser.tabs.active().id();
+    browser.page_load_finished(page, HISTORY_PAGE_URL.into());
+    assert_eq!(browser.visits.len(), 2);
+
+    views[1].take_calls();
+    browser.handle_page_message(page, HISTORY_PAGE_URL, &search);
+    let found = vec![visits[1].clone()];
+    assert_eq!(
+        views[1].take_calls(),
+        [
+            ViewCall::EvaluateScript(ipc::event_script(&CoreEvent::Visits { visits: found })),
+            ViewCall::EvaluateScript(ipc::event_script(&CoreEvent::Ack { id: 1 })),
+        ]
+    );
+    // Nor can another page shown in the history page's tab.
+    browser.handle_page_message(page, "https://a.example/", &search);
+    assert!(views[1].take_calls().is_empty());
+
+    let remove = ToolbarCommand::RemoveVisit {
+        visit: visits[1].id.0,
+    };
+    browser.handle_page_message(
+        page,
+        HISTORY_PAGE_URL,
+        &ipc::encode_request(&ipc::Request {
+            id: None,
+            command: remove,
+        }),
+    );
+    assert_eq!(browser.visits.len(), 1);
+    browser.handle_page_message(
+        page,
+        HISTORY_PAGE_URL,
+        &ipc::encode_request(&ipc::Request {
+            id: Some(2),
+            command: ToolbarCommand::NewTab,
+        }),
+    );
+    assert_eq!(browser.tabs.len(), 2);
+    assert!(matches!(
+        views[1].take_calls().first(),
+        Some(ViewCall::EvaluateScript(script)) if script.contains(r#""type":"error""#)
+    ));
+
+    send(
+        &mut browser,
+        ToolbarCommand::ClearHistory {
+            range: ClearRange::LastHour,
+        },
+    );
+    assert!(browser.visits.is_empty());
+
+    // Once the tab starts leaving the history page, the page it is leaving
+    // for may already be running: nothing it posts is heard.
+    let index = browser
+        .tabs
+        .iter()
+        .position(|tab| tab.id() == page)
+        .unwrap();
+    browser.tab_command(TabCommand::Select(index));
+    browser.navigate("https://c.example/");
+    views[1].take_calls();
+    browser.handle_page_message(page, "https://c.example/", &search);
+    browser.handle_page_message(page, HISTORY_PAGE_URL, &search);
+    assert!(!views[1]
+        .take_calls()
+        .iter()
+        .any(|call| matches!(call, ViewCall::EvaluateScript(_))));
 }